@@ -1,5 +1,9 @@
 use glam::{Mat4, Vec3};
 use std::time::Duration;
+use voxel_core::{
+    camera::{Camera, Projection},
+    physics::{CollisionWorld, Player, PlayerInput},
+};
 use winit::{event::MouseScrollDelta, keyboard::KeyCode};
 
 #[repr(C)]
@@ -23,60 +27,6 @@ impl CameraUniform {
     }
 }
 
-#[derive(Debug)]
-pub struct Camera {
-    pub position: Vec3,
-    pub yaw: f32,
-    pub pitch: f32,
-}
-
-impl Camera {
-    pub fn new(position: Vec3, yaw: f32, pitch: f32) -> Self {
-        Self {
-            position,
-            yaw,
-            pitch,
-        }
-    }
-
-    pub fn calc_matrix(&self) -> Mat4 {
-        let (sin_pitch, cos_pitch) = self.pitch.sin_cos();
-        let (sin_yaw, cos_yaw) = self.yaw.sin_cos();
-
-        Mat4::look_to_rh(
-            self.position,
-            Vec3::new(cos_pitch * cos_yaw, sin_pitch, cos_pitch * sin_yaw).normalize(),
-            Vec3::Y,
-        )
-    }
-}
-
-pub struct Projection {
-    aspect: f32,
-    fovy: f32,
-    znear: f32,
-    zfar: f32,
-}
-
-impl Projection {
-    pub fn new(width: u32, height: u32, fovy: f32, znear: f32, zfar: f32) -> Self {
-        Self {
-            aspect: width as f32 / height as f32,
-            fovy,
-            znear,
-            zfar,
-        }
-    }
-
-    pub fn resize(&mut self, width: u32, height: u32) {
-        self.aspect = width as f32 / height as f32;
-    }
-
-    pub fn calc_matrix(&self) -> Mat4 {
-        Mat4::perspective_rh(self.fovy.to_radians(), self.aspect, self.znear, self.zfar)
-    }
-}
-
 #[derive(Debug)]
 pub struct CameraController {
     amount_left: f32,
@@ -88,6 +38,9 @@ pub struct CameraController {
     rotate_horizontal: f32,
     rotate_vertical: f32,
     axis_locked: bool,
+    /// walks the camera around with gravity and collisions instead of flying
+    walking: bool,
+    player: Option<Player>,
     speed: f32,
     sensitivity: f32,
 }
@@ -104,6 +57,8 @@ impl CameraController {
             rotate_horizontal: 0.0,
             rotate_vertical: 0.0,
             axis_locked: false,
+            walking: false,
+            player: None,
             speed,
             sensitivity,
         }
@@ -140,6 +95,10 @@ impl CameraController {
                 self.axis_locked = !self.axis_locked;
                 true
             }
+            KeyCode::KeyF if pressed => {
+                self.walking = !self.walking;
+                true
+            }
             _ => false,
         }
     }
@@ -166,7 +125,12 @@ impl CameraController {
         self.rotate_vertical += mouse_dy as f32;
     }
 
-    pub fn update_camera(&mut self, camera: &mut Camera, dt: Duration) {
+    pub fn update_camera(
+        &mut self,
+        camera: &mut Camera,
+        world: &impl CollisionWorld,
+        dt: Duration,
+    ) {
         let dt = dt.as_secs_f32();
 
         camera.yaw += self.rotate_horizontal.to_radians() * self.sensitivity;
@@ -175,7 +139,9 @@ impl CameraController {
         self.rotate_horizontal = 0.0;
         self.rotate_vertical = 0.0;
 
-        camera.pitch = camera.pitch.clamp(-89.0_f32.to_radians(), 89.0_f32.to_radians());
+        camera.pitch = camera
+            .pitch
+            .clamp(-89.0_f32.to_radians(), 89.0_f32.to_radians());
 
         let (yaw_sin, yaw_cos) = camera.yaw.sin_cos();
         let (pitch_sin, pitch_cos) = camera.pitch.sin_cos();
@@ -188,6 +154,24 @@ impl CameraController {
 
         let right = Vec3::new(-yaw_sin, 0.0, yaw_cos).normalize();
 
+        if self.walking {
+            let player = self
+                .player
+                .get_or_insert_with(|| Player::new(camera.position - Vec3::Y * Player::EYE_HEIGHT));
+
+            let forward = Vec3::new(yaw_cos, 0.0, yaw_sin);
+            let input = PlayerInput {
+                movement: forward * (self.amount_forward - self.amount_backward)
+                    + right * (self.amount_right - self.amount_left),
+                jump: self.amount_up > 0.0,
+            };
+            player.update(world, input, dt);
+
+            camera.position = player.eye_position();
+            return;
+        }
+        self.player = None;
+
         let forward = forward * (self.amount_forward - self.amount_backward);
         let right = right * (self.amount_right - self.amount_left);
         let up = Vec3::ZERO.with_y(self.amount_up - self.amount_down);
@@ -0,0 +1,415 @@
+use std::{collections::VecDeque, ops::Range};
+
+use ahash::{AHashMap, AHashSet};
+use glam::{IVec3, Mat4, Vec3};
+use voxel_core::{
+    chunk::{CHUNK_SIZE, ChunkMeshData, Vertex},
+    chunk_manager::{ChunkManager, FinishedMeshes},
+    frustum::{Aabb, Frustum},
+    lod::LodNode,
+};
+
+use crate::{
+    culling::{Culling, create_culling},
+    indirect::{IndirectDraws, Pass},
+    mesh_allocator::{ArenaStats, MeshAllocation, MeshAllocator},
+    texture::Texture,
+};
+
+/// the most vertex and index data uploaded in one frame, so a burst of
+/// finished meshes is spread over a few frames instead of stalling one
+const UPLOAD_BUDGET: usize = 4 * 1024 * 1024;
+
+const VERTEX_ATTRIBS: [wgpu::VertexAttribute; 2] =
+    wgpu::vertex_attr_array![0 => Uint32, 1 => Uint32];
+
+pub fn vertex_desc() -> wgpu::VertexBufferLayout<'static> {
+    wgpu::VertexBufferLayout {
+        array_stride: std::mem::size_of::<Vertex>() as wgpu::BufferAddress,
+        step_mode: wgpu::VertexStepMode::Vertex,
+        attributes: &VERTEX_ATTRIBS,
+    }
+}
+
+pub struct ChunkMesh {
+    world_position: IVec3,
+    /// size of a mesh unit in voxels, 1 except for level of detail meshes
+    scale: i32,
+    bounding_box: Aabb,
+    vertex_count: u32,
+    index_count: u32,
+    translucent_index_count: u32,
+    allocation: MeshAllocation,
+    /// CPU copy of the translucent faces, kept to sort them again whenever
+    /// the camera moves to another block
+    translucent_mesh: Option<ChunkMeshData>,
+    sorted_from: Option<IVec3>,
+}
+
+impl ChunkMesh {
+    pub fn new(
+        world_position: IVec3,
+        scale: i32,
+        mut mesh_data: ChunkMeshData,
+        allocator: &mut MeshAllocator,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+    ) -> Self {
+        let allocation = allocator.upload(device, queue, &mesh_data);
+
+        let vertex_count = mesh_data.vertices.len() as u32;
+        let index_count = mesh_data.indices.len() as u32;
+        let translucent_index_count = mesh_data.translucent_indices.len() as u32;
+        let translucent_mesh = (translucent_index_count > 0).then(|| {
+            mesh_data.indices = Vec::new();
+            mesh_data
+        });
+
+        Self {
+            world_position,
+            scale,
+            bounding_box: Aabb::new(
+                world_position.as_vec3(),
+                world_position.as_vec3() + (CHUNK_SIZE as i32 * scale) as f32,
+            ),
+            vertex_count,
+            index_count,
+            translucent_index_count,
+            allocation,
+            translucent_mesh,
+            sorted_from: None,
+        }
+    }
+
+    /// Sorts the translucent faces back to front as seen from `camera_position`,
+    /// unless they were already sorted from within the same block.
+    pub fn sort_translucent(
+        &mut self,
+        allocator: &MeshAllocator,
+        queue: &wgpu::Queue,
+        camera_position: Vec3,
+    ) {
+        let Some(mesh_data) = &mut self.translucent_mesh else {
+            return;
+        };
+
+        let block = camera_position.floor().as_ivec3();
+        if self.sorted_from == Some(block) {
+            return;
+        }
+        self.sorted_from = Some(block);
+
+        mesh_data.sort_translucent(
+            (camera_position - self.world_position.as_vec3()) / self.scale as f32,
+        );
+        allocator.write_translucent(
+            queue,
+            self.allocation,
+            self.index_count,
+            &mesh_data.translucent_indices,
+        );
+    }
+
+    fn pages(&self) -> (usize, usize) {
+        (self.allocation.vertices.page, self.allocation.indices.page)
+    }
+
+    /// Adds a draw of a range of the mesh's own indices.
+    fn push_draw(&self, draws: &mut IndirectDraws, pass: Pass, indices: Range<u32>) {
+        draws.push(
+            pass,
+            self.pages(),
+            self.world_position.extend(self.scale).to_array(),
+            self.allocation.indices.offset + indices.start,
+            indices.end - indices.start,
+            self.allocation.vertices.offset,
+        );
+    }
+}
+
+/// Owns the GPU side of every chunk and level of detail node the
+/// [`ChunkManager`] has meshed.
+pub struct ChunkRenderer {
+    meshes: AHashMap<IVec3, ChunkMesh>,
+    lod_meshes: AHashMap<LodNode, ChunkMesh>,
+    /// finished chunk meshes waiting to be uploaded, with only the latest
+    /// mesh kept for every chunk
+    pending: AHashMap<IVec3, Option<ChunkMeshData>>,
+    /// the order `pending` was filled in, oldest first
+    pending_order: VecDeque<IVec3>,
+    pending_lods: VecDeque<(LodNode, Option<ChunkMeshData>)>,
+    allocator: MeshAllocator,
+    draws: IndirectDraws,
+    culling: Box<dyn Culling>,
+}
+
+impl ChunkRenderer {
+    /// Culls the meshes on the GPU if `gpu_culling` is set and the device
+    /// can draw indirectly, on the CPU otherwise.
+    pub fn new(device: &wgpu::Device, gpu_culling: bool) -> Self {
+        let draws = IndirectDraws::new(device);
+        let culling = create_culling(device, &draws, gpu_culling);
+        log::info!("culling chunks on the {}", culling.name());
+
+        Self {
+            meshes: AHashMap::new(),
+            lod_meshes: AHashMap::new(),
+            pending: AHashMap::new(),
+            pending_order: VecDeque::new(),
+            pending_lods: VecDeque::new(),
+            allocator: MeshAllocator::default(),
+            draws,
+            culling,
+        }
+    }
+
+    /// The layout of the per draw data the chunk shader reads from group 3.
+    pub fn bind_group_layout(&self) -> &wgpu::BindGroupLayout {
+        self.draws.bind_group_layout()
+    }
+
+    /// Queues meshes finished by the workers to be uploaded.
+    pub fn queue_uploads(&mut self, finished: FinishedMeshes) {
+        for (position, mesh_data) in finished.chunks {
+            if self.pending.insert(position, mesh_data).is_none() {
+                self.pending_order.push_back(position);
+            }
+        }
+        self.pending_lods.extend(finished.lods);
+    }
+
+    /// Uploads queued meshes until the frame's budget runs out, chunks
+    /// before level of detail nodes. Nodes that aren't needed anymore are
+    /// only dropped once every node replacing them is uploaded, so there are
+    /// no holes in the distance while moving.
+    pub fn upload(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        chunk_manager: &ChunkManager,
+    ) {
+        let mut budget = UPLOAD_BUDGET;
+        let upload_size = |mesh_data: &ChunkMeshData| {
+            mesh_data.vertices.len() * std::mem::size_of::<Vertex>()
+                + (mesh_data.indices.len() + mesh_data.translucent_indices.len()) * 4
+        };
+
+        while budget > 0
+            && let Some(position) = self.pending_order.pop_front()
+        {
+            // freed first, so a remeshed chunk can take the space it had
+            if let Some(old) = self.meshes.remove(&position) {
+                self.allocator.free(old.allocation);
+            }
+
+            if let Some(mesh_data) = self.pending.remove(&position).flatten()
+                && !mesh_data.is_empty()
+            {
+                budget = budget.saturating_sub(upload_size(&mesh_data));
+                let mesh = ChunkMesh::new(
+                    position * CHUNK_SIZE as i32,
+                    1,
+                    mesh_data,
+                    &mut self.allocator,
+                    device,
+                    queue,
+                );
+                self.meshes.insert(position, mesh);
+            }
+        }
+
+        let mut uploaded_lods = false;
+        while budget > 0
+            && let Some((node, mesh_data)) = self.pending_lods.pop_front()
+        {
+            uploaded_lods = true;
+            if let Some(mesh_data) = mesh_data {
+                budget = budget.saturating_sub(upload_size(&mesh_data));
+                let mesh = ChunkMesh::new(
+                    node.world_position(),
+                    node.size(),
+                    mesh_data,
+                    &mut self.allocator,
+                    device,
+                    queue,
+                );
+                if let Some(old) = self.lod_meshes.insert(node, mesh) {
+                    self.allocator.free(old.allocation);
+                }
+            }
+        }
+
+        if uploaded_lods {
+            self.remove_stale_lods(chunk_manager);
+        }
+    }
+
+    fn remove_stale_lods(&mut self, chunk_manager: &ChunkManager) {
+        if !chunk_manager.lod_pending() && self.pending_lods.is_empty() {
+            for (_, mesh) in self
+                .lod_meshes
+                .extract_if(|node, _| !chunk_manager.lod_nodes.contains(node))
+            {
+                self.allocator.free(mesh.allocation);
+            }
+        }
+    }
+
+    /// Drops the meshes of chunks the manager has unloaded.
+    pub fn remove_unloaded(&mut self, chunk_manager: &ChunkManager) {
+        for (_, mesh) in self
+            .meshes
+            .extract_if(|position, _| !chunk_manager.chunk_map.contains_key(position))
+        {
+            self.allocator.free(mesh.allocation);
+        }
+        self.pending
+            .retain(|position, _| chunk_manager.chunk_map.contains_key(position));
+        let pending = &self.pending;
+        self.pending_order
+            .retain(|position| pending.contains_key(position));
+        self.remove_stale_lods(chunk_manager);
+    }
+
+    /// Keeps the translucent faces of every mesh sorted for the camera.
+    pub fn sort_translucent(&mut self, queue: &wgpu::Queue, camera_position: Vec3) {
+        for mesh in self.meshes.values_mut().chain(self.lod_meshes.values_mut()) {
+            mesh.sort_translucent(&self.allocator, queue, camera_position);
+        }
+    }
+
+    /// Collects the draws of every mesh the culling lets through for this
+    /// frame and uploads them, which has to happen before the render pass
+    /// they're drawn in. Translucent faces are drawn furthest mesh first.
+    ///
+    /// Chunks missing from `visible_chunks` are left out, level of detail
+    /// nodes are always candidates.
+    pub fn prepare(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        frustum: &Frustum,
+        camera_position: Vec3,
+        visible_chunks: Option<&AHashSet<IVec3>>,
+        chunk_manager: &ChunkManager,
+    ) {
+        self.draws.clear();
+
+        let mut visible = self
+            .meshes
+            .iter()
+            .filter(|(position, _)| visible_chunks.is_none_or(|chunks| chunks.contains(position)))
+            .map(|(_, mesh)| mesh)
+            .chain(self.lod_meshes.values())
+            .filter(|mesh| self.culling.is_candidate(frustum, &mesh.bounding_box))
+            .collect::<Vec<&ChunkMesh>>();
+
+        // meshes in the same pages go together, so they can share a call
+        visible.sort_unstable_by_key(|mesh| mesh.pages());
+        for mesh in visible.iter().filter(|mesh| mesh.index_count > 0) {
+            mesh.push_draw(&mut self.draws, Pass::Opaque, 0..mesh.index_count);
+        }
+
+        let mut translucent = visible
+            .iter()
+            .filter(|mesh| mesh.translucent_index_count > 0)
+            .map(|mesh| {
+                let center =
+                    mesh.world_position.as_vec3() + (CHUNK_SIZE as i32 * mesh.scale) as f32 / 2.0;
+                (center.distance_squared(camera_position), mesh)
+            })
+            .collect::<Vec<_>>();
+        translucent.sort_by(|a, b| b.0.total_cmp(&a.0));
+        for (_, mesh) in translucent {
+            mesh.push_draw(
+                &mut self.draws,
+                Pass::Translucent,
+                mesh.index_count..mesh.index_count + mesh.translucent_index_count,
+            );
+        }
+
+        self.draws.upload(device, queue);
+
+        let count = visible.iter().filter(|mesh| mesh.scale == 1).count();
+        let lod_count = visible.len() - count;
+        let (draw_count, call_count) = self.draws.counts();
+        let vertex_count = self
+            .meshes
+            .values()
+            .map(|mesh| mesh.vertex_count as usize)
+            .sum::<usize>();
+        let block_memory = chunk_manager
+            .chunk_map
+            .values()
+            .map(|chunk| chunk.blocks.memory_usage())
+            .sum::<usize>();
+        let arena = |stats: ArenaStats| {
+            format!(
+                "{}/{}MiB in {} pages, {} gaps {:.0}% fragmented",
+                stats.used / (1024 * 1024),
+                stats.capacity / (1024 * 1024),
+                stats.pages,
+                stats.free_ranges,
+                stats.fragmentation() * 100.0
+            )
+        };
+        println!(
+            "{}/{}\t{}/{}\t{} draws in {} calls, {} culled\t{}\t{}\t{}\t{}\t{}KiB\t{}\t{}",
+            count,
+            chunk_manager.chunk_map.len(),
+            lod_count,
+            self.lod_meshes.len(),
+            draw_count,
+            call_count,
+            self.culling.name(),
+            chunk_manager.chunk_data_load_queue.len(),
+            chunk_manager.chunk_mesh_load_queue.len()
+                + chunk_manager.chunk_mesh_reload_queue.len()
+                + chunk_manager.chunk_neighbor_loaded_queue.len(),
+            chunk_manager.chunks_with_missing_neighbors.len(),
+            vertex_count,
+            block_memory / 1024,
+            arena(self.allocator.vertices.stats()),
+            arena(self.allocator.indices.stats()),
+        );
+    }
+
+    /// Records the culling of the draws uploaded by
+    /// [`ChunkRenderer::prepare`], before the render pass drawing them.
+    pub fn cull(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        encoder: &mut wgpu::CommandEncoder,
+        frustum: &Frustum,
+    ) {
+        self.culling
+            .cull(device, queue, encoder, &self.draws, frustum);
+    }
+
+    /// Records keeping the frame's depth buffer around to cull the next
+    /// frame with, after the render pass drawing it.
+    pub fn keep_depth(
+        &mut self,
+        device: &wgpu::Device,
+        encoder: &mut wgpu::CommandEncoder,
+        depth_texture: &Texture,
+        view_proj: Mat4,
+    ) {
+        self.culling
+            .keep_depth(device, encoder, depth_texture, view_proj);
+    }
+
+    /// Draws the opaque and cutout faces of every visible chunk.
+    pub fn render(&self, render_pass: &mut wgpu::RenderPass) {
+        self.draws.draw(render_pass, Pass::Opaque, &self.allocator);
+    }
+
+    /// Draws the translucent faces of every visible chunk. Has to come after
+    /// [`ChunkRenderer::render`] so whatever is behind the faces is already
+    /// drawn.
+    pub fn render_translucent(&self, render_pass: &mut wgpu::RenderPass) {
+        self.draws
+            .draw(render_pass, Pass::Translucent, &self.allocator);
+    }
+}
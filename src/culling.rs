@@ -0,0 +1,400 @@
+use glam::Mat4;
+use voxel_core::{
+    chunk::CHUNK_SIZE,
+    frustum::{Aabb, Frustum},
+};
+
+use crate::{indirect::IndirectDraws, texture::Texture};
+
+/// Decides which chunk meshes are drawn, either on the CPU while the draws
+/// are collected or on the GPU right before they're issued.
+pub trait Culling {
+    fn name(&self) -> &'static str;
+
+    /// Whether a mesh gets a draw at all this frame.
+    fn is_candidate(&self, frustum: &Frustum, aabb: &Aabb) -> bool;
+
+    /// Records hiding the uploaded draws that aren't visible. Has to be
+    /// recorded before the pass drawing them.
+    fn cull(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        encoder: &mut wgpu::CommandEncoder,
+        draws: &IndirectDraws,
+        frustum: &Frustum,
+    );
+
+    /// Records keeping the depth buffer of the frame, drawn with `view_proj`,
+    /// around for culling the next one. Has to be recorded after the pass
+    /// drawing it.
+    fn keep_depth(
+        &mut self,
+        device: &wgpu::Device,
+        encoder: &mut wgpu::CommandEncoder,
+        depth_texture: &Texture,
+        view_proj: Mat4,
+    );
+}
+
+/// Picks culling on the GPU when the device can run it and it's wanted,
+/// falling back to the CPU otherwise.
+pub fn create_culling(device: &wgpu::Device, draws: &IndirectDraws, gpu: bool) -> Box<dyn Culling> {
+    if gpu && draws.is_indirect() {
+        Box::new(GpuCulling::new(device))
+    } else {
+        Box::new(CpuCulling)
+    }
+}
+
+/// Tests every mesh against the view frustum while collecting the draws.
+pub struct CpuCulling;
+
+impl Culling for CpuCulling {
+    fn name(&self) -> &'static str {
+        "cpu"
+    }
+
+    fn is_candidate(&self, frustum: &Frustum, aabb: &Aabb) -> bool {
+        frustum.contains_aabb(aabb)
+    }
+
+    fn cull(
+        &mut self,
+        _device: &wgpu::Device,
+        _queue: &wgpu::Queue,
+        _encoder: &mut wgpu::CommandEncoder,
+        _draws: &IndirectDraws,
+        _frustum: &Frustum,
+    ) {
+    }
+
+    fn keep_depth(
+        &mut self,
+        _device: &wgpu::Device,
+        _encoder: &mut wgpu::CommandEncoder,
+        _depth_texture: &Texture,
+        _view_proj: Mat4,
+    ) {
+    }
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct CullUniform {
+    frustum: [[f32; 4]; 6],
+    pyramid_view_proj: [[f32; 4]; 4],
+    pyramid_size: [f32; 2],
+    draw_count: u32,
+    occlusion: u32,
+    chunk_size: f32,
+    _padding: [u32; 3],
+}
+
+/// The depth buffer of the last frame, halved level by level.
+struct DepthPyramid {
+    texture: wgpu::Texture,
+    view: wgpu::TextureView,
+    /// a view of every level on its own, to write it while reading the one
+    /// below
+    level_views: Vec<wgpu::TextureView>,
+    view_proj: Mat4,
+}
+
+impl DepthPyramid {
+    fn new(device: &wgpu::Device, width: u32, height: u32) -> Self {
+        let level_count = width.max(height).ilog2() + 1;
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("Depth Pyramid"),
+            size: wgpu::Extent3d {
+                width,
+                height,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: level_count,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: wgpu::TextureFormat::R32Float,
+            usage: wgpu::TextureUsages::STORAGE_BINDING | wgpu::TextureUsages::TEXTURE_BINDING,
+            view_formats: &[],
+        });
+
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        let level_views = (0..level_count)
+            .map(|level| {
+                texture.create_view(&wgpu::TextureViewDescriptor {
+                    base_mip_level: level,
+                    mip_level_count: Some(1),
+                    ..Default::default()
+                })
+            })
+            .collect();
+
+        Self {
+            texture,
+            view,
+            level_views,
+            view_proj: Mat4::IDENTITY,
+        }
+    }
+
+    fn level_size(&self, level: usize) -> (u32, u32) {
+        let size = self
+            .texture
+            .size()
+            .mip_level_size(level as u32, self.texture.dimension());
+        (size.width, size.height)
+    }
+}
+
+/// Tests the bounding box of every draw against the view frustum and a
+/// depth pyramid of the last frame in a compute pass, hiding the draws of
+/// meshes that are off screen or behind what was drawn then.
+///
+/// Meshes that come out from behind something show up a frame late, as the
+/// pyramid is always a frame old.
+pub struct GpuCulling {
+    pyramid_layout: wgpu::BindGroupLayout,
+    copy_depth_pipeline: wgpu::ComputePipeline,
+    reduce_pipeline: wgpu::ComputePipeline,
+    cull_layout: wgpu::BindGroupLayout,
+    cull_pipeline: wgpu::ComputePipeline,
+    uniform_buffer: wgpu::Buffer,
+    /// stands in for the pyramid until the first frame is drawn
+    empty_pyramid: DepthPyramid,
+    pyramid: Option<DepthPyramid>,
+}
+
+impl GpuCulling {
+    pub fn new(device: &wgpu::Device) -> Self {
+        let texture_entry = |binding, sample_type| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::COMPUTE,
+            ty: wgpu::BindingType::Texture {
+                sample_type,
+                view_dimension: wgpu::TextureViewDimension::D2,
+                multisampled: false,
+            },
+            count: None,
+        };
+        let buffer_entry = |binding, ty| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::COMPUTE,
+            ty: wgpu::BindingType::Buffer {
+                ty,
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        };
+        let unfilterable = wgpu::TextureSampleType::Float { filterable: false };
+
+        let pyramid_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Depth Pyramid Bind Group Layout"),
+            entries: &[
+                texture_entry(0, unfilterable),
+                texture_entry(1, unfilterable),
+                wgpu::BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::StorageTexture {
+                        access: wgpu::StorageTextureAccess::WriteOnly,
+                        format: wgpu::TextureFormat::R32Float,
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                    },
+                    count: None,
+                },
+            ],
+        });
+        let cull_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Cull Bind Group Layout"),
+            entries: &[
+                buffer_entry(0, wgpu::BufferBindingType::Uniform),
+                buffer_entry(1, wgpu::BufferBindingType::Storage { read_only: true }),
+                buffer_entry(2, wgpu::BufferBindingType::Storage { read_only: false }),
+                texture_entry(3, unfilterable),
+            ],
+        });
+
+        let pyramid_shader =
+            device.create_shader_module(wgpu::include_wgsl!("shaders/depth_pyramid.wgsl"));
+        let cull_shader = device.create_shader_module(wgpu::include_wgsl!("shaders/cull.wgsl"));
+        let create_pipeline = |label, layout, module, entry_point| {
+            device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+                label: Some(label),
+                layout: Some(
+                    &device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                        label: Some(label),
+                        bind_group_layouts: &[layout],
+                        push_constant_ranges: &[],
+                    }),
+                ),
+                module,
+                entry_point: Some(entry_point),
+                compilation_options: wgpu::PipelineCompilationOptions::default(),
+                cache: None,
+            })
+        };
+
+        let uniform_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Cull Buffer"),
+            size: std::mem::size_of::<CullUniform>() as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        Self {
+            copy_depth_pipeline: create_pipeline(
+                "Copy Depth Pipeline",
+                &pyramid_layout,
+                &pyramid_shader,
+                "copy_depth",
+            ),
+            reduce_pipeline: create_pipeline(
+                "Reduce Depth Pipeline",
+                &pyramid_layout,
+                &pyramid_shader,
+                "reduce",
+            ),
+            cull_pipeline: create_pipeline(
+                "Cull Pipeline",
+                &cull_layout,
+                &cull_shader,
+                "cull_draws",
+            ),
+            pyramid_layout,
+            cull_layout,
+            uniform_buffer,
+            empty_pyramid: DepthPyramid::new(device, 1, 1),
+            pyramid: None,
+        }
+    }
+}
+
+impl Culling for GpuCulling {
+    fn name(&self) -> &'static str {
+        "gpu"
+    }
+
+    fn is_candidate(&self, _frustum: &Frustum, _aabb: &Aabb) -> bool {
+        true
+    }
+
+    fn cull(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        encoder: &mut wgpu::CommandEncoder,
+        draws: &IndirectDraws,
+        frustum: &Frustum,
+    ) {
+        let draw_count = draws.counts().0 as u32;
+        if draw_count == 0 {
+            return;
+        }
+
+        let pyramid = self.pyramid.as_ref().unwrap_or(&self.empty_pyramid);
+        let (width, height) = pyramid.level_size(0);
+        let uniform = CullUniform {
+            frustum: frustum.planes.map(|plane| plane.to_array()),
+            pyramid_view_proj: pyramid.view_proj.to_cols_array_2d(),
+            pyramid_size: [width as f32, height as f32],
+            draw_count,
+            occlusion: self.pyramid.is_some() as u32,
+            chunk_size: CHUNK_SIZE as f32,
+            _padding: [0; 3],
+        };
+        queue.write_buffer(&self.uniform_buffer, 0, bytemuck::cast_slice(&[uniform]));
+
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Cull Bind Group"),
+            layout: &self.cull_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: self.uniform_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: draws.instance_buffer().as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: draws.draw_buffer().as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: wgpu::BindingResource::TextureView(&pyramid.view),
+                },
+            ],
+        });
+
+        let mut compute_pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+            label: Some("Cull Pass"),
+            timestamp_writes: None,
+        });
+        compute_pass.set_pipeline(&self.cull_pipeline);
+        compute_pass.set_bind_group(0, &bind_group, &[]);
+        compute_pass.dispatch_workgroups(draw_count.div_ceil(64), 1, 1);
+    }
+
+    fn keep_depth(
+        &mut self,
+        device: &wgpu::Device,
+        encoder: &mut wgpu::CommandEncoder,
+        depth_texture: &Texture,
+        view_proj: Mat4,
+    ) {
+        let depth_size = depth_texture.texture.size();
+        let pyramid = match &mut self.pyramid {
+            Some(pyramid) if pyramid.level_size(0) == (depth_size.width, depth_size.height) => {
+                pyramid
+            }
+            pyramid => pyramid.insert(DepthPyramid::new(
+                device,
+                depth_size.width,
+                depth_size.height,
+            )),
+        };
+        pyramid.view_proj = view_proj;
+
+        let mut compute_pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+            label: Some("Depth Pyramid Pass"),
+            timestamp_writes: None,
+        });
+        for (level, level_view) in pyramid.level_views.iter().enumerate() {
+            // the first level reads the depth buffer, and every other one the
+            // level below, the other texture is only bound to fill the slot
+            let previous = match level {
+                0 => &self.empty_pyramid.view,
+                _ => &pyramid.level_views[level - 1],
+            };
+            let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+                label: Some("Depth Pyramid Bind Group"),
+                layout: &self.pyramid_layout,
+                entries: &[
+                    wgpu::BindGroupEntry {
+                        binding: 0,
+                        resource: wgpu::BindingResource::TextureView(&depth_texture.view),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 1,
+                        resource: wgpu::BindingResource::TextureView(previous),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 2,
+                        resource: wgpu::BindingResource::TextureView(level_view),
+                    },
+                ],
+            });
+
+            compute_pass.set_pipeline(match level {
+                0 => &self.copy_depth_pipeline,
+                _ => &self.reduce_pipeline,
+            });
+            compute_pass.set_bind_group(0, &bind_group, &[]);
+            let (width, height) = pyramid.level_size(level);
+            compute_pass.dispatch_workgroups(width.div_ceil(8), height.div_ceil(8), 1);
+        }
+    }
+}
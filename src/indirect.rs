@@ -0,0 +1,266 @@
+use wgpu::util::DrawIndexedIndirectArgs;
+
+use crate::mesh_allocator::MeshAllocator;
+
+/// the fewest draws the buffers are made for, they grow from there as needed
+const MIN_DRAW_CAPACITY: usize = 256;
+
+/// Which faces of the meshes a draw covers, drawn with different pipelines.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Pass {
+    Opaque,
+    Translucent,
+}
+
+/// Consecutive draws reading from the same vertex and index pages, issued
+/// with a single call.
+struct Batch {
+    vertex_page: usize,
+    index_page: usize,
+    first_draw: u32,
+    count: u32,
+}
+
+/// The chunk draws of a frame, collected on the CPU and issued as a few
+/// indirect multi-draws instead of one draw call per chunk.
+///
+/// Every draw gets its own instance, whose index the vertex shader uses to
+/// look up the position and scale of the mesh in a storage buffer, so
+/// nothing has to be set between draws.
+pub struct IndirectDraws {
+    /// whether the device can take the draws from a buffer, otherwise they
+    /// are issued one by one with the same instance data
+    indirect: bool,
+    instances: Vec<[i32; 4]>,
+    draws: Vec<DrawIndexedIndirectArgs>,
+    opaque: Vec<Batch>,
+    translucent: Vec<Batch>,
+    capacity: usize,
+    instance_buffer: wgpu::Buffer,
+    draw_buffer: wgpu::Buffer,
+    bind_group_layout: wgpu::BindGroupLayout,
+    bind_group: wgpu::BindGroup,
+}
+
+/// The device features indirect drawing needs, requested when the adapter
+/// has them.
+pub const INDIRECT_FEATURES: wgpu::Features =
+    wgpu::Features::MULTI_DRAW_INDIRECT.union(wgpu::Features::INDIRECT_FIRST_INSTANCE);
+
+impl IndirectDraws {
+    pub fn new(device: &wgpu::Device) -> Self {
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Chunk Instance Bind Group Layout"),
+            entries: &[wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStages::VERTEX,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Storage { read_only: true },
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            }],
+        });
+
+        let (instance_buffer, draw_buffer, bind_group) =
+            Self::create_buffers(device, &bind_group_layout, MIN_DRAW_CAPACITY);
+
+        let indirect = device.features().contains(INDIRECT_FEATURES);
+        if !indirect {
+            log::info!("indirect drawing isn't supported, drawing chunks one by one");
+        }
+
+        Self {
+            indirect,
+            instances: Vec::new(),
+            draws: Vec::new(),
+            opaque: Vec::new(),
+            translucent: Vec::new(),
+            capacity: MIN_DRAW_CAPACITY,
+            instance_buffer,
+            draw_buffer,
+            bind_group_layout,
+            bind_group,
+        }
+    }
+
+    pub fn bind_group_layout(&self) -> &wgpu::BindGroupLayout {
+        &self.bind_group_layout
+    }
+
+    fn create_buffers(
+        device: &wgpu::Device,
+        layout: &wgpu::BindGroupLayout,
+        capacity: usize,
+    ) -> (wgpu::Buffer, wgpu::Buffer, wgpu::BindGroup) {
+        let instance_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Chunk Instance Buffer"),
+            size: (capacity * std::mem::size_of::<[i32; 4]>()) as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let draw_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Chunk Draw Buffer"),
+            size: (capacity * std::mem::size_of::<DrawIndexedIndirectArgs>())
+                as wgpu::BufferAddress,
+            // written by the culling compute pass as well
+            usage: wgpu::BufferUsages::INDIRECT
+                | wgpu::BufferUsages::STORAGE
+                | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Chunk Instance Bind Group"),
+            layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: instance_buffer.as_entire_binding(),
+            }],
+        });
+
+        (instance_buffer, draw_buffer, bind_group)
+    }
+
+    /// Whether the draws are taken from the draw buffer, so the GPU can
+    /// change them before they're issued.
+    pub fn is_indirect(&self) -> bool {
+        self.indirect
+    }
+
+    /// Position and scale of the mesh of every draw, at the draw's index.
+    pub fn instance_buffer(&self) -> &wgpu::Buffer {
+        &self.instance_buffer
+    }
+
+    pub fn draw_buffer(&self) -> &wgpu::Buffer {
+        &self.draw_buffer
+    }
+
+    /// Forgets the draws of the last frame.
+    pub fn clear(&mut self) {
+        self.instances.clear();
+        self.draws.clear();
+        self.opaque.clear();
+        self.translucent.clear();
+    }
+
+    /// Adds a draw of `index_count` indices starting at `first_index`, for a
+    /// mesh whose first vertex is at `base_vertex`. Draws are issued in the
+    /// order they were added, and all the opaque ones have to be added
+    /// before the translucent ones.
+    pub fn push(
+        &mut self,
+        pass: Pass,
+        pages: (usize, usize),
+        instance: [i32; 4],
+        first_index: u32,
+        index_count: u32,
+        base_vertex: u32,
+    ) {
+        let draw = self.draws.len() as u32;
+        self.instances.push(instance);
+        self.draws.push(DrawIndexedIndirectArgs {
+            index_count,
+            instance_count: 1,
+            first_index,
+            base_vertex: base_vertex as i32,
+            first_instance: draw,
+        });
+
+        let batches = match pass {
+            Pass::Opaque => &mut self.opaque,
+            Pass::Translucent => &mut self.translucent,
+        };
+        match batches.last_mut() {
+            Some(batch)
+                if (batch.vertex_page, batch.index_page) == pages
+                    && batch.first_draw + batch.count == draw =>
+            {
+                batch.count += 1;
+            }
+            _ => batches.push(Batch {
+                vertex_page: pages.0,
+                index_page: pages.1,
+                first_draw: draw,
+                count: 1,
+            }),
+        }
+    }
+
+    /// The number of draws and of the calls they're issued with.
+    pub fn counts(&self) -> (usize, usize) {
+        let calls = if self.indirect {
+            self.opaque.len() + self.translucent.len()
+        } else {
+            self.draws.len()
+        };
+        (self.draws.len(), calls)
+    }
+
+    /// Sends the draws of this frame to the GPU, growing the buffers if they
+    /// don't fit anymore.
+    pub fn upload(&mut self, device: &wgpu::Device, queue: &wgpu::Queue) {
+        if self.draws.len() > self.capacity {
+            self.capacity = self.draws.len().next_power_of_two();
+            (self.instance_buffer, self.draw_buffer, self.bind_group) =
+                Self::create_buffers(device, &self.bind_group_layout, self.capacity);
+        }
+
+        if self.draws.is_empty() {
+            return;
+        }
+
+        queue.write_buffer(
+            &self.instance_buffer,
+            0,
+            bytemuck::cast_slice(&self.instances),
+        );
+        if self.indirect {
+            let bytes = self
+                .draws
+                .iter()
+                .flat_map(|draw| draw.as_bytes())
+                .copied()
+                .collect::<Vec<u8>>();
+            queue.write_buffer(&self.draw_buffer, 0, &bytes);
+        }
+    }
+
+    /// Issues the draws of a pass, uploaded with [`IndirectDraws::upload`].
+    /// The pipeline for the pass has to be set already.
+    pub fn draw(&self, render_pass: &mut wgpu::RenderPass, pass: Pass, allocator: &MeshAllocator) {
+        let batches = match pass {
+            Pass::Opaque => &self.opaque,
+            Pass::Translucent => &self.translucent,
+        };
+
+        render_pass.set_bind_group(3, &self.bind_group, &[]);
+        for batch in batches {
+            render_pass
+                .set_vertex_buffer(0, allocator.vertices.buffer(batch.vertex_page).slice(..));
+            render_pass.set_index_buffer(
+                allocator.indices.buffer(batch.index_page).slice(..),
+                wgpu::IndexFormat::Uint32,
+            );
+
+            if self.indirect {
+                render_pass.multi_draw_indexed_indirect(
+                    &self.draw_buffer,
+                    (batch.first_draw as usize * std::mem::size_of::<DrawIndexedIndirectArgs>())
+                        as wgpu::BufferAddress,
+                    batch.count,
+                );
+            } else {
+                let first = batch.first_draw as usize;
+                for draw in &self.draws[first..first + batch.count as usize] {
+                    render_pass.draw_indexed(
+                        draw.first_index..draw.first_index + draw.index_count,
+                        draw.base_vertex,
+                        draw.first_instance..draw.first_instance + 1,
+                    );
+                }
+            }
+        }
+    }
+}
@@ -1,12 +1,25 @@
-use std::{sync::Arc, time::Instant};
+use std::{path::Path, sync::Arc, time::Instant};
 
-use camera::{Camera, CameraController, CameraUniform, Projection};
-use chunk::{Block, CHUNK_SIZE, Vertex};
-use chunk_manager::ChunkManager;
-use enum_iterator::last;
-use frustum::Frustum;
+use anyhow::Context;
+
+use camera::{CameraController, CameraUniform};
+use chunk_renderer::ChunkRenderer;
 use glam::{IVec3, Vec3};
+use options::Options;
 use texture::Texture;
+use voxel_core::{
+    block::{Block, BlockRegistry, registry, set_registry},
+    camera::{Camera, Projection},
+    chunk::{CHUNK_SIZE, MeshingMode},
+    chunk_manager::ChunkManager,
+    client::Client,
+    frustum::Frustum,
+    generator::generator_from_name,
+    schematic::{Schematic, Transform},
+    time::TimeOfDay,
+    vox::{self, BlockColors, PaletteMapping},
+    world_save::{LevelInfo, WorldSave},
+};
 use wgpu::{PresentMode, util::DeviceExt};
 use winit::{
     application::ApplicationHandler,
@@ -17,11 +30,17 @@ use winit::{
 };
 
 mod camera;
-mod chunk;
-mod chunk_manager;
-mod frustum;
+mod chunk_renderer;
+mod culling;
+mod indirect;
+mod mesh_allocator;
+mod options;
 mod texture;
 
+const ATLAS_PATH: &str = "assets/atlas.png";
+/// where copied selections are saved
+const SELECTION_PATH: &str = "schematics/selection.vxs";
+
 pub struct State {
     start: std::time::Instant,
 
@@ -31,13 +50,23 @@ pub struct State {
     config: wgpu::SurfaceConfiguration,
     is_surface_configured: bool,
     render_pipeline: wgpu::RenderPipeline,
+    translucent_pipeline: wgpu::RenderPipeline,
     window: Arc<Window>,
     is_cursor_visible: bool,
 
     chunk_manager: ChunkManager,
+    /// the server the world comes from, if it isn't a local one
+    client: Option<Client>,
+    /// the settings of the local world, saved with it
+    level_info: Option<LevelInfo>,
+    chunk_renderer: ChunkRenderer,
     chosen_block: Block,
     look_at_position: IVec3,
     look_at_normal: IVec3,
+    /// opposite corners of the box to copy
+    selection: [Option<IVec3>; 2],
+    clipboard: Option<Schematic>,
+    paste_transform: Transform,
 
     camera: Camera,
     projection: Projection,
@@ -56,7 +85,7 @@ pub struct State {
 }
 
 impl State {
-    pub async fn new(window: Arc<Window>) -> anyhow::Result<Self> {
+    pub async fn new(window: Arc<Window>, options: &Options) -> anyhow::Result<Self> {
         let size = window.inner_size();
 
         let instance = wgpu::Instance::new(&wgpu::InstanceDescriptor {
@@ -79,9 +108,10 @@ impl State {
                 label: None,
                 required_features: wgpu::Features::POLYGON_MODE_LINE
                     | wgpu::Features::POLYGON_MODE_POINT
-                    | wgpu::Features::PUSH_CONSTANTS,
+                    | wgpu::Features::PUSH_CONSTANTS
+                    | (adapter.features() & indirect::INDIRECT_FEATURES),
                 required_limits: wgpu::Limits {
-                    max_push_constant_size: 24,
+                    max_push_constant_size: 12,
                     ..wgpu::Limits::downlevel_defaults()
                 },
                 memory_hints: Default::default(),
@@ -114,7 +144,32 @@ impl State {
             desired_maximum_frame_latency: 2,
         };
 
-        let camera = Camera::new(Vec3::new(0.0, CHUNK_SIZE as f32, 0.0), 0.0, 0.0);
+        let (mut chunk_manager, client, level_info) = match &options.connect {
+            Some(address) => {
+                let client = Client::connect(address.as_str(), &options.name)
+                    .with_context(|| format!("unable to join {address}"))?;
+                let mut chunk_manager = client.create_chunk_manager()?;
+                chunk_manager.lod_levels = options.lod_levels;
+                (chunk_manager, Some(client), None)
+            }
+            None => {
+                let (chunk_manager, level_info) = open_world(options)?;
+                (chunk_manager, None, Some(level_info))
+            }
+        };
+
+        let clipboard = options
+            .schematic
+            .as_deref()
+            .map(load_schematic)
+            .transpose()?;
+
+        let spawn = client
+            .as_ref()
+            .map_or(Vec3::new(0.0, CHUNK_SIZE as f32, 0.0), |client| {
+                client.welcome.spawn
+            });
+        let camera = Camera::new(spawn, 0.0, 0.0);
         let projection = Projection::new(size.width, size.height, 60.0, 0.1, 1000.0);
         let camera_controller = CameraController::new(10.0, 0.1);
 
@@ -152,7 +207,7 @@ impl State {
         });
 
         let atlas_texture =
-            Texture::from_path(&device, &queue, "assets/atlas.png", Some("Atlas Texture")).unwrap();
+            Texture::from_path(&device, &queue, ATLAS_PATH, Some("Atlas Texture")).unwrap();
 
         let atlas_bind_group_layout =
             device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
@@ -194,7 +249,7 @@ impl State {
 
         let time_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
             label: Some("Time Buffer"),
-            contents: bytemuck::cast_slice(&[0]),
+            contents: bytemuck::cast_slice(&[0.0f32, 1.0]),
             usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
         });
 
@@ -222,6 +277,13 @@ impl State {
             label: Some("Time Bind Group"),
         });
 
+        let gpu_culling = !options.cpu_culling
+            && adapter
+                .get_downlevel_capabilities()
+                .flags
+                .contains(wgpu::DownlevelFlags::COMPUTE_SHADERS);
+        let chunk_renderer = ChunkRenderer::new(&device, gpu_culling);
+
         let shader = device.create_shader_module(wgpu::include_wgsl!("shaders/shader.wgsl"));
         let render_pipeline_layout =
             device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
@@ -230,65 +292,30 @@ impl State {
                     &camera_bind_group_layout,
                     &atlas_bind_group_layout,
                     &time_bind_group_layout,
+                    chunk_renderer.bind_group_layout(),
                 ],
-                push_constant_ranges: &[
-                    wgpu::PushConstantRange {
-                        stages: wgpu::ShaderStages::VERTEX,
-                        range: 0..12,
-                    },
-                    wgpu::PushConstantRange {
-                        stages: wgpu::ShaderStages::FRAGMENT,
-                        range: 12..24,
-                    },
-                ],
+                push_constant_ranges: &[wgpu::PushConstantRange {
+                    stages: wgpu::ShaderStages::FRAGMENT,
+                    range: 0..12,
+                }],
             });
 
-        let render_pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
-            label: Some("Render Pipeline"),
-            layout: Some(&render_pipeline_layout),
-            vertex: wgpu::VertexState {
-                module: &shader,
-                entry_point: Some("vs_main"),
-                buffers: &[Vertex::desc()],
-                compilation_options: wgpu::PipelineCompilationOptions::default(),
-            },
-            fragment: Some(wgpu::FragmentState {
-                module: &shader,
-                entry_point: Some("fs_main"),
-                targets: &[Some(wgpu::ColorTargetState {
-                    format: config.format,
-                    blend: Some(wgpu::BlendState::REPLACE),
-                    write_mask: wgpu::ColorWrites::ALL,
-                })],
-                compilation_options: wgpu::PipelineCompilationOptions::default(),
-            }),
-            primitive: wgpu::PrimitiveState {
-                topology: wgpu::PrimitiveTopology::TriangleList,
-                strip_index_format: None,
-                front_face: wgpu::FrontFace::Cw,
-                cull_mode: Some(wgpu::Face::Back),
-                polygon_mode: wgpu::PolygonMode::Fill,
-                unclipped_depth: false,
-                conservative: false,
-            },
-            depth_stencil: Some(wgpu::DepthStencilState {
-                format: wgpu::TextureFormat::Depth32Float,
-                depth_write_enabled: true,
-                depth_compare: wgpu::CompareFunction::Less,
-                bias: wgpu::DepthBiasState::default(),
-                stencil: wgpu::StencilState::default(),
-            }),
-            multisample: wgpu::MultisampleState {
-                count: 1,
-                mask: !0,
-                alpha_to_coverage_enabled: false,
-            },
-            multiview: None,
-            cache: None,
-        });
+        let render_pipeline = create_chunk_pipeline(
+            &device,
+            &render_pipeline_layout,
+            &shader,
+            config.format,
+            false,
+        );
+        let translucent_pipeline = create_chunk_pipeline(
+            &device,
+            &render_pipeline_layout,
+            &shader,
+            config.format,
+            true,
+        );
 
-        let mut chunk_manager = ChunkManager::new(10);
-        chunk_manager.update_around(IVec3::ZERO);
+        chunk_manager.update_around((spawn / CHUNK_SIZE as f32).floor().as_ivec3());
 
         let depth_texture =
             Texture::create_depth_texture(&device, size.width, size.height, Some("Depth Texture"));
@@ -302,13 +329,20 @@ impl State {
             config,
             is_surface_configured: false,
             render_pipeline,
+            translucent_pipeline,
             window,
             is_cursor_visible: false,
 
             chunk_manager,
-            chosen_block: Block::Dirt,
+            client,
+            level_info,
+            chunk_renderer,
+            chosen_block: Block::named("dirt"),
             look_at_position: IVec3::ZERO,
             look_at_normal: IVec3::ZERO,
+            selection: [None; 2],
+            clipboard,
+            paste_transform: Transform::default(),
 
             camera,
             projection,
@@ -338,6 +372,22 @@ impl State {
         }
     }
 
+    /// Saves the edited chunks and the time of day of a local world.
+    pub fn save(&mut self) {
+        if let Err(e) = self.chunk_manager.save_all() {
+            log::error!("unable to save world {:#}", e);
+        }
+
+        if let Some(level_info) = &mut self.level_info
+            && let Some(world_save) = &self.chunk_manager.world_save
+        {
+            level_info.time = self.chunk_manager.time_of_day;
+            if let Err(e) = world_save.save_level_info(level_info) {
+                log::error!("unable to save level info {:#}", e);
+            }
+        }
+    }
+
     pub fn handle_mouse_button(
         &mut self,
         _event_loop: &ActiveEventLoop,
@@ -346,47 +396,106 @@ impl State {
     ) {
         let pos = self.look_at_position;
         let normal = self.look_at_normal;
-        let hit_block = self.chunk_manager.get_block(self.look_at_position);
+        let hit_block = self
+            .chunk_manager
+            .get_block(self.look_at_position)
+            .filter(|block| *block != Block::AIR);
+
+        match (button, is_pressed, hit_block) {
+            (MouseButton::Left, true, Some(_)) => self.set_block(pos, Block::AIR),
+            (MouseButton::Right, true, Some(_)) => self.set_block(pos + normal, self.chosen_block),
+            (MouseButton::Middle, true, Some(block)) => self.chosen_block = block,
+            (MouseButton::Forward, true, _) => self.cycle_chosen_block(1),
+            (MouseButton::Back, true, _) => self.cycle_chosen_block(-1),
+            _ => (),
+        }
+    }
 
-        match (button, is_pressed) {
-            (MouseButton::Left, true) => {
-                if hit_block.is_some() && hit_block != Some(Block::Air) {
-                    self.chunk_manager.set_block(pos, Block::Air);
-                }
-            }
-            (MouseButton::Right, true) => {
-                if hit_block.is_some() && hit_block != Some(Block::Air) {
-                    self.chunk_manager
-                        .set_block(pos + normal, self.chosen_block);
-                }
-            }
-            (MouseButton::Middle, true) => {
-                if hit_block.is_some() && hit_block != Some(Block::Air) {
-                    if let Some(block) = self.chunk_manager.get_block(pos) {
-                        self.chosen_block = block;
-                    }
-                }
-            }
-            (MouseButton::Forward, true) => {
-                let current: usize = self.chosen_block.into();
-                if let Ok(next) = Block::try_from(current + 1) {
-                    self.chosen_block = next;
-                } else if let Ok(block) = Block::try_from(1) {
-                    self.chosen_block = block;
-                }
-            }
-            (MouseButton::Back, true) => {
-                let current: usize = self.chosen_block.into();
-                if current > 1
-                    && let Ok(next) = Block::try_from(current - 1)
-                {
-                    self.chosen_block = next;
-                } else if let Some(block) = last::<Block>() {
-                    self.chosen_block = block;
-                }
+    /// Changes a block, through the server when playing on one.
+    fn set_block(&mut self, position: IVec3, block: Block) {
+        match &self.client {
+            Some(client) => client.set_block(&mut self.chunk_manager, position, block),
+            None => self.chunk_manager.set_block(position, block),
+        }
+    }
+
+    fn is_looking_at_block(&self) -> bool {
+        self.chunk_manager
+            .get_block(self.look_at_position)
+            .is_some_and(|block| !block.is_air())
+    }
+
+    /// Sets one corner of the selection to the block being looked at.
+    fn select_corner(&mut self, corner: usize) {
+        if !self.is_looking_at_block() {
+            return;
+        }
+        self.selection[corner] = Some(self.look_at_position);
+        log::info!(
+            "selection corner {} at {}",
+            corner + 1,
+            self.look_at_position
+        );
+    }
+
+    /// Copies the selection to the clipboard and saves it so it can be
+    /// pasted into other worlds.
+    fn copy_selection(&mut self) {
+        let [Some(a), Some(b)] = self.selection else {
+            log::warn!("select two corners before copying");
+            return;
+        };
+
+        let schematic = match Schematic::copy(&self.chunk_manager, a, b) {
+            Ok(schematic) => schematic,
+            Err(e) => {
+                log::error!("unable to copy the selection: {e:#}");
+                return;
             }
-            _ => (),
+        };
+        log::info!("copied {} blocks", schematic.size());
+        if let Err(e) = schematic.save(SELECTION_PATH) {
+            log::error!("unable to save the selection: {e:#}");
         }
+        self.clipboard = Some(schematic);
+    }
+
+    /// Pastes the clipboard against the face being looked at.
+    fn paste_clipboard(&mut self) {
+        let Some(clipboard) = &self.clipboard else {
+            log::warn!("nothing to paste, copy a selection first");
+            return;
+        };
+        if self.client.is_some() {
+            log::warn!("pasting isn't allowed on servers");
+            return;
+        }
+        if !self.is_looking_at_block() {
+            return;
+        }
+
+        let origin = self.look_at_position + self.look_at_normal;
+        let placed = clipboard.paste(&mut self.chunk_manager, origin, self.paste_transform);
+        log::info!("pasted {placed} blocks at {origin}");
+    }
+
+    /// Steps through every block that can be placed, which leaves out air
+    /// and the flowing levels of fluids.
+    fn cycle_chosen_block(&mut self, step: isize) {
+        let placeable = registry()
+            .blocks()
+            .filter(|block| !block.is_air() && block.fluid().is_none_or(|fluid| fluid.is_source()))
+            .collect::<Vec<_>>();
+        if placeable.is_empty() {
+            return;
+        }
+
+        let current = placeable
+            .iter()
+            .position(|&block| block == self.chosen_block)
+            .unwrap_or(0);
+        let next = (current as isize + step).rem_euclid(placeable.len() as isize);
+        self.chosen_block = placeable[next as usize];
     }
 
     pub fn handle_key(&mut self, _event_loop: &ActiveEventLoop, code: KeyCode, is_pressed: bool) {
@@ -403,16 +512,46 @@ impl State {
                     self.window.set_cursor_visible(!self.is_cursor_visible);
                     self.is_cursor_visible = !self.is_cursor_visible;
                 }
+                (KeyCode::KeyG, true) => {
+                    let mode = match self.chunk_manager.meshing_mode {
+                        MeshingMode::Naive => MeshingMode::Greedy,
+                        MeshingMode::Greedy => MeshingMode::Naive,
+                    };
+                    log::info!("switching to {:?} meshing", mode);
+                    self.chunk_manager.set_meshing_mode(mode);
+                }
+                (KeyCode::KeyZ, true) => self.select_corner(0),
+                (KeyCode::KeyX, true) => self.select_corner(1),
+                (KeyCode::KeyC, true) => self.copy_selection(),
+                (KeyCode::KeyV, true) => self.paste_clipboard(),
+                (KeyCode::KeyR, true) => {
+                    self.paste_transform.rotation = (self.paste_transform.rotation + 1) % 4;
+                    log::info!(
+                        "pasting rotated {} degrees",
+                        self.paste_transform.rotation as u32 * 90
+                    );
+                }
+                (KeyCode::KeyM, true) => {
+                    self.paste_transform.mirror = !self.paste_transform.mirror;
+                    log::info!("pasting mirrored: {}", self.paste_transform.mirror);
+                }
                 _ => (),
             }
         }
     }
 
-    pub fn update(&mut self, dt: std::time::Duration) {
+    /// Advances the world by `dt`. Fails when the connection to the server
+    /// is lost.
+    pub fn update(&mut self, dt: std::time::Duration) -> anyhow::Result<()> {
+        if let Some(position) = self.client.as_mut().and_then(Client::take_teleport) {
+            self.camera.position = position;
+        }
+
         let prev_chunk = (self.camera.position / CHUNK_SIZE as f32)
             .floor()
             .as_ivec3();
-        self.camera_controller.update_camera(&mut self.camera, dt);
+        self.camera_controller
+            .update_camera(&mut self.camera, &self.chunk_manager, dt);
         let new_chunk = (self.camera.position / CHUNK_SIZE as f32)
             .floor()
             .as_ivec3();
@@ -425,15 +564,26 @@ impl State {
         );
 
         if prev_chunk != new_chunk {
-            self.chunk_manager.update_around(new_chunk);
+            let unloaded = self.chunk_manager.update_around(new_chunk);
+            if let Some(client) = &self.client {
+                client.unload_chunks(unloaded);
+            }
+            self.chunk_renderer.remove_unloaded(&self.chunk_manager);
         }
+        self.chunk_manager.set_view(
+            self.camera.forward(),
+            Some(Frustum::from_camera(&self.camera, &self.projection)),
+        );
 
         self.queue.write_buffer(
             &self.time_buffer,
             0,
-            bytemuck::cast_slice(&[std::time::Instant::now()
-                .duration_since(self.start)
-                .as_millis() as f32]),
+            bytemuck::cast_slice(&[
+                std::time::Instant::now()
+                    .duration_since(self.start)
+                    .as_millis() as f32,
+                self.chunk_manager.time_of_day.daylight(),
+            ]),
         );
 
         self.camera_uniform
@@ -444,9 +594,21 @@ impl State {
             bytemuck::cast_slice(&[self.camera_uniform]),
         );
 
-        self.chunk_manager.build_chunk_data_in_queue(20);
-        self.chunk_manager
-            .build_chunk_mesh_in_queue(12, &self.device);
+        if let Some(client) = &mut self.client {
+            client.send_position(self.camera.position, self.camera.yaw, self.camera.pitch);
+            client.update(&mut self.chunk_manager)?;
+        }
+
+        self.chunk_manager.time_of_day.advance(dt.as_secs_f32());
+        self.chunk_manager.update_fluids(dt.as_secs_f32());
+        let finished = self.chunk_manager.update_jobs();
+        self.chunk_renderer.queue_uploads(finished);
+        self.chunk_renderer
+            .upload(&self.device, &self.queue, &self.chunk_manager);
+        self.chunk_renderer
+            .sort_translucent(&self.queue, self.camera.position);
+
+        Ok(())
     }
 
     pub fn render(&mut self) -> Result<(), wgpu::SurfaceError> {
@@ -461,12 +623,30 @@ impl State {
             .texture
             .create_view(&wgpu::TextureViewDescriptor::default());
 
+        let frustum = Frustum::from_camera(&self.camera, &self.projection);
+        let visible_chunks = self
+            .chunk_manager
+            .visible_chunks(self.camera.position, Some(&frustum));
+        self.chunk_renderer.prepare(
+            &self.device,
+            &self.queue,
+            &frustum,
+            self.camera.position,
+            visible_chunks.as_ref(),
+            &self.chunk_manager,
+        );
+
         let mut encoder = self
             .device
             .create_command_encoder(&wgpu::CommandEncoderDescriptor {
                 label: Some("Render Encoder"),
             });
 
+        self.chunk_renderer
+            .cull(&self.device, &self.queue, &mut encoder, &frustum);
+
+        let daylight = self.chunk_manager.time_of_day.daylight() as f64;
+
         {
             let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
                 label: Some("Render Pass"),
@@ -475,9 +655,9 @@ impl State {
                     resolve_target: None,
                     ops: wgpu::Operations {
                         load: wgpu::LoadOp::Clear(wgpu::Color {
-                            r: 0.1,
-                            g: 0.2,
-                            b: 0.3,
+                            r: 0.1 * daylight,
+                            g: 0.2 * daylight,
+                            b: 0.3 * daylight,
                             a: 1.0,
                         }),
                         store: wgpu::StoreOp::Store,
@@ -502,13 +682,22 @@ impl State {
             render_pass.set_bind_group(2, &self.time_bind_group, &[]);
             render_pass.set_push_constants(
                 wgpu::ShaderStages::FRAGMENT,
-                12,
+                0,
                 bytemuck::cast_slice(&self.look_at_position.to_array()),
             );
-            let frustum = Frustum::from_camera(&self.camera, &self.projection);
-            self.chunk_manager.render(&mut render_pass, &frustum);
+            self.chunk_renderer.render(&mut render_pass);
+
+            render_pass.set_pipeline(&self.translucent_pipeline);
+            self.chunk_renderer.render_translucent(&mut render_pass);
         }
 
+        self.chunk_renderer.keep_depth(
+            &self.device,
+            &mut encoder,
+            &self.depth_texture,
+            self.projection.calc_matrix() * self.camera.calc_matrix(),
+        );
+
         self.queue.submit(std::iter::once(encoder.finish()));
         output.present();
 
@@ -516,14 +705,141 @@ impl State {
     }
 }
 
+/// Creates the pipeline chunks are drawn with. The translucent one blends
+/// with what's already drawn and leaves the depth buffer alone, so faces
+/// behind other translucent faces still show through.
+fn create_chunk_pipeline(
+    device: &wgpu::Device,
+    layout: &wgpu::PipelineLayout,
+    shader: &wgpu::ShaderModule,
+    format: wgpu::TextureFormat,
+    translucent: bool,
+) -> wgpu::RenderPipeline {
+    device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+        label: Some(if translucent {
+            "Translucent Render Pipeline"
+        } else {
+            "Render Pipeline"
+        }),
+        layout: Some(layout),
+        vertex: wgpu::VertexState {
+            module: shader,
+            entry_point: Some("vs_main"),
+            buffers: &[chunk_renderer::vertex_desc()],
+            compilation_options: wgpu::PipelineCompilationOptions::default(),
+        },
+        fragment: Some(wgpu::FragmentState {
+            module: shader,
+            entry_point: Some(if translucent {
+                "fs_translucent"
+            } else {
+                "fs_main"
+            }),
+            targets: &[Some(wgpu::ColorTargetState {
+                format,
+                blend: Some(if translucent {
+                    wgpu::BlendState::ALPHA_BLENDING
+                } else {
+                    wgpu::BlendState::REPLACE
+                }),
+                write_mask: wgpu::ColorWrites::ALL,
+            })],
+            compilation_options: wgpu::PipelineCompilationOptions::default(),
+        }),
+        primitive: wgpu::PrimitiveState {
+            topology: wgpu::PrimitiveTopology::TriangleList,
+            strip_index_format: None,
+            front_face: wgpu::FrontFace::Cw,
+            cull_mode: Some(wgpu::Face::Back),
+            polygon_mode: wgpu::PolygonMode::Fill,
+            unclipped_depth: false,
+            conservative: false,
+        },
+        depth_stencil: Some(wgpu::DepthStencilState {
+            format: wgpu::TextureFormat::Depth32Float,
+            depth_write_enabled: !translucent,
+            depth_compare: wgpu::CompareFunction::Less,
+            bias: wgpu::DepthBiasState::default(),
+            stencil: wgpu::StencilState::default(),
+        }),
+        multisample: wgpu::MultisampleState {
+            count: 1,
+            mask: !0,
+            alpha_to_coverage_enabled: false,
+        },
+        multiview: None,
+        cache: None,
+    })
+}
+
+/// Loads a schematic, or a MagicaVoxel model with its colours matched to the
+/// blocks of the atlas.
+fn load_schematic(path: &Path) -> anyhow::Result<Schematic> {
+    if path.extension().is_none_or(|extension| extension != "vox") {
+        return Schematic::load(path);
+    }
+
+    let atlas = image::open(ATLAS_PATH)
+        .with_context(|| format!("unable to read the atlas {ATLAS_PATH}"))?
+        .to_rgba8();
+    let colors = BlockColors::from_atlas(atlas.as_raw(), atlas.width(), atlas.height());
+    vox::load(path, &colors, &PaletteMapping::default())
+}
+
+/// Opens the world directory, creating it with the requested seed and
+/// generator if it doesn't exist yet.
+fn open_world(options: &Options) -> anyhow::Result<(ChunkManager, LevelInfo)> {
+    let world_save = WorldSave::new(&options.world)?;
+
+    let level_info = match world_save.load_level_info()? {
+        Some(level_info) => {
+            if options.seed.is_some_and(|seed| seed != level_info.seed) {
+                log::warn!("world already exists, keeping its seed {}", level_info.seed);
+            }
+            level_info
+        }
+        None => {
+            let level_info = LevelInfo {
+                seed: options.seed.unwrap_or_else(rand::random),
+                generator: options
+                    .generator
+                    .clone()
+                    .unwrap_or_else(|| "default".to_string()),
+                time: TimeOfDay::default(),
+            };
+            world_save.save_level_info(&level_info)?;
+            level_info
+        }
+    };
+
+    let generator = generator_from_name(&level_info.generator, level_info.seed)
+        .with_context(|| format!("unknown world generator {}", level_info.generator))?;
+    log::info!(
+        "opened world {:?} with seed {} and {} generator",
+        options.world,
+        level_info.seed,
+        level_info.generator
+    );
+
+    let mut chunk_manager = ChunkManager::new(10, generator);
+    chunk_manager.world_save = Some(Arc::new(world_save));
+    chunk_manager.load_area.shape = options.load_shape;
+    chunk_manager.lod_levels = options.lod_levels;
+    chunk_manager.time_of_day = level_info.time;
+
+    Ok((chunk_manager, level_info))
+}
+
 pub struct App {
+    options: Options,
     state: Option<State>,
     last_time: Instant,
 }
 
 impl App {
-    pub fn new() -> Self {
+    pub fn new(options: Options) -> Self {
         Self {
+            options,
             state: None,
             last_time: Instant::now(),
         }
@@ -536,7 +852,7 @@ impl ApplicationHandler<State> for App {
         let window = Arc::new(event_loop.create_window(window_attributes).unwrap());
         window.set_cursor_grab(CursorGrabMode::Confined).unwrap();
         window.set_cursor_visible(false);
-        self.state = Some(pollster::block_on(State::new(window)).unwrap());
+        self.state = Some(pollster::block_on(State::new(window, &self.options)).unwrap());
     }
 
     fn user_event(&mut self, _event_loop: &ActiveEventLoop, event: State) {
@@ -554,11 +870,8 @@ impl ApplicationHandler<State> for App {
             None => return,
         };
 
-        match event {
-            DeviceEvent::MouseMotion { delta: (dx, dy) } => {
-                state.camera_controller.handle_mouse(dx, dy);
-            }
-            _ => (),
+        if let DeviceEvent::MouseMotion { delta: (dx, dy) } = event {
+            state.camera_controller.handle_mouse(dx, dy);
         }
     }
 
@@ -574,12 +887,19 @@ impl ApplicationHandler<State> for App {
         };
 
         match event {
-            WindowEvent::CloseRequested => event_loop.exit(),
+            WindowEvent::CloseRequested => {
+                state.save();
+                event_loop.exit();
+            }
             WindowEvent::Resized(size) => state.resize(size.width, size.height),
             WindowEvent::RedrawRequested => {
                 let dt = self.last_time.elapsed();
                 self.last_time = Instant::now();
-                state.update(dt);
+                if let Err(e) = state.update(dt) {
+                    log::error!("{:#}", e);
+                    event_loop.exit();
+                    return;
+                }
 
                 match state.render() {
                     Ok(_) => (),
@@ -613,8 +933,13 @@ impl ApplicationHandler<State> for App {
 pub fn run() -> anyhow::Result<()> {
     env_logger::init();
 
+    let options = Options::from_args()?;
+    if set_registry(BlockRegistry::from_file(&options.blocks)?).is_err() {
+        anyhow::bail!("the block registry was already in use");
+    }
+
     let event_loop = EventLoop::with_user_event().build()?;
-    let mut app = App::new();
+    let mut app = App::new(options);
     event_loop.run_app(&mut app)?;
 
     Ok(())
@@ -0,0 +1,367 @@
+use std::collections::BTreeMap;
+
+use voxel_core::chunk::{ChunkMeshData, Vertex};
+
+/// vertices in every page of the vertex arena, 8MiB worth
+const VERTEX_PAGE_SIZE: u32 = 1 << 20;
+/// indices in every page of the index arena, 8MiB worth
+const INDEX_PAGE_SIZE: u32 = 1 << 21;
+/// allocations are rounded up to this many elements, so the range a remeshed
+/// chunk gives back is likely to fit the next mesh and there are fewer tiny gaps
+const GRANULE: u32 = 64;
+
+/// A range of elements in one page of a [`BufferArena`].
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct Allocation {
+    pub page: usize,
+    /// first element of the range
+    pub offset: u32,
+    pub size: u32,
+}
+
+/// The free ranges of a page of `size` elements.
+struct FreeList {
+    size: u32,
+    /// free ranges by their first element, never touching each other
+    free: BTreeMap<u32, u32>,
+}
+
+impl FreeList {
+    fn new(size: u32) -> Self {
+        Self {
+            size,
+            free: BTreeMap::from([(0, size)]),
+        }
+    }
+
+    /// The offset and size of the smallest free range that fits `size` elements.
+    fn best_fit(&self, size: u32) -> Option<(u32, u32)> {
+        self.free
+            .iter()
+            .filter(|&(_, &free)| free >= size)
+            .min_by_key(|&(_, &free)| free)
+            .map(|(&offset, &free)| (offset, free))
+    }
+
+    fn take(&mut self, offset: u32, size: u32) {
+        let free = self.free.remove(&offset).unwrap();
+        if free > size {
+            self.free.insert(offset + size, free - size);
+        }
+    }
+
+    /// Gives a range back, merging it with the free ranges on either side.
+    fn give_back(&mut self, mut offset: u32, mut size: u32) {
+        if let Some((&before, &before_size)) = self.free.range(..offset).next_back()
+            && before + before_size == offset
+        {
+            self.free.remove(&before);
+            offset = before;
+            size += before_size;
+        }
+
+        if let Some(after_size) = self.free.remove(&(offset + size)) {
+            size += after_size;
+        }
+
+        self.free.insert(offset, size);
+    }
+}
+
+struct Page {
+    buffer: wgpu::Buffer,
+    free_list: FreeList,
+}
+
+/// Hands out ranges of one large buffer after another instead of a buffer
+/// per mesh, adding a page whenever none of them has a range that fits.
+pub struct BufferArena {
+    label: &'static str,
+    usage: wgpu::BufferUsages,
+    element_size: u64,
+    page_size: u32,
+    pages: Vec<Page>,
+}
+
+/// How full and how fragmented a [`BufferArena`] is, in bytes.
+#[derive(Copy, Clone, Default, Debug)]
+pub struct ArenaStats {
+    pub pages: usize,
+    pub capacity: u64,
+    pub used: u64,
+    pub free_ranges: usize,
+    pub largest_free: u64,
+}
+
+impl ArenaStats {
+    fn add_page(&mut self, free_list: &FreeList, element_size: u64) {
+        let free = free_list.free.values().sum::<u32>();
+        self.pages += 1;
+        self.capacity += free_list.size as u64 * element_size;
+        self.used += (free_list.size - free) as u64 * element_size;
+        self.free_ranges += free_list.free.len();
+        self.largest_free = self
+            .largest_free
+            .max(free_list.free.values().copied().max().unwrap_or(0) as u64 * element_size);
+    }
+
+    /// The share of free space that is outside the largest free range, 0
+    /// when all of it is in one piece.
+    pub fn fragmentation(&self) -> f32 {
+        let free = self.capacity - self.used;
+        if free == 0 {
+            return 0.0;
+        }
+
+        1.0 - self.largest_free as f32 / free as f32
+    }
+}
+
+impl BufferArena {
+    pub fn new(
+        label: &'static str,
+        usage: wgpu::BufferUsages,
+        element_size: u64,
+        page_size: u32,
+    ) -> Self {
+        Self {
+            label,
+            usage: usage | wgpu::BufferUsages::COPY_DST,
+            element_size,
+            page_size,
+            pages: Vec::new(),
+        }
+    }
+
+    pub fn buffer(&self, page: usize) -> &wgpu::Buffer {
+        &self.pages[page].buffer
+    }
+
+    /// Reserves room for `count` elements, preferring the tightest fitting
+    /// free range of any page.
+    pub fn allocate(&mut self, device: &wgpu::Device, count: u32) -> Allocation {
+        let size = count.max(1).div_ceil(GRANULE) * GRANULE;
+
+        let best = self
+            .pages
+            .iter()
+            .enumerate()
+            .filter_map(|(page, pages)| Some((page, pages.free_list.best_fit(size)?)))
+            .min_by_key(|&(_, (_, free))| free);
+
+        let (page, offset) = match best {
+            Some((page, (offset, _))) => (page, offset),
+            None => {
+                // meshes bigger than a page get a page of their own
+                let page_size = size.max(self.page_size);
+                self.pages.push(Page {
+                    buffer: device.create_buffer(&wgpu::BufferDescriptor {
+                        label: Some(self.label),
+                        size: page_size as u64 * self.element_size,
+                        usage: self.usage,
+                        mapped_at_creation: false,
+                    }),
+                    free_list: FreeList::new(page_size),
+                });
+                (self.pages.len() - 1, 0)
+            }
+        };
+
+        self.pages[page].free_list.take(offset, size);
+        Allocation { page, offset, size }
+    }
+
+    pub fn free(&mut self, allocation: Allocation) {
+        self.pages[allocation.page]
+            .free_list
+            .give_back(allocation.offset, allocation.size);
+    }
+
+    /// Writes `data` to the start of the allocation, which has to fit it.
+    pub fn write(&self, queue: &wgpu::Queue, allocation: Allocation, data: &[u8]) {
+        if data.is_empty() {
+            return;
+        }
+
+        debug_assert!(data.len() as u64 <= allocation.size as u64 * self.element_size);
+        queue.write_buffer(
+            &self.pages[allocation.page].buffer,
+            allocation.offset as u64 * self.element_size,
+            data,
+        );
+    }
+
+    pub fn stats(&self) -> ArenaStats {
+        let mut stats = ArenaStats::default();
+        for page in &self.pages {
+            stats.add_page(&page.free_list, self.element_size);
+        }
+        stats
+    }
+}
+
+/// Where a mesh lives in the [`MeshAllocator`].
+#[derive(Copy, Clone, Debug)]
+pub struct MeshAllocation {
+    pub vertices: Allocation,
+    /// the opaque indices followed by the translucent ones
+    pub indices: Allocation,
+}
+
+/// Keeps every chunk mesh in a few shared vertex and index buffers.
+pub struct MeshAllocator {
+    pub vertices: BufferArena,
+    pub indices: BufferArena,
+}
+
+impl Default for MeshAllocator {
+    fn default() -> Self {
+        Self {
+            vertices: BufferArena::new(
+                "Chunk Vertex Arena",
+                wgpu::BufferUsages::VERTEX,
+                std::mem::size_of::<Vertex>() as u64,
+                VERTEX_PAGE_SIZE,
+            ),
+            indices: BufferArena::new(
+                "Chunk Index Arena",
+                wgpu::BufferUsages::INDEX,
+                4,
+                INDEX_PAGE_SIZE,
+            ),
+        }
+    }
+}
+
+impl MeshAllocator {
+    /// Finds room for the mesh and uploads it. The indices stay relative to
+    /// the mesh's first vertex, so they have to be drawn with the vertex
+    /// allocation's offset as the base vertex.
+    pub fn upload(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        mesh_data: &ChunkMeshData,
+    ) -> MeshAllocation {
+        let index_count = mesh_data.indices.len() + mesh_data.translucent_indices.len();
+        let allocation = MeshAllocation {
+            vertices: self
+                .vertices
+                .allocate(device, mesh_data.vertices.len() as u32),
+            indices: self.indices.allocate(device, index_count as u32),
+        };
+
+        self.vertices.write(
+            queue,
+            allocation.vertices,
+            bytemuck::cast_slice(&mesh_data.vertices),
+        );
+        self.indices.write(
+            queue,
+            allocation.indices,
+            bytemuck::cast_slice(&mesh_data.indices),
+        );
+        if !mesh_data.translucent_indices.is_empty() {
+            self.write_translucent(
+                queue,
+                allocation,
+                mesh_data.indices.len() as u32,
+                &mesh_data.translucent_indices,
+            );
+        }
+
+        allocation
+    }
+
+    /// Overwrites the translucent indices, which start after the
+    /// `opaque_count` opaque ones.
+    pub fn write_translucent(
+        &self,
+        queue: &wgpu::Queue,
+        allocation: MeshAllocation,
+        opaque_count: u32,
+        translucent_indices: &[u32],
+    ) {
+        let range = Allocation {
+            offset: allocation.indices.offset + opaque_count,
+            size: allocation.indices.size - opaque_count,
+            ..allocation.indices
+        };
+        self.indices
+            .write(queue, range, bytemuck::cast_slice(translucent_indices));
+    }
+
+    pub fn free(&mut self, allocation: MeshAllocation) {
+        self.vertices.free(allocation.vertices);
+        self.indices.free(allocation.indices);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ranges(free_list: &FreeList) -> Vec<(u32, u32)> {
+        free_list
+            .free
+            .iter()
+            .map(|(&offset, &size)| (offset, size))
+            .collect()
+    }
+
+    #[test]
+    fn freed_ranges_merge_with_both_neighbors() {
+        let mut free_list = FreeList::new(300);
+        for offset in [0, 100, 200] {
+            assert_eq!(free_list.best_fit(100), Some((offset, 300 - offset)));
+            free_list.take(offset, 100);
+        }
+        assert_eq!(free_list.best_fit(1), None);
+
+        free_list.give_back(0, 100);
+        free_list.give_back(200, 100);
+        assert_eq!(ranges(&free_list), [(0, 100), (200, 100)]);
+
+        free_list.give_back(100, 100);
+        assert_eq!(ranges(&free_list), [(0, 300)]);
+    }
+
+    #[test]
+    fn the_tightest_range_is_used() {
+        let mut free_list = FreeList::new(1000);
+        free_list.take(0, 1000);
+        free_list.give_back(0, 300);
+        free_list.give_back(400, 100);
+        free_list.give_back(600, 200);
+
+        assert_eq!(free_list.best_fit(100), Some((400, 100)));
+        assert_eq!(free_list.best_fit(150), Some((600, 200)));
+        assert_eq!(free_list.best_fit(250), Some((0, 300)));
+        assert_eq!(free_list.best_fit(301), None);
+
+        free_list.take(600, 150);
+        assert_eq!(ranges(&free_list), [(0, 300), (400, 100), (750, 50)]);
+    }
+
+    #[test]
+    fn fragmentation_is_the_free_space_outside_the_largest_range() {
+        let mut free_list = FreeList::new(1000);
+        let stats = |free_list: &FreeList| {
+            let mut stats = ArenaStats::default();
+            stats.add_page(free_list, 4);
+            stats
+        };
+        assert_eq!(stats(&free_list).fragmentation(), 0.0);
+
+        free_list.take(0, 1000);
+        assert_eq!(stats(&free_list).fragmentation(), 0.0);
+
+        free_list.give_back(0, 300);
+        free_list.give_back(500, 100);
+        let fragmented = stats(&free_list);
+        assert_eq!(fragmented.used, 600 * 4);
+        assert_eq!(fragmented.free_ranges, 2);
+        assert_eq!(fragmented.largest_free, 300 * 4);
+        assert_eq!(fragmented.fragmentation(), 0.25);
+    }
+}
@@ -0,0 +1,86 @@
+use std::path::PathBuf;
+
+use anyhow::{Context, bail};
+use voxel_core::{load_area::LoadShape, lod::MAX_LOD_LEVEL, net::DEFAULT_PORT};
+
+/// Command line options for the windowed app.
+pub struct Options {
+    pub world: PathBuf,
+    /// only used when creating a new world, existing worlds keep their seed
+    pub seed: Option<u32>,
+    pub generator: Option<String>,
+    /// the block pack to play with
+    pub blocks: PathBuf,
+    /// levels of detail drawn beyond the render distance
+    pub lod_levels: u32,
+    /// the shape of the area of chunks loaded at full detail
+    pub load_shape: LoadShape,
+    /// cull chunks on the CPU even when the GPU could do it
+    pub cpu_culling: bool,
+    /// the address of a server to join instead of opening `world`
+    pub connect: Option<String>,
+    /// the name to join servers with
+    pub name: String,
+    /// a schematic to start out with on the clipboard
+    pub schematic: Option<PathBuf>,
+}
+
+impl Options {
+    pub fn from_args() -> anyhow::Result<Self> {
+        let mut options = Self {
+            world: PathBuf::from("world"),
+            seed: None,
+            generator: None,
+            blocks: PathBuf::from("assets/blocks.toml"),
+            lod_levels: 2,
+            load_shape: LoadShape::default(),
+            cpu_culling: false,
+            connect: None,
+            name: "player".to_string(),
+            schematic: None,
+        };
+
+        let mut args = std::env::args().skip(1);
+        while let Some(arg) = args.next() {
+            let mut value = || {
+                args.next()
+                    .with_context(|| format!("missing value for {arg}"))
+            };
+            match arg.as_str() {
+                "--world" => options.world = PathBuf::from(value()?),
+                "--seed" => options.seed = Some(value()?.parse().context("invalid seed")?),
+                "--generator" => options.generator = Some(value()?),
+                "--blocks" => options.blocks = PathBuf::from(value()?),
+                "--lod-levels" => {
+                    options.lod_levels = value()?.parse().context("invalid lod levels")?;
+                    if options.lod_levels > MAX_LOD_LEVEL {
+                        bail!("at most {MAX_LOD_LEVEL} lod levels are supported");
+                    }
+                }
+                "--load-shape" => {
+                    let name = value()?;
+                    options.load_shape = LoadShape::parse(&name).with_context(|| {
+                        format!(
+                            "invalid load shape {name}, expected cube, sphere or cylinder:<height>"
+                        )
+                    })?;
+                }
+                "--cpu-culling" => options.cpu_culling = true,
+                "--connect" => {
+                    let address = value()?;
+                    // servers listen on the default port unless told otherwise
+                    options.connect = Some(if address.contains(':') {
+                        address
+                    } else {
+                        format!("{address}:{DEFAULT_PORT}")
+                    });
+                }
+                "--name" => options.name = value()?,
+                "--schematic" => options.schematic = Some(PathBuf::from(value()?)),
+                _ => bail!("unknown argument {arg}"),
+            }
+        }
+
+        Ok(options)
+    }
+}
@@ -0,0 +1,110 @@
+// Hides the chunk draws whose bounding box is outside the view frustum or
+// behind what was drawn in the previous frame, by setting their instance
+// count to zero. The draws keep their order, so the batches they're issued
+// in stay the same.
+
+struct Cull {
+	frustum: array<vec4<f32>, 6>,
+	// the view projection of the frame the depth pyramid was built from
+	pyramid_view_proj: mat4x4<f32>,
+	pyramid_size: vec2<f32>,
+	draw_count: u32,
+	// 0 until there is a depth pyramid to test against
+	occlusion: u32,
+	chunk_size: f32,
+};
+
+struct Draw {
+	index_count: u32,
+	instance_count: u32,
+	first_index: u32,
+	base_vertex: i32,
+	first_instance: u32,
+};
+
+@group(0) @binding(0)
+var<uniform> cull: Cull;
+
+// the position of every mesh and the size of its cells in voxels
+@group(0) @binding(1)
+var<storage, read> meshes: array<vec4<i32>>;
+
+@group(0) @binding(2)
+var<storage, read_write> draws: array<Draw>;
+
+@group(0) @binding(3)
+var pyramid: texture_2d<f32>;
+
+fn in_frustum(min_corner: vec3<f32>, max_corner: vec3<f32>) -> bool {
+	for (var i = 0; i < 6; i++) {
+		let plane = cull.frustum[i];
+		let farthest = select(min_corner, max_corner, plane.xyz > vec3<f32>(0.0));
+		if dot(plane.xyz, farthest) < -plane.w {
+			return false;
+		}
+	}
+
+	return true;
+}
+
+fn is_occluded(min_corner: vec3<f32>, max_corner: vec3<f32>) -> bool {
+	var rect_min = vec2<f32>(1.0);
+	var rect_max = vec2<f32>(-1.0);
+	var nearest = 1.0;
+	for (var i = 0u; i < 8u; i++) {
+		let corner = select(
+			min_corner,
+			max_corner,
+			vec3<bool>((i & 1u) != 0u, (i & 2u) != 0u, (i & 4u) != 0u),
+		);
+		let clip = cull.pyramid_view_proj * vec4<f32>(corner, 1.0);
+		// boxes reaching behind the camera cover the whole screen
+		if clip.w <= 0.0 {
+			return false;
+		}
+
+		let ndc = clip.xyz / clip.w;
+		rect_min = min(rect_min, ndc.xy);
+		rect_max = max(rect_max, ndc.xy);
+		nearest = min(nearest, ndc.z);
+	}
+
+	let uv_min = clamp(vec2<f32>(rect_min.x, -rect_max.y) * 0.5 + 0.5, vec2<f32>(0.0), vec2<f32>(1.0));
+	let uv_max = clamp(vec2<f32>(rect_max.x, -rect_min.y) * 0.5 + 0.5, vec2<f32>(0.0), vec2<f32>(1.0));
+	let texel_min = vec2<u32>(uv_min * cull.pyramid_size);
+	let texel_max = vec2<u32>(uv_max * cull.pyramid_size);
+
+	// the level where the box covers at most two texels either way
+	let extent = max(texel_max - texel_min, vec2<u32>(1u));
+	let level = min(firstLeadingBit(max(extent.x, extent.y)) + 1u, textureNumLevels(pyramid) - 1u);
+	let level_max = textureDimensions(pyramid, i32(level)) - 1u;
+	let lo = min(texel_min >> vec2<u32>(level), level_max);
+	let hi = min(texel_max >> vec2<u32>(level), level_max);
+
+	var farthest = 0.0;
+	for (var y = lo.y; y <= hi.y; y++) {
+		for (var x = lo.x; x <= hi.x; x++) {
+			farthest = max(farthest, textureLoad(pyramid, vec2<u32>(x, y), i32(level)).r);
+		}
+	}
+
+	return nearest > farthest;
+}
+
+@compute @workgroup_size(64)
+fn cull_draws(@builtin(global_invocation_id) id: vec3<u32>) {
+	if id.x >= cull.draw_count {
+		return;
+	}
+
+	let mesh = meshes[draws[id.x].first_instance];
+	let min_corner = vec3<f32>(mesh.xyz);
+	let max_corner = min_corner + cull.chunk_size * f32(mesh.w);
+
+	var visible = in_frustum(min_corner, max_corner);
+	if visible && cull.occlusion != 0u {
+		visible = !is_occluded(min_corner, max_corner);
+	}
+
+	draws[id.x].instance_count = select(0u, 1u, visible);
+}
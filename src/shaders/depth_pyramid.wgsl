@@ -0,0 +1,50 @@
+// Builds a hierarchical depth buffer out of the depth buffer of a frame. Every
+// texel of a level holds the farthest depth of the texels it covers in the
+// level below, so a single lookup tells whether anything nearer than that
+// could still be visible.
+
+// bound as a plain float texture, which every backend can load from
+@group(0) @binding(0)
+var depth: texture_2d<f32>;
+
+@group(0) @binding(1)
+var previous: texture_2d<f32>;
+
+@group(0) @binding(2)
+var level: texture_storage_2d<r32float, write>;
+
+@compute @workgroup_size(8, 8)
+fn copy_depth(@builtin(global_invocation_id) id: vec3<u32>) {
+	let size = textureDimensions(level);
+	if id.x >= size.x || id.y >= size.y {
+		return;
+	}
+
+	textureStore(level, id.xy, vec4<f32>(textureLoad(depth, id.xy, 0).r, 0.0, 0.0, 0.0));
+}
+
+@compute @workgroup_size(8, 8)
+fn reduce(@builtin(global_invocation_id) id: vec3<u32>) {
+	let size = textureDimensions(level);
+	if id.x >= size.x || id.y >= size.y {
+		return;
+	}
+
+	// the last texel of a row or column also covers the one left over when
+	// the level below has an odd size
+	let previous_size = textureDimensions(previous);
+	let last = vec2<u32>(
+		select(1u, 2u, id.x == size.x - 1u && (previous_size.x & 1u) == 1u),
+		select(1u, 2u, id.y == size.y - 1u && (previous_size.y & 1u) == 1u),
+	);
+
+	var farthest = 0.0;
+	for (var y = 0u; y <= last.y; y++) {
+		for (var x = 0u; x <= last.x; x++) {
+			let texel = min(id.xy * 2u + vec2<u32>(x, y), previous_size - 1u);
+			farthest = max(farthest, textureLoad(previous, texel, 0).r);
+		}
+	}
+
+	textureStore(level, id.xy, vec4<f32>(farthest, 0.0, 0.0, 0.0));
+}
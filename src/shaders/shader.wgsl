@@ -1,6 +1,6 @@
 struct VertexInput {
 	@location(0) packed_data: u32,
-	@location(1) voxel_pos: vec3<i32>,
+	@location(1) light: u32,
 };
 
 struct Camera {
@@ -11,10 +11,23 @@ struct Camera {
 @group(0) @binding(0)
 var<uniform> camera: Camera;
 
+struct Time {
+	// milliseconds since the app started
+	elapsed: f32,
+	// how bright the sky light is at the time of day, 0..1
+	daylight: f32,
+};
+
 @group(2) @binding(0)
-var<uniform> time: f32;
+var<uniform> time: Time;
+
+// the position of every mesh drawn this frame and the size of its cells in
+// voxels, looked up by the instance index of the draw
+@group(3) @binding(0)
+var<storage, read> meshes: array<vec4<i32>>;
 
-var<push_constant> push: array<i32, 6>;
+// the voxel looked at
+var<push_constant> look_at: vec3<i32>;
 
 const NORMALS: array<vec3<f32>, 6> = array(
 		vec3<f32>( 0.0,  0.0, -1.0), // Front
@@ -25,18 +38,25 @@ const NORMALS: array<vec3<f32>, 6> = array(
 		vec3<f32>( 0.0,  1.0,  0.0), // Top
 );
 
+// brightness of a vertex with 0 to 3 unoccluded neighbors
+const AMBIENT_OCCLUSION: array<f32, 4> = array(0.45, 0.65, 0.82, 1.0);
+
 struct VertexOutput {
     @builtin(position) clip_position: vec4<f32>,
-    @location(0) uv: vec2<f32>,
+	// position within the face in block units, wrapped per block in the fragment shader
+    @location(0) tile_uv: vec2<f32>,
 	@location(1) normal: vec3<f32>,
+	@location(2) @interpolate(flat) uv_index: u32,
 	@location(3) frag_position: vec3<f32>,
-	@location(4) voxel_pos: vec3<i32>,
+	// sky and block light of the voxel in front of the face, 0..15
+	@location(4) light: vec2<f32>,
+	@location(5) ambient_occlusion: f32,
 };
 
 @vertex
 fn vs_main(
     vertex: VertexInput,
-	@builtin(vertex_index) vertex_index: u32,
+	@builtin(instance_index) instance: u32,
 ) -> VertexOutput {
 	let position = vec3<f32>(
 		f32((vertex.packed_data >> 12) & 0x3F),
@@ -50,39 +70,39 @@ fn vs_main(
 		0.0, 0.0, 1.0, 0.0,
 		0.0, 0.0, 0.0, 1.0,
 	);
-	let chunk_pos = vec3<f32>(f32(push[0]), f32(push[1]), f32(push[2]));
+	let mesh = meshes[instance];
+	let chunk_pos = vec3<f32>(mesh.xyz);
 	model[3] = vec4<f32>(chunk_pos, 1.0);
-	let world_position = model * vec4<f32>(position, 1.0);
+	// the surface of fluids sits below the top of the block
+	let lowered = f32((vertex.light >> 8) & 0xF) / 16.0;
+	// level of detail meshes are made of cells covering several voxels
+	let scale = f32(mesh.w);
+	let world_position = model * vec4<f32>((position - vec3<f32>(0.0, lowered, 0.0)) * scale, 1.0);
 
 	let normal_index = (vertex.packed_data >> 18) & 0x07;
 
 	let uv_index = (vertex.packed_data >> 21) & 0xFF;
-	let v_index = vertex_index % 4;
-
-	var uv = vec2<f32>(f32(uv_index % 16) / 16.0, floor(f32(uv_index) / 16.0));
 
-	switch v_index {
-		case 0: {
-			uv.x += 1.0 / 16.0;
-			uv.y += 1.0 / 16.0;
-		}
-		case 1: {
-			uv.y += 1.0 / 16.0;
-		}
-		case 3: {
-			uv.x += 1.0 / 16.0;
-		}
-		default: {
-		}
+	// quads can span several blocks so the texture coordinates come from the
+	// position along the face, oriented the same way for every block
+	var tile_uv: vec2<f32>;
+	switch normal_index {
+		case 0u: { tile_uv = vec2<f32>(-position.x, -position.y); }
+		case 1u: { tile_uv = vec2<f32>( position.x, -position.y); }
+		case 2u: { tile_uv = vec2<f32>( position.z, -position.y); }
+		case 3u: { tile_uv = vec2<f32>(-position.z, -position.y); }
+		case 4u: { tile_uv = vec2<f32>(-position.x,  position.z); }
+		default: { tile_uv = vec2<f32>(-position.x, -position.z); }
 	}
 
-
     var out: VertexOutput;
-    out.uv = uv;
+    out.tile_uv = tile_uv;
+    out.uv_index = uv_index;
     out.clip_position = camera.view_proj * world_position;
     out.frag_position = world_position.xyz;
 	out.normal = NORMALS[normal_index];
-	out.voxel_pos = vertex.voxel_pos;
+	out.light = vec2<f32>(f32((vertex.light >> 4) & 0xF), f32(vertex.light & 0xF));
+	out.ambient_occlusion = AMBIENT_OCCLUSION[(vertex.packed_data >> 29) & 0x03];
     return out;
 }
 
@@ -92,16 +112,41 @@ const LIGHT_RANGE: f32 = 15.0;
 const LINEAR: f32 = 1.0 / LIGHT_RANGE;
 const QUADRATIC: f32 = 1.0 / (LIGHT_RANGE * LIGHT_RANGE);
 
+// how much darker every light level is than the one above it
+const LIGHT_FALLOFF: f32 = 0.8;
+const MIN_BRIGHTNESS: f32 = 0.03;
+
 @group(1) @binding(0)
 var t_atlas: texture_2d<f32>;
 @group(1) @binding(1)
 var s_atlas: sampler;
 
+// texels of cutout blocks below this alpha are fully see through
+const ALPHA_CUTOFF: f32 = 0.5;
+
+// opaque and cutout faces, written to the depth buffer in any order
 @fragment
 fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+	let color = shade(in);
+	if (color.a < ALPHA_CUTOFF) {
+		discard;
+	}
+	return vec4<f32>(color.rgb, 1.0);
+}
+
+// translucent faces, blended over everything drawn before them
+@fragment
+fn fs_translucent(in: VertexOutput) -> @location(0) vec4<f32> {
+	return shade(in);
+}
+
+fn shade(in: VertexOutput) -> vec4<f32> {
 	var result = vec3<f32>(1.0);
-	let look = vec3<i32>(push[3], push[4], push[5]);
-	let color = textureSample(t_atlas, s_atlas, in.uv).xyz;
+	let look = look_at;
+	let tile = vec2<f32>(f32(in.uv_index % 16), f32(in.uv_index / 16));
+	let uv = (tile + fract(in.tile_uv)) / 16.0;
+	let texel = textureSample(t_atlas, s_atlas, uv);
+	let color = texel.rgb;
 	if (BLINN_PHONG) {
 		let ambient = 0.4 * color;
 
@@ -137,10 +182,16 @@ fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
 		}
 	}
 
-	if (in.voxel_pos.x == look.x && in.voxel_pos.y == look.y && in.voxel_pos.z == look.z) {
-		result *= 1.0 + 2.0 * ((sin(time / 500.0) + 1.0) / 2.0);
+	let light_level = max(in.light.x * time.daylight, in.light.y);
+	result *= max(pow(LIGHT_FALLOFF, 15.0 - light_level), MIN_BRIGHTNESS);
+	result *= in.ambient_occlusion;
+
+	// step half a block back from the face to find the voxel it belongs to
+	let voxel_pos = vec3<i32>(floor(in.frag_position - in.normal * 0.5));
+	if (voxel_pos.x == look.x && voxel_pos.y == look.y && voxel_pos.z == look.z) {
+		result *= 1.0 + 2.0 * ((sin(time.elapsed / 500.0) + 1.0) / 2.0);
 		result = clamp(result, vec3<f32>(0.0), vec3<f32>(1.0));
 	}
 
-	return vec4<f32>(result, 1.0);
+	return vec4<f32>(result, texel.a);
 }
@@ -0,0 +1,205 @@
+use enum_iterator::{Sequence, all, cardinality};
+use noise::{Fbm, MultiFractal, NoiseFn, Simplex};
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Sequence)]
+pub enum Biome {
+    Plains,
+    Desert,
+    SnowyPeaks,
+}
+
+/// Describes what the columns of a biome are made of and how tall they get.
+/// Blocks are given by their name in the block registry.
+pub struct BiomeParams {
+    /// the top block of every column
+    pub surface: &'static str,
+    /// the blocks right below the surface, stone is used underneath
+    pub filler: &'static str,
+    pub filler_depth: i32,
+    /// replaces the surface block on columns at least this high
+    pub peak: Option<(&'static str, i32)>,
+    /// lowest possible terrain height
+    pub base_height: f64,
+    /// how much the height noise can raise the terrain above `base_height`
+    pub height_variation: f64,
+    /// the climate (temperature, humidity) this biome is most likely at, both 0..1
+    pub climate: (f64, f64),
+}
+
+impl Biome {
+    pub fn params(&self) -> &'static BiomeParams {
+        match self {
+            Self::Plains => &BiomeParams {
+                surface: "grass",
+                filler: "dirt",
+                filler_depth: 4,
+                peak: None,
+                base_height: 0.0,
+                height_variation: 32.0,
+                climate: (0.5, 0.6),
+            },
+            Self::Desert => &BiomeParams {
+                surface: "sand",
+                filler: "sand",
+                filler_depth: 5,
+                peak: None,
+                base_height: 2.0,
+                height_variation: 14.0,
+                climate: (0.8, 0.2),
+            },
+            Self::SnowyPeaks => &BiomeParams {
+                surface: "snow",
+                filler: "dirt",
+                filler_depth: 2,
+                peak: Some(("ice", 72)),
+                base_height: 20.0,
+                height_variation: 76.0,
+                climate: (0.15, 0.5),
+            },
+        }
+    }
+}
+
+pub const BIOME_COUNT: usize = cardinality::<Biome>();
+
+/// how far apart two climates can be while still blending, smaller values
+/// make for narrower borders between biomes
+const BLEND_WIDTH: f64 = 0.12;
+
+/// Picks biomes from two low frequency noise maps for temperature and humidity.
+pub struct BiomeMap {
+    temperature: Fbm<Simplex>,
+    humidity: Fbm<Simplex>,
+}
+
+impl BiomeMap {
+    pub fn new(seed: u32) -> Self {
+        let climate_noise = |seed| {
+            Fbm::<Simplex>::new(seed)
+                .set_octaves(2)
+                .set_frequency(0.0015)
+                .set_lacunarity(2.0)
+                .set_persistence(0.5)
+        };
+
+        Self {
+            temperature: climate_noise(seed.wrapping_add(1)),
+            humidity: climate_noise(seed.wrapping_add(2)),
+        }
+    }
+
+    /// Returns the (temperature, humidity) of a column, both roughly 0..1.
+    pub fn climate(&self, x: i32, z: i32) -> (f64, f64) {
+        let pos = [x as f64 + 0.5, z as f64 + 0.5];
+        (
+            (self.temperature.get(pos) * 1.5 + 1.0) / 2.0,
+            (self.humidity.get(pos) * 1.5 + 1.0) / 2.0,
+        )
+    }
+
+    /// How strongly each biome applies to a column, in the order of
+    /// [`enum_iterator::all`]. The weights always add up to 1 and change
+    /// smoothly with position, so blending by them avoids cliffs at borders.
+    pub fn weights(&self, x: i32, z: i32) -> [f64; BIOME_COUNT] {
+        let (temperature, humidity) = self.climate(x, z);
+
+        let mut weights = [0.0; BIOME_COUNT];
+        for (weight, biome) in weights.iter_mut().zip(all::<Biome>()) {
+            let (t, h) = biome.params().climate;
+            let distance_squared = (temperature - t).powi(2) + (humidity - h).powi(2);
+            *weight = (-distance_squared / (2.0 * BLEND_WIDTH * BLEND_WIDTH)).exp();
+        }
+
+        let total = weights.iter().sum::<f64>();
+        if total <= f64::EPSILON {
+            // far away from every biome, fall back to whichever is closest
+            let closest = self.closest(temperature, humidity);
+            weights = [0.0; BIOME_COUNT];
+            weights[closest] = 1.0;
+        } else {
+            weights.iter_mut().for_each(|weight| *weight /= total);
+        }
+
+        weights
+    }
+
+    pub fn biome_at(&self, x: i32, z: i32) -> Biome {
+        let (temperature, humidity) = self.climate(x, z);
+        all::<Biome>()
+            .nth(self.closest(temperature, humidity))
+            .unwrap()
+    }
+
+    fn closest(&self, temperature: f64, humidity: f64) -> usize {
+        all::<Biome>()
+            .map(|biome| {
+                let (t, h) = biome.params().climate;
+                (temperature - t).powi(2) + (humidity - h).powi(2)
+            })
+            .enumerate()
+            .min_by(|(_, a), (_, b)| a.total_cmp(b))
+            .map(|(i, _)| i)
+            .unwrap()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::generator::NoiseGenerator;
+
+    #[test]
+    fn weights_add_up_to_one() {
+        let biome_map = BiomeMap::new(7);
+        for x in (-20_000..20_000).step_by(397) {
+            for z in (-20_000..20_000).step_by(401) {
+                let weights = biome_map.weights(x, z);
+                let total = weights.iter().sum::<f64>();
+                assert!((total - 1.0).abs() < 1e-9, "{weights:?} at {x} {z}");
+                assert!(weights.iter().all(|weight| (0.0..=1.0).contains(weight)));
+
+                // the dominant biome is the one weighing the most
+                let heaviest = weights
+                    .iter()
+                    .enumerate()
+                    .max_by(|(_, a), (_, b)| a.total_cmp(b))
+                    .unwrap()
+                    .0;
+                assert_eq!(all::<Biome>().nth(heaviest), Some(biome_map.biome_at(x, z)));
+            }
+        }
+    }
+
+    #[test]
+    fn climates_far_from_every_biome_fall_back_to_the_closest() {
+        let biome_map = BiomeMap::new(0);
+        let closest = biome_map.closest(5.0, -5.0);
+        assert_eq!(all::<Biome>().nth(closest), Some(Biome::Desert));
+        assert_eq!(biome_map.closest(0.5, 0.6), 0);
+    }
+
+    #[test]
+    fn heights_change_smoothly_across_borders() {
+        let generator = NoiseGenerator::new(0);
+        let mut borders = 0;
+        for z in [0, -2000] {
+            for x in -6000..6000 {
+                let (a, b) = (generator.column(x, z), generator.column(x + 1, z));
+                if a.biome == b.biome {
+                    continue;
+                }
+                borders += 1;
+                assert!(
+                    (a.height - b.height).abs() <= 2,
+                    "a cliff from {} to {} between {:?} and {:?} at {x} {z}",
+                    a.height,
+                    b.height,
+                    a.biome,
+                    b.biome
+                );
+            }
+        }
+        // make sure the line actually crosses borders between biomes
+        assert!(borders > 10, "only {borders} borders");
+    }
+}
@@ -0,0 +1,534 @@
+use std::{collections::HashMap, path::Path, sync::OnceLock};
+
+use ahash::AHashMap;
+use anyhow::{Context, bail};
+use serde::Deserialize;
+
+use crate::light::MAX_LIGHT;
+
+/// The block pack shipped with the game, used unless another one is installed
+/// with [`set_registry`].
+pub const DEFAULT_BLOCKS: &str = include_str!("../../assets/blocks.toml");
+
+static REGISTRY: OnceLock<BlockRegistry> = OnceLock::new();
+
+/// The furthest a fluid can be made to flow, chosen so every level still gets
+/// its own surface height.
+pub const MAX_FLOW_DISTANCE: u8 = 12;
+
+/// A block type, identified by its position in the [`BlockRegistry`].
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug, Default)]
+pub struct Block(u16);
+
+impl Block {
+    pub const AIR: Self = Self(0);
+
+    /// A block with any id, even one past the end of the registry, which
+    /// [`Block::info`] can't look up. Everything outside of tests goes
+    /// through [`Block::named`] or the registry instead.
+    #[cfg(test)]
+    pub(crate) fn from_id(id: u16) -> Self {
+        Self(id)
+    }
+
+    pub fn id(&self) -> u16 {
+        self.0
+    }
+
+    /// Looks up a block of the active registry by name. Falls back to air
+    /// when there's no such block, so world generation keeps working with
+    /// packs that leave out blocks it uses; look blocks up once up front
+    /// rather than in hot loops.
+    pub fn named(name: &str) -> Self {
+        registry().get(name).unwrap_or_else(|| {
+            log::warn!("there's no block named \"{}\", using air instead", name);
+            Self::AIR
+        })
+    }
+
+    pub fn is_air(&self) -> bool {
+        *self == Self::AIR
+    }
+
+    pub fn info(&self) -> &'static BlockInfo {
+        &registry().blocks[self.0 as usize]
+    }
+
+    pub fn name(&self) -> &'static str {
+        &self.info().name
+    }
+
+    /// The atlas tile shown on the given face, in the order of `FACE_NORMALS`.
+    pub fn texture(&self, face: usize) -> u8 {
+        self.info().textures[face]
+    }
+
+    /// Whether light is stopped by this block. Light passing through a
+    /// non-air block that doesn't stop it still dims by one level per block.
+    pub fn blocks_light(&self) -> bool {
+        !self.info().transparent
+    }
+
+    /// The block light level this block gives off.
+    pub fn light_emission(&self) -> u8 {
+        self.info().light_emission
+    }
+
+    pub fn render_layer(&self) -> RenderLayer {
+        self.info().render_layer
+    }
+
+    pub fn fluid(&self) -> Option<&'static FluidInfo> {
+        self.info().fluid.as_ref()
+    }
+
+    /// Whether both blocks are levels of the same fluid.
+    pub fn is_same_fluid(&self, other: Block) -> bool {
+        self.fluid()
+            .zip(other.fluid())
+            .is_some_and(|(a, b)| a.source == b.source)
+    }
+
+    /// Whether a face of this block is hidden by `neighbor` in front of it.
+    /// Opaque blocks hide every face, translucent ones only hide faces of the
+    /// same block or fluid so the inside of a body of ice or water isn't drawn.
+    pub fn is_face_hidden_by(&self, neighbor: Block) -> bool {
+        if neighbor.is_air() {
+            return false;
+        }
+
+        match neighbor.render_layer() {
+            RenderLayer::Opaque => true,
+            RenderLayer::Cutout => false,
+            RenderLayer::Translucent => neighbor == *self || neighbor.is_same_fluid(*self),
+        }
+    }
+}
+
+/// How the faces of a block are drawn.
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Default, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RenderLayer {
+    /// fully covers whatever is behind it
+    #[default]
+    Opaque,
+    /// either fully opaque or fully see through per pixel, like leaves
+    Cutout,
+    /// blended with whatever is behind it, like ice or water
+    Translucent,
+}
+
+/// Everything known about a block type.
+#[derive(Clone, Debug)]
+pub struct BlockInfo {
+    pub name: String,
+    /// atlas tile of every face, in the order of `FACE_NORMALS`
+    pub textures: [u8; 6],
+    /// whether things collide with the block
+    pub solid: bool,
+    /// whether light passes through the block
+    pub transparent: bool,
+    pub light_emission: u8,
+    /// how long the block takes to break
+    pub hardness: f32,
+    pub render_layer: RenderLayer,
+    pub fluid: Option<FluidInfo>,
+}
+
+/// One level of a fluid. Every fluid is registered as a source block followed
+/// by one block per level it can flow, named like `water:3`.
+#[derive(Copy, Clone, Debug)]
+pub struct FluidInfo {
+    /// the block of this fluid that never dries up
+    pub source: Block,
+    /// 0 for the source, going up by one for every block the fluid flowed
+    /// sideways, fluid falling down is reset to 1
+    pub level: u8,
+    /// the highest level the fluid flows to
+    pub flow_distance: u8,
+    /// ticks between two steps of the fluid flowing, see
+    /// [`FLUID_TICK`](crate::fluid::FLUID_TICK)
+    pub flow_interval: u32,
+}
+
+impl FluidInfo {
+    pub fn is_source(&self) -> bool {
+        self.level == 0
+    }
+
+    /// The block of the same fluid at another level.
+    pub fn with_level(&self, level: u8) -> Block {
+        debug_assert!(level <= self.flow_distance);
+        Block(self.source.0 + level as u16)
+    }
+}
+
+/// Every block type of the game, in ID order, with air always first.
+#[derive(Debug)]
+pub struct BlockRegistry {
+    blocks: Vec<BlockInfo>,
+    by_name: AHashMap<String, Block>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct BlockPack {
+    textures: HashMap<String, u8>,
+    blocks: Vec<BlockDefinition>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct BlockDefinition {
+    name: String,
+    #[serde(default)]
+    textures: FaceTextures,
+    #[serde(default = "default_solid")]
+    solid: bool,
+    #[serde(default)]
+    transparent: bool,
+    #[serde(default)]
+    light_emission: u8,
+    #[serde(default = "default_hardness")]
+    hardness: f32,
+    #[serde(default)]
+    render_layer: RenderLayer,
+    fluid: Option<FluidDefinition>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct FluidDefinition {
+    flow_distance: u8,
+    flow_interval: u32,
+}
+
+fn default_solid() -> bool {
+    true
+}
+
+fn default_hardness() -> f32 {
+    1.0
+}
+
+#[derive(Deserialize, Default)]
+#[serde(deny_unknown_fields)]
+struct FaceTextures {
+    all: Option<String>,
+    sides: Option<String>,
+    front: Option<String>,
+    back: Option<String>,
+    left: Option<String>,
+    right: Option<String>,
+    bottom: Option<String>,
+    top: Option<String>,
+}
+
+impl FaceTextures {
+    /// The texture name of every face, in the order of `FACE_NORMALS`.
+    fn resolve<'a>(&'a self) -> [Option<&'a String>; 6] {
+        let side =
+            |face: &'a Option<String>| face.as_ref().or(self.sides.as_ref()).or(self.all.as_ref());
+        [
+            side(&self.front),
+            side(&self.back),
+            side(&self.left),
+            side(&self.right),
+            self.bottom.as_ref().or(self.all.as_ref()),
+            self.top.as_ref().or(self.all.as_ref()),
+        ]
+    }
+}
+
+impl BlockRegistry {
+    /// Parses a block pack in the format of `assets/blocks.toml`.
+    pub fn from_toml(source: &str) -> anyhow::Result<Self> {
+        let pack: BlockPack = toml::from_str(source)?;
+
+        let mut registry = Self {
+            blocks: vec![BlockInfo {
+                name: "air".to_string(),
+                textures: [0; 6],
+                solid: false,
+                transparent: true,
+                light_emission: 0,
+                hardness: 0.0,
+                render_layer: RenderLayer::Translucent,
+                fluid: None,
+            }],
+            by_name: AHashMap::from_iter([("air".to_string(), Block::AIR)]),
+        };
+
+        for definition in pack.blocks {
+            if registry.by_name.contains_key(&definition.name) {
+                bail!("block \"{}\" is defined more than once", definition.name);
+            }
+            if definition.light_emission > MAX_LIGHT {
+                bail!(
+                    "block \"{}\" gives off light {}, the most is {}",
+                    definition.name,
+                    definition.light_emission,
+                    MAX_LIGHT
+                );
+            }
+
+            let mut textures = [0; 6];
+            for (texture, name) in textures.iter_mut().zip(definition.textures.resolve()) {
+                let Some(name) = name else {
+                    bail!(
+                        "block \"{}\" is missing a texture for some faces",
+                        definition.name
+                    );
+                };
+                *texture = *pack.textures.get(name).with_context(|| {
+                    format!(
+                        "block \"{}\" uses unknown texture \"{}\"",
+                        definition.name, name
+                    )
+                })?;
+            }
+
+            let source = registry.next_block()?;
+            let fluid = match &definition.fluid {
+                Some(fluid) => {
+                    if !(1..=MAX_FLOW_DISTANCE).contains(&fluid.flow_distance) {
+                        bail!(
+                            "fluid \"{}\" flows {} blocks, it has to be between 1 and {}",
+                            definition.name,
+                            fluid.flow_distance,
+                            MAX_FLOW_DISTANCE
+                        );
+                    }
+                    if fluid.flow_interval == 0 {
+                        bail!(
+                            "fluid \"{}\" has to flow at least 1 tick apart",
+                            definition.name
+                        );
+                    }
+                    Some(FluidInfo {
+                        source,
+                        level: 0,
+                        flow_distance: fluid.flow_distance,
+                        flow_interval: fluid.flow_interval,
+                    })
+                }
+                None => None,
+            };
+
+            let info = BlockInfo {
+                name: definition.name,
+                textures,
+                solid: definition.solid,
+                transparent: definition.transparent,
+                light_emission: definition.light_emission,
+                hardness: definition.hardness,
+                render_layer: definition.render_layer,
+                fluid,
+            };
+            registry.by_name.insert(info.name.clone(), source);
+            registry.blocks.push(info.clone());
+
+            // the flowing levels follow right after the source
+            if let Some(fluid) = fluid {
+                for level in 1..=fluid.flow_distance {
+                    let block = registry.next_block()?;
+                    let name = format!("{}:{}", info.name, level);
+                    if registry.by_name.contains_key(&name) {
+                        bail!("block \"{}\" is defined more than once", name);
+                    }
+                    registry.by_name.insert(name.clone(), block);
+                    registry.blocks.push(BlockInfo {
+                        name,
+                        fluid: Some(FluidInfo { level, ..fluid }),
+                        ..info.clone()
+                    });
+                }
+            }
+        }
+
+        Ok(registry)
+    }
+
+    /// The ID the next registered block gets.
+    fn next_block(&self) -> anyhow::Result<Block> {
+        Ok(Block(
+            u16::try_from(self.blocks.len()).context("too many blocks in the pack")?,
+        ))
+    }
+
+    pub fn from_file(path: impl AsRef<Path>) -> anyhow::Result<Self> {
+        let path = path.as_ref();
+        let source = std::fs::read_to_string(path)
+            .with_context(|| format!("unable to read {}", path.display()))?;
+        Self::from_toml(&source).with_context(|| format!("invalid block pack {}", path.display()))
+    }
+
+    pub fn get(&self, name: &str) -> Option<Block> {
+        self.by_name.get(name).copied()
+    }
+
+    /// The number of block types, including air.
+    pub fn len(&self) -> usize {
+        self.blocks.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.blocks.is_empty()
+    }
+
+    /// Every block type in ID order, starting with air.
+    pub fn blocks(&self) -> impl Iterator<Item = Block> + use<> {
+        (0..self.blocks.len() as u16).map(Block)
+    }
+}
+
+/// The active block registry, which is the default pack unless
+/// [`set_registry`] was called before the first block was looked up.
+pub fn registry() -> &'static BlockRegistry {
+    REGISTRY.get_or_init(|| {
+        BlockRegistry::from_toml(DEFAULT_BLOCKS).expect("the default block pack is valid")
+    })
+}
+
+/// Installs the block registry for the rest of the program. Block IDs have
+/// to stay the same once chunks exist, so this fails by handing the registry
+/// back if one is already in use.
+pub fn set_registry(registry: BlockRegistry) -> Result<(), BlockRegistry> {
+    REGISTRY.set(registry)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TEXTURES: &str = "[textures]\na = 1\nb = 2\nc = 3\n";
+
+    fn pack(blocks: &str) -> anyhow::Result<BlockRegistry> {
+        BlockRegistry::from_toml(&format!("{TEXTURES}{blocks}"))
+    }
+
+    #[test]
+    fn faces_use_the_most_specific_texture() {
+        let registry = pack(
+            r#"
+            [[blocks]]
+            name = "first"
+            textures = { all = "a", sides = "b", front = "c" }
+
+            [[blocks]]
+            name = "second"
+            textures = { sides = "a", top = "b", bottom = "c" }
+            light_emission = 15
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(registry.len(), 3);
+        assert_eq!(registry.get("air"), Some(Block::AIR));
+        let first = registry.get("first").unwrap();
+        let second = registry.get("second").unwrap();
+        assert_eq!((first.id(), second.id()), (1, 2));
+        assert_eq!(registry.blocks[1].textures, [3, 2, 2, 2, 1, 1]);
+        assert_eq!(registry.blocks[2].textures, [1, 1, 1, 1, 3, 2]);
+        assert!(registry.blocks[1].solid);
+        assert_eq!(registry.blocks[2].light_emission, 15);
+        assert_eq!(registry.get("third"), None);
+    }
+
+    #[test]
+    fn broken_packs_are_refused() {
+        let broken = [
+            // the same name twice, including the built in air
+            "[[blocks]]\nname = \"x\"\ntextures = { all = \"a\" }\n\
+             [[blocks]]\nname = \"x\"\ntextures = { all = \"b\" }",
+            "[[blocks]]\nname = \"air\"\ntextures = { all = \"a\" }",
+            "[[blocks]]\nname = \"x\"\ntextures = { all = \"d\" }",
+            // no texture for the top and bottom
+            "[[blocks]]\nname = \"x\"\ntextures = { sides = \"a\" }",
+            "[[blocks]]\nname = \"x\"",
+            "[[blocks]]\ntextures = { all = \"a\" }",
+            "[[blocks]]\nname = \"x\"\ntextures = { all = \"a\" }\nlight_emission = 16",
+            "[[blocks]]\nname = \"x\"\ntextures = { all = \"a\" }\nglowing = true",
+            "[[blocks]]\nname = \"x\"\ntextures = { al = \"a\" }",
+        ];
+        for blocks in broken {
+            assert!(pack(blocks).is_err(), "{blocks}");
+        }
+    }
+
+    #[test]
+    fn the_default_pack_is_valid() {
+        let registry = BlockRegistry::from_toml(DEFAULT_BLOCKS).unwrap();
+        for name in ["grass", "stone", "log", "leaves", "lamp"] {
+            assert!(registry.get(name).is_some(), "{name}");
+        }
+    }
+
+    #[test]
+    fn translucent_blocks_only_hide_their_own_kind() {
+        let stone = Block::named("stone");
+        let leaves = Block::named("leaves");
+        let ice = Block::named("ice");
+
+        assert!(stone.is_face_hidden_by(stone));
+        assert!(ice.is_face_hidden_by(stone));
+        assert!(!stone.is_face_hidden_by(Block::AIR));
+        assert!(!stone.is_face_hidden_by(leaves));
+        assert!(!leaves.is_face_hidden_by(leaves));
+        assert!(!stone.is_face_hidden_by(ice));
+        assert!(ice.is_face_hidden_by(ice));
+    }
+
+    #[test]
+    fn fluids_get_a_block_per_level() {
+        let registry = pack(
+            r#"
+            [[blocks]]
+            name = "goo"
+            textures = { all = "a" }
+            fluid = { flow_distance = 3, flow_interval = 2 }
+
+            [[blocks]]
+            name = "after"
+            textures = { all = "b" }
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(registry.len(), 6);
+        let source = registry.get("goo").unwrap();
+        for level in 0..=3 {
+            let name = if level == 0 {
+                "goo".to_string()
+            } else {
+                format!("goo:{level}")
+            };
+            let block = registry.get(&name).unwrap();
+            assert_eq!(block.id(), source.id() + level as u16);
+
+            let fluid = registry.blocks[block.id() as usize].fluid.unwrap();
+            assert_eq!(fluid.level, level);
+            assert_eq!(fluid.source, source);
+            assert_eq!(fluid.with_level(level), block);
+        }
+        assert_eq!(registry.get("goo:4"), None);
+        assert_eq!(registry.get("after").unwrap().id(), 5);
+
+        for fluid in [
+            "{ flow_distance = 0, flow_interval = 2 }",
+            "{ flow_distance = 13, flow_interval = 2 }",
+            "{ flow_distance = 3, flow_interval = 0 }",
+            "{ flow_distance = 3 }",
+        ] {
+            let blocks = format!(
+                "[[blocks]]\nname = \"goo\"\ntextures = {{ all = \"a\" }}\nfluid = {fluid}"
+            );
+            assert!(pack(&blocks).is_err(), "{fluid}");
+        }
+        // a level clashing with a block of the same name
+        let clash = "[[blocks]]\nname = \"goo:1\"\ntextures = { all = \"a\" }\n\
+                     [[blocks]]\nname = \"goo\"\ntextures = { all = \"a\" }\n\
+                     fluid = { flow_distance = 3, flow_interval = 2 }";
+        assert!(pack(clash).is_err());
+    }
+}
@@ -0,0 +1,274 @@
+use crate::{block::Block, chunk::CHUNK_SIZE};
+
+const VOLUME: usize = CHUNK_SIZE * CHUNK_SIZE * CHUNK_SIZE;
+
+/// The blocks of a chunk, indexed the same as [`Chunk::block_index`](crate::chunk::Chunk::block_index).
+///
+/// Most chunks are either all air, all stone, or made of a handful of
+/// different blocks, so rather than storing every block in full this keeps a
+/// palette of the blocks used and packs each voxel into as few bits as it
+/// takes to index into it.
+#[derive(Clone, Debug)]
+pub enum BlockStorage {
+    /// every voxel holds the same block
+    Uniform(Block),
+    Paletted(PalettedBlocks),
+}
+
+impl Default for BlockStorage {
+    fn default() -> Self {
+        Self::Uniform(Block::AIR)
+    }
+}
+
+impl BlockStorage {
+    pub fn get(&self, index: usize) -> Block {
+        match self {
+            Self::Uniform(block) => *block,
+            Self::Paletted(blocks) => blocks.palette[blocks.palette_index(index)],
+        }
+    }
+
+    /// Replaces the block at `index`, returning whether it changed.
+    pub fn set(&mut self, index: usize, block: Block) -> bool {
+        let blocks = match self {
+            Self::Uniform(current) if *current == block => return false,
+            Self::Uniform(current) => {
+                *self = Self::Paletted(PalettedBlocks::filled(*current));
+                let Self::Paletted(blocks) = self else {
+                    unreachable!()
+                };
+                blocks
+            }
+            Self::Paletted(blocks) => blocks,
+        };
+
+        if !blocks.set(index, block) {
+            return false;
+        }
+
+        // go back to the fast path once the last different block is gone
+        if let Some(block) = blocks.uniform() {
+            *self = Self::Uniform(block);
+        }
+
+        true
+    }
+
+    /// Sets every voxel to the same block.
+    pub fn fill(&mut self, block: Block) {
+        *self = Self::Uniform(block);
+    }
+
+    /// The block every voxel holds, if they're all the same.
+    pub fn uniform(&self) -> Option<Block> {
+        match self {
+            Self::Uniform(block) => Some(*block),
+            Self::Paletted(blocks) => blocks.uniform(),
+        }
+    }
+
+    /// Every block in index order.
+    pub fn iter(&self) -> impl Iterator<Item = Block> + '_ {
+        (0..VOLUME).map(|index| self.get(index))
+    }
+
+    /// Bytes used by the storage, including its heap allocations.
+    pub fn memory_usage(&self) -> usize {
+        std::mem::size_of::<Self>()
+            + match self {
+                Self::Uniform(_) => 0,
+                Self::Paletted(blocks) => blocks.heap_size(),
+            }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct PalettedBlocks {
+    palette: Vec<Block>,
+    /// how many voxels use each palette entry, entries nothing uses anymore
+    /// are reused for the next new block
+    counts: Vec<u16>,
+    bits_per_index: usize,
+    /// palette indices packed into words, never straddling two words
+    words: Vec<u64>,
+}
+
+impl PalettedBlocks {
+    fn filled(block: Block) -> Self {
+        Self {
+            palette: vec![block],
+            counts: vec![VOLUME as u16],
+            bits_per_index: 1,
+            words: vec![0; VOLUME.div_ceil(64)],
+        }
+    }
+
+    fn indices_per_word(&self) -> usize {
+        64 / self.bits_per_index
+    }
+
+    fn palette_index(&self, index: usize) -> usize {
+        let per_word = self.indices_per_word();
+        let shift = (index % per_word) * self.bits_per_index;
+        let mask = (1 << self.bits_per_index) - 1;
+        ((self.words[index / per_word] >> shift) & mask) as usize
+    }
+
+    fn set_palette_index(&mut self, index: usize, palette_index: usize) {
+        let per_word = self.indices_per_word();
+        let shift = (index % per_word) * self.bits_per_index;
+        let mask = ((1 << self.bits_per_index) - 1) << shift;
+        let word = &mut self.words[index / per_word];
+        *word = (*word & !mask) | ((palette_index as u64) << shift);
+    }
+
+    fn set(&mut self, index: usize, block: Block) -> bool {
+        let old = self.palette_index(index);
+        if self.palette[old] == block {
+            return false;
+        }
+
+        self.counts[old] -= 1;
+        let new = self.find_or_insert(block);
+        self.counts[new] += 1;
+        self.set_palette_index(index, new);
+
+        true
+    }
+
+    fn find_or_insert(&mut self, block: Block) -> usize {
+        if let Some(i) = self
+            .palette
+            .iter()
+            .zip(&self.counts)
+            .position(|(&entry, &count)| entry == block && count > 0)
+        {
+            return i;
+        }
+
+        if let Some(i) = self.counts.iter().position(|&count| count == 0) {
+            self.palette[i] = block;
+            return i;
+        }
+
+        if self.palette.len() == 1 << self.bits_per_index {
+            self.repack(self.bits_per_index + 1);
+        }
+        self.palette.push(block);
+        self.counts.push(0);
+        self.palette.len() - 1
+    }
+
+    /// Moves every index over to a new width, needed once the palette outgrows
+    /// what the current width can address.
+    fn repack(&mut self, bits_per_index: usize) {
+        let old = std::mem::replace(
+            self,
+            Self {
+                palette: Vec::new(),
+                counts: Vec::new(),
+                bits_per_index,
+                words: vec![0; VOLUME.div_ceil(64 / bits_per_index)],
+            },
+        );
+
+        for index in 0..VOLUME {
+            self.set_palette_index(index, old.palette_index(index));
+        }
+        self.palette = old.palette;
+        self.counts = old.counts;
+    }
+
+    fn uniform(&self) -> Option<Block> {
+        self.counts
+            .iter()
+            .position(|&count| count as usize == VOLUME)
+            .map(|i| self.palette[i])
+    }
+
+    fn heap_size(&self) -> usize {
+        self.palette.capacity() * std::mem::size_of::<Block>()
+            + self.counts.capacity() * std::mem::size_of::<u16>()
+            + self.words.capacity() * std::mem::size_of::<u64>()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bits_per_index(storage: &BlockStorage) -> usize {
+        match storage {
+            BlockStorage::Uniform(_) => 0,
+            BlockStorage::Paletted(blocks) => blocks.bits_per_index,
+        }
+    }
+
+    #[test]
+    fn uniform_chunks_use_no_heap() {
+        let mut storage = BlockStorage::default();
+        assert_eq!(storage.memory_usage(), std::mem::size_of::<BlockStorage>());
+
+        let stone = Block::from_id(3);
+        assert!(storage.set(100, stone));
+        assert!(storage.memory_usage() > std::mem::size_of::<BlockStorage>());
+        // taking the only different block out goes back to a uniform chunk
+        assert!(storage.set(100, Block::AIR));
+        assert_eq!(storage.uniform(), Some(Block::AIR));
+        assert_eq!(storage.memory_usage(), std::mem::size_of::<BlockStorage>());
+    }
+
+    #[test]
+    fn two_block_types_take_a_few_kib() {
+        let mut storage = BlockStorage::default();
+        storage.fill(Block::from_id(3));
+        for index in 0..VOLUME / 2 {
+            storage.set(index, Block::AIR);
+        }
+
+        let dense = VOLUME * std::mem::size_of::<Block>();
+        assert_eq!(bits_per_index(&storage), 1);
+        assert!(storage.memory_usage() <= 5 * 1024);
+        assert!(storage.memory_usage() * 10 < dense);
+    }
+
+    #[test]
+    fn blocks_survive_every_repack() {
+        let mut storage = BlockStorage::default();
+        let mut expected = vec![Block::AIR; VOLUME];
+        let mut width = bits_per_index(&storage);
+
+        for id in 1..2000 {
+            let index = (id as usize * 7919) % VOLUME;
+            assert!(storage.set(index, Block::from_id(id)));
+            expected[index] = Block::from_id(id);
+
+            if bits_per_index(&storage) != width {
+                assert_eq!(bits_per_index(&storage), width + 1);
+                width += 1;
+                assert!(
+                    storage.iter().eq(expected.iter().copied()),
+                    "at {width} bits"
+                );
+            }
+        }
+        assert_eq!(width, 11);
+        assert!(storage.iter().eq(expected.iter().copied()));
+
+        // freed palette entries are reused without growing any further, 7919
+        // is odd so every id lands on its own index
+        for id in 1..1000 {
+            let index = (id * 7919) % VOLUME;
+            storage.set(index, Block::AIR);
+            expected[index] = Block::AIR;
+        }
+        for id in 3000..3999 {
+            let index = (id as usize * 7919) % VOLUME;
+            storage.set(index, Block::from_id(id));
+            expected[index] = Block::from_id(id);
+        }
+        assert_eq!(bits_per_index(&storage), 11);
+        assert!(storage.iter().eq(expected.iter().copied()));
+    }
+}
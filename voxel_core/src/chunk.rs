@@ -0,0 +1,782 @@
+use glam::{IVec3, UVec3, Vec3};
+
+use crate::{
+    block::{Block, RenderLayer},
+    block_storage::BlockStorage,
+    frustum::Aabb,
+    light::{LightChannel, LightStorage, MAX_LIGHT},
+    visibility::FaceConnections,
+};
+
+pub const CHUNK_SIZE: usize = 32;
+
+const CUBE_VERTICES: [UVec3; 8] = [
+    UVec3::new(0, 0, 0),
+    UVec3::new(1, 0, 0),
+    UVec3::new(1, 1, 0),
+    UVec3::new(0, 1, 0),
+    UVec3::new(0, 0, 1),
+    UVec3::new(1, 0, 1),
+    UVec3::new(1, 1, 1),
+    UVec3::new(0, 1, 1),
+];
+
+const FACE_INDICES: [[usize; 4]; 6] = [
+    [0, 1, 2, 3], // Front
+    [5, 4, 7, 6], // Back
+    [4, 0, 3, 7], // Left
+    [1, 5, 6, 2], // Right
+    [4, 5, 1, 0], // Bottom
+    [3, 2, 6, 7], // Top
+];
+
+pub(crate) const FACE_NORMALS: [IVec3; 6] = [
+    IVec3::NEG_Z, // Front
+    IVec3::Z,     // Back
+    IVec3::NEG_X, // Left
+    IVec3::X,     // Right
+    IVec3::NEG_Y, // Bottom
+    IVec3::Y,     // Top
+];
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct Vertex {
+    /// mapped to 0b0aauuuuuuuunnnxxxxxxyyyyyyzzzzzz, where a is the ambient
+    /// occlusion of the corner from 0 (darkest) to 3 (unoccluded)
+    pub packed_data: u32,
+    /// light of the voxel in front of the face, mapped to 0bhhhhssssbbbb,
+    /// where h lowers the vertex by that many sixteenths of a block to draw
+    /// the surface of fluids
+    pub light: u32,
+}
+
+impl Vertex {
+    /// Position of the vertex relative to the chunk.
+    pub fn position(&self) -> UVec3 {
+        UVec3::new(
+            (self.packed_data >> 12) & 0x3F,
+            (self.packed_data >> 6) & 0x3F,
+            self.packed_data & 0x3F,
+        )
+    }
+
+    /// The face the vertex belongs to, in the order of `FACE_NORMALS`.
+    pub fn face(&self) -> usize {
+        ((self.packed_data >> 18) & 0x07) as usize
+    }
+
+    /// The atlas tile of the face.
+    pub fn texture(&self) -> u8 {
+        (self.packed_data >> 21) as u8
+    }
+
+    /// How many sixteenths of a block the vertex is lowered by.
+    pub fn lowered(&self) -> u32 {
+        (self.light >> 8) & 0xF
+    }
+}
+
+/// Everything the vertices of a face share, faces can only be merged into
+/// one quad if this is equal.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+struct FaceData {
+    packed_data: u32,
+    light: u32,
+    /// ambient occlusion of every corner, in the order of `FACE_INDICES`
+    ambient_occlusion: [u32; 4],
+    translucent: bool,
+    /// how far the top corners of the face are lowered, see [`Vertex::light`]
+    lowered: u32,
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Default)]
+pub enum MeshingMode {
+    /// one quad per visible block face
+    Naive,
+    /// merges neighboring faces with the same texture into larger quads
+    #[default]
+    Greedy,
+}
+
+#[derive(Clone)]
+pub struct Chunk {
+    pub position: IVec3,
+    pub world_position: IVec3,
+    pub blocks: BlockStorage,
+    /// sky light in the high 4 bits and block light in the low 4 bits of
+    /// every voxel, indexed the same as `blocks`
+    pub light: LightStorage,
+    pub is_empty: bool,
+    /// set when the chunk has been edited since it was last saved
+    pub modified: bool,
+    pub bounding_box: Aabb,
+    /// which faces see each other through the chunk, worked out whenever it
+    /// is meshed and `None` until then
+    pub face_connections: Option<FaceConnections>,
+}
+
+impl Chunk {
+    pub fn new(position: IVec3) -> Self {
+        let world_position = position * CHUNK_SIZE as i32;
+
+        Self {
+            position,
+            world_position,
+            blocks: BlockStorage::default(),
+            light: LightStorage::default(),
+            is_empty: true,
+            modified: false,
+            bounding_box: Aabb::new(
+                world_position.as_vec3(),
+                world_position.as_vec3() + CHUNK_SIZE as f32,
+            ),
+            face_connections: None,
+        }
+    }
+
+    pub fn block_index(x: usize, y: usize, z: usize) -> usize {
+        CHUNK_SIZE * CHUNK_SIZE * z + CHUNK_SIZE * y + x
+    }
+
+    pub fn set_block(&mut self, position: IVec3, block: Block) -> bool {
+        let index = CHUNK_SIZE * CHUNK_SIZE * position.z as usize
+            + CHUNK_SIZE * position.y as usize
+            + position.x as usize;
+
+        if self.blocks.set(index, block) {
+            self.modified = true;
+            self.is_empty = self.blocks.uniform() == Some(Block::AIR);
+            return true;
+        }
+
+        false
+    }
+
+    /// Looks up a voxel relative to this chunk, reaching into the neighboring chunk
+    /// when the position is just outside of it. Returns the chunk holding the voxel
+    /// and its index, or `None` if that neighbor hasn't been loaded yet.
+    fn get_voxel_or_neighbor<'a>(
+        &'a self,
+        neighbors: &[Option<&'a Chunk>; 6],
+        pos: IVec3,
+    ) -> Option<(&'a Chunk, usize)> {
+        let size = CHUNK_SIZE as i32;
+        let face = if pos.z < 0 {
+            0
+        } else if pos.z >= size {
+            1
+        } else if pos.x < 0 {
+            2
+        } else if pos.x >= size {
+            3
+        } else if pos.y < 0 {
+            4
+        } else if pos.y >= size {
+            5
+        } else {
+            return Some((
+                self,
+                CHUNK_SIZE * CHUNK_SIZE * pos.z as usize
+                    + CHUNK_SIZE * pos.y as usize
+                    + pos.x as usize,
+            ));
+        };
+
+        // the voxel we wanna check is in a neighboring chunk
+        neighbors[face].map(|chunk| {
+            let pos = pos.rem_euclid(IVec3::splat(size));
+            (
+                chunk,
+                CHUNK_SIZE * CHUNK_SIZE * pos.z as usize
+                    + CHUNK_SIZE * pos.y as usize
+                    + pos.x as usize,
+            )
+        })
+    }
+
+    /// Works out whether the face of a block pointing towards `front` is visible,
+    /// returning the face data to draw it with if so. Faces bordering a neighbor
+    /// that hasn't loaded yet are drawn fully sky lit and flag `missing_neighbors`.
+    fn face_data(
+        &self,
+        neighbors: &[Option<&Chunk>; 6],
+        block: Block,
+        face: usize,
+        front: IVec3,
+        missing_neighbors: &mut bool,
+    ) -> Option<FaceData> {
+        let light = match self.get_voxel_or_neighbor(neighbors, front) {
+            Some((chunk, index)) if !block.is_face_hidden_by(chunk.blocks.get(index)) => {
+                chunk.light.get(index)
+            }
+            Some(_) => return None,
+            // the neighbor hasnt loaded yet so we'll need to remesh this later
+            None => {
+                *missing_neighbors = true;
+                LightChannel::Sky.pack(0, MAX_LIGHT)
+            }
+        };
+
+        // every corner is darkened by the two voxels beside it and the one
+        // diagonal to it in the layer in front of the face
+        let d = FACE_NORMALS[face].abs().max_position();
+        let u = (d + 1) % 3;
+        let v = (d + 2) % 3;
+
+        let mut ambient_occlusion = [0; 4];
+        for (ao, corner) in ambient_occlusion.iter_mut().zip(FACE_INDICES[face]) {
+            let direction = CUBE_VERTICES[corner].as_ivec3() * 2 - 1;
+            let mut side1 = front;
+            side1[u] += direction[u];
+            let mut side2 = front;
+            side2[v] += direction[v];
+            let mut diagonal = side1;
+            diagonal[v] += direction[v];
+
+            let side1 = self.is_occluder(neighbors, side1);
+            let side2 = self.is_occluder(neighbors, side2);
+            let diagonal = self.is_occluder(neighbors, diagonal);
+
+            *ao = if side1 && side2 {
+                0
+            } else {
+                3 - (side1 as u32 + side2 as u32 + diagonal as u32)
+            };
+        }
+
+        Some(FaceData {
+            packed_data: ((block.texture(face) as u32) << 21) | ((face as u32) << 18),
+            light: light as u32,
+            ambient_occlusion,
+            translucent: block.render_layer() == RenderLayer::Translucent,
+            lowered: self.fluid_lowering(neighbors, block, front - FACE_NORMALS[face]),
+        })
+    }
+
+    /// How far the surface of a fluid voxel sits below the top of the block,
+    /// in sixteenths of a block. It gets lower the further the fluid flowed,
+    /// unless more of the same fluid above fills it up to the top.
+    fn fluid_lowering(&self, neighbors: &[Option<&Chunk>; 6], block: Block, pos: IVec3) -> u32 {
+        let Some(fluid) = block.fluid() else {
+            return 0;
+        };
+
+        let filled = self
+            .get_voxel_or_neighbor(neighbors, pos + IVec3::Y)
+            .is_some_and(|(chunk, index)| chunk.blocks.get(index).is_same_fluid(block));
+        if filled {
+            return 0;
+        }
+
+        2 + fluid.level as u32 * 12 / fluid.flow_distance as u32
+    }
+
+    /// Whether a voxel casts ambient occlusion onto the faces next to it,
+    /// which every block but translucent ones do. Voxels in chunks that only
+    /// touch this one along an edge aren't available while meshing, so those
+    /// are treated as open.
+    fn is_occluder(&self, neighbors: &[Option<&Chunk>; 6], pos: IVec3) -> bool {
+        let outside = (0..3)
+            .filter(|&axis| pos[axis] < 0 || pos[axis] >= CHUNK_SIZE as i32)
+            .count();
+
+        outside <= 1
+            && self
+                .get_voxel_or_neighbor(neighbors, pos)
+                .is_some_and(|(chunk, index)| {
+                    let block = chunk.blocks.get(index);
+                    !block.is_air() && block.render_layer() != RenderLayer::Translucent
+                })
+    }
+
+    pub fn generate_mesh(
+        &self,
+        neighbors: [Option<&Chunk>; 6],
+        mode: MeshingMode,
+    ) -> (Option<ChunkMeshData>, bool) {
+        if self.is_empty {
+            return (None, false);
+        }
+
+        match mode {
+            MeshingMode::Naive => self.generate_naive_mesh(&neighbors),
+            MeshingMode::Greedy => self.generate_greedy_mesh(&neighbors),
+        }
+    }
+
+    /// Emits one quad for every exposed block face.
+    fn generate_naive_mesh(
+        &self,
+        neighbors: &[Option<&Chunk>; 6],
+    ) -> (Option<ChunkMeshData>, bool) {
+        let mut mesh_data = ChunkMeshData::default();
+        let mut missing_neighors = false;
+
+        for x in 0..CHUNK_SIZE {
+            for y in 0..CHUNK_SIZE {
+                for z in 0..CHUNK_SIZE {
+                    let block = self
+                        .blocks
+                        .get(CHUNK_SIZE * CHUNK_SIZE * z + CHUNK_SIZE * y + x);
+                    if block.is_air() {
+                        continue;
+                    }
+
+                    let position = IVec3::new(x as i32, y as i32, z as i32);
+
+                    for (face, &normal) in FACE_NORMALS.iter().enumerate() {
+                        if let Some(face_data) = self.face_data(
+                            neighbors,
+                            block,
+                            face,
+                            position + normal,
+                            &mut missing_neighors,
+                        ) {
+                            mesh_data.push_quad(face, position.as_uvec3(), UVec3::ONE, face_data);
+                        }
+                    }
+                }
+            }
+        }
+
+        (Some(mesh_data), missing_neighors)
+    }
+
+    /// Sweeps every slice of the chunk once per face direction, merging runs of
+    /// coplanar faces that share a texture into as few rectangles as possible.
+    fn generate_greedy_mesh(
+        &self,
+        neighbors: &[Option<&Chunk>; 6],
+    ) -> (Option<ChunkMeshData>, bool) {
+        let mut mesh_data = ChunkMeshData::default();
+        let mut missing_neighors = false;
+
+        // the face data of every visible face in the current slice, indexed by [v][u]
+        let mut mask = [None::<FaceData>; CHUNK_SIZE * CHUNK_SIZE];
+
+        for (face, &normal) in FACE_NORMALS.iter().enumerate() {
+            let d = normal.abs().max_position();
+            let u = (d + 1) % 3;
+            let v = (d + 2) % 3;
+
+            for slice in 0..CHUNK_SIZE {
+                for j in 0..CHUNK_SIZE {
+                    for i in 0..CHUNK_SIZE {
+                        let mut position = IVec3::ZERO;
+                        position[d] = slice as i32;
+                        position[u] = i as i32;
+                        position[v] = j as i32;
+
+                        mask[CHUNK_SIZE * j + i] = None;
+
+                        let block = self.blocks.get(
+                            CHUNK_SIZE * CHUNK_SIZE * position.z as usize
+                                + CHUNK_SIZE * position.y as usize
+                                + position.x as usize,
+                        );
+                        if block.is_air() {
+                            continue;
+                        }
+
+                        mask[CHUNK_SIZE * j + i] = self.face_data(
+                            neighbors,
+                            block,
+                            face,
+                            position + normal,
+                            &mut missing_neighors,
+                        );
+                    }
+                }
+
+                for j in 0..CHUNK_SIZE {
+                    let mut i = 0;
+                    while i < CHUNK_SIZE {
+                        let Some(face_data) = mask[CHUNK_SIZE * j + i] else {
+                            i += 1;
+                            continue;
+                        };
+
+                        let mut width = 1;
+                        while i + width < CHUNK_SIZE
+                            && mask[CHUNK_SIZE * j + i + width] == Some(face_data)
+                        {
+                            width += 1;
+                        }
+
+                        let mut height = 1;
+                        'grow: while j + height < CHUNK_SIZE {
+                            for k in i..i + width {
+                                if mask[CHUNK_SIZE * (j + height) + k] != Some(face_data) {
+                                    break 'grow;
+                                }
+                            }
+                            height += 1;
+                        }
+
+                        for row in j..j + height {
+                            mask[CHUNK_SIZE * row + i..CHUNK_SIZE * row + i + width].fill(None);
+                        }
+
+                        let mut origin = UVec3::ZERO;
+                        origin[d] = slice as u32;
+                        origin[u] = i as u32;
+                        origin[v] = j as u32;
+
+                        let mut size = UVec3::ONE;
+                        size[u] = width as u32;
+                        size[v] = height as u32;
+
+                        mesh_data.push_quad(face, origin, size, face_data);
+
+                        i += width;
+                    }
+                }
+            }
+        }
+
+        (Some(mesh_data), missing_neighors)
+    }
+}
+
+impl Chunk {
+    pub fn world_to_chunk_pos(world_position: IVec3) -> IVec3 {
+        (world_position.as_vec3() / CHUNK_SIZE as f32)
+            .floor()
+            .as_ivec3()
+    }
+
+    pub fn world_to_local_pos(world_position: IVec3) -> IVec3 {
+        let mut pos = world_position % CHUNK_SIZE as i32;
+        if pos.x < 0 {
+            pos.x += CHUNK_SIZE as i32;
+        }
+        if pos.y < 0 {
+            pos.y += CHUNK_SIZE as i32;
+        }
+        if pos.z < 0 {
+            pos.z += CHUNK_SIZE as i32;
+        }
+
+        pos
+    }
+}
+
+#[derive(Default)]
+pub struct ChunkMeshData {
+    pub vertices: Vec<Vertex>,
+    /// triangles of opaque and cutout faces, which can be drawn in any order
+    pub indices: Vec<u32>,
+    /// triangles of translucent faces, which have to be drawn after the
+    /// opaque ones from back to front, see [`ChunkMeshData::sort_translucent`]
+    pub translucent_indices: Vec<u32>,
+}
+
+impl ChunkMeshData {
+    pub fn is_empty(&self) -> bool {
+        self.vertices.is_empty() || (self.indices.is_empty() && self.translucent_indices.is_empty())
+    }
+
+    /// Orders the translucent quads from the furthest to the closest to `eye`,
+    /// given relative to the chunk, so blending them layers correctly.
+    pub fn sort_translucent(&mut self, eye: Vec3) {
+        let mut quads = self
+            .translucent_indices
+            .chunks_exact(6)
+            .map(|quad| {
+                let base = *quad.iter().min().unwrap() as usize;
+                let center = self.vertices[base..base + 4]
+                    .iter()
+                    .map(|vertex| vertex.position().as_vec3())
+                    .sum::<Vec3>()
+                    / 4.0;
+                (
+                    center.distance_squared(eye),
+                    <[u32; 6]>::try_from(quad).unwrap(),
+                )
+            })
+            .collect::<Vec<_>>();
+
+        quads.sort_by(|a, b| b.0.total_cmp(&a.0));
+
+        self.translucent_indices.clear();
+        self.translucent_indices
+            .extend(quads.into_iter().flat_map(|(_, quad)| quad));
+    }
+
+    /// Adds a quad covering `size` voxels starting at `origin` on the given face.
+    /// `face_data` holds the packed normal, texture and light shared by all 4 vertices.
+    fn push_quad(&mut self, face: usize, origin: UVec3, size: UVec3, face_data: FaceData) {
+        let base_index = self.vertices.len() as u32;
+
+        for (corner, ao) in FACE_INDICES[face].iter().zip(face_data.ambient_occlusion) {
+            let position = origin + CUBE_VERTICES[*corner] * size;
+            let packed_position = (position.x << 12) | (position.y << 6) | position.z;
+            let lowered = if CUBE_VERTICES[*corner].y == 1 {
+                face_data.lowered
+            } else {
+                0
+            };
+
+            self.vertices.push(Vertex {
+                packed_data: (ao << 29) | face_data.packed_data | packed_position,
+                light: (lowered << 8) | face_data.light,
+            });
+        }
+
+        let indices = if face_data.translucent {
+            &mut self.translucent_indices
+        } else {
+            &mut self.indices
+        };
+
+        // split the quad along the diagonal between the brighter pair of corners,
+        // otherwise the occlusion gets smeared unevenly across the two triangles
+        let [ao0, ao1, ao2, ao3] = face_data.ambient_occlusion;
+        if ao1 + ao3 > ao0 + ao2 {
+            indices.extend_from_slice(&[
+                base_index + 1,
+                base_index + 2,
+                base_index + 3,
+                base_index + 1,
+                base_index + 3,
+                base_index,
+            ]);
+        } else {
+            indices.extend_from_slice(&[
+                base_index,
+                base_index + 1,
+                base_index + 2,
+                base_index,
+                base_index + 2,
+                base_index + 3,
+            ]);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+    use crate::generator::{NoiseGenerator, WorldGenerator};
+
+    /// What one block face was drawn with.
+    #[derive(PartialEq, Debug)]
+    struct DrawnFace {
+        texture: u32,
+        light: u32,
+        ambient_occlusion: [u32; 4],
+    }
+
+    /// Splits every quad of a mesh back into the block faces it covers, keyed
+    /// by face direction, layer and position within the layer.
+    fn rasterize(mesh: &ChunkMeshData) -> HashMap<(usize, u32, u32, u32), DrawnFace> {
+        let mut faces = HashMap::new();
+        for quad in mesh.vertices.chunks_exact(4) {
+            let data = quad[0].packed_data;
+            let face = (data >> 18 & 0b111) as usize;
+            let d = FACE_NORMALS[face].abs().max_position();
+            let u = (d + 1) % 3;
+            let v = (d + 2) % 3;
+
+            let corners = quad.iter().map(|vertex| {
+                let data = vertex.packed_data;
+                UVec3::new(data >> 12 & 63, data >> 6 & 63, data & 63)
+            });
+            let min = corners.clone().reduce(UVec3::min).unwrap();
+            let max = corners.reduce(UVec3::max).unwrap();
+            assert_eq!(min[d], max[d], "quads are flat");
+
+            for j in min[v]..max[v] {
+                for i in min[u]..max[u] {
+                    let drawn = DrawnFace {
+                        texture: data >> 21 & 0xFF,
+                        light: quad[0].light,
+                        ambient_occlusion: [0, 1, 2, 3].map(|k| quad[k].packed_data >> 29),
+                    };
+                    let previous = faces.insert((face, min[d], i, j), drawn);
+                    assert!(previous.is_none(), "quads overlap");
+                }
+            }
+        }
+
+        faces
+    }
+
+    /// A floor with patches of grass and dirt, a few log pillars casting
+    /// occlusion and a lamp on it.
+    fn mixed_chunk() -> Chunk {
+        let mut chunk = Chunk::new(IVec3::ZERO);
+        for x in 0..CHUNK_SIZE as i32 {
+            for z in 0..CHUNK_SIZE as i32 {
+                for y in 0..3 {
+                    chunk.set_block(IVec3::new(x, y, z), Block::named("stone"));
+                }
+                let top = if (x / 5 + z / 7) % 3 == 0 {
+                    Block::named("dirt")
+                } else {
+                    Block::named("grass")
+                };
+                chunk.set_block(IVec3::new(x, 3, z), top);
+            }
+        }
+        for (x, z) in [(4, 4), (5, 4), (12, 20), (25, 9), (0, 31)] {
+            for y in 4..4 + x % 3 + 1 {
+                chunk.set_block(IVec3::new(x, y, z), Block::named("log"));
+            }
+        }
+        chunk.set_block(IVec3::new(16, 4, 16), Block::named("lamp"));
+        chunk.compute_initial_light(true);
+        chunk
+    }
+
+    fn terrain_chunk() -> Chunk {
+        let mut chunk = Chunk::new(IVec3::ZERO);
+        NoiseGenerator::new(0).generate(&mut chunk);
+        chunk
+    }
+
+    fn meshes(chunk: &Chunk, neighbors: [Option<&Chunk>; 6]) -> (ChunkMeshData, ChunkMeshData) {
+        let (naive, naive_missing) = chunk.generate_mesh(neighbors, MeshingMode::Naive);
+        let (greedy, greedy_missing) = chunk.generate_mesh(neighbors, MeshingMode::Greedy);
+        assert_eq!(naive_missing, greedy_missing);
+        (naive.unwrap(), greedy.unwrap())
+    }
+
+    #[test]
+    fn greedy_meshes_cover_the_same_faces_as_naive_ones() {
+        let air = Chunk::new(IVec3::Y);
+        let neighbors = [None, None, None, None, None, Some(&air)];
+
+        let (naive, greedy) = meshes(&mixed_chunk(), neighbors);
+        let (naive, greedy) = (rasterize(&naive), rasterize(&greedy));
+        // make sure the chunk has the faces that are easy to get wrong
+        assert!(naive.values().any(|face| face.light & 0xF > 0));
+        assert!(naive.values().any(|face| face.ambient_occlusion != [3; 4]));
+        assert_eq!(naive, greedy);
+
+        let (naive, greedy) = meshes(&terrain_chunk(), neighbors);
+        assert_eq!(rasterize(&naive), rasterize(&greedy));
+    }
+
+    #[test]
+    fn faces_against_missing_neighbors_are_kept() {
+        let chunk = mixed_chunk();
+        let (naive, greedy) = meshes(&chunk, [None; 6]);
+        // the sides of the floor face the missing neighbors
+        let sides = rasterize(&greedy)
+            .keys()
+            .filter(|(face, ..)| *face < 4)
+            .count();
+        assert!(sides >= 4 * 4 * CHUNK_SIZE);
+        assert_eq!(rasterize(&naive), rasterize(&greedy));
+        assert!(chunk.generate_mesh([None; 6], MeshingMode::Greedy).1);
+    }
+
+    #[test]
+    fn greedy_meshes_have_fewer_vertices() {
+        let air = Chunk::new(IVec3::Y);
+        let neighbors = [None, None, None, None, None, Some(&air)];
+
+        let (naive, greedy) = meshes(&terrain_chunk(), neighbors);
+        let (naive, greedy) = (naive.vertices.len(), greedy.vertices.len());
+        let reduction = naive as f32 / greedy as f32;
+        // ambient occlusion and the inner faces of leaves keep a lot of faces apart
+        assert!(
+            reduction >= 2.0,
+            "{naive} naive vertices against {greedy} greedy ones is only {reduction:.1}x fewer"
+        );
+
+        // a flat slab merges into one quad per side
+        let mut slab = Chunk::new(IVec3::ZERO);
+        for x in 0..CHUNK_SIZE as i32 {
+            for z in 0..CHUNK_SIZE as i32 {
+                slab.set_block(IVec3::new(x, 0, z), Block::named("stone"));
+            }
+        }
+        let (_, greedy) = meshes(&slab, [None; 6]);
+        assert_eq!(greedy.vertices.len(), 6 * 4);
+    }
+
+    #[test]
+    fn corners_next_to_walls_are_occluded() {
+        let mut chunk = Chunk::new(IVec3::ZERO);
+        for x in 0..3 {
+            for z in 0..3 {
+                chunk.set_block(IVec3::new(x, 0, z), Block::named("stone"));
+            }
+        }
+        chunk.set_block(IVec3::new(1, 1, 2), Block::named("stone"));
+        chunk.compute_initial_light(true);
+
+        let faces = rasterize(
+            &chunk
+                .generate_mesh([None; 6], MeshingMode::Naive)
+                .0
+                .unwrap(),
+        );
+        // the top of the floor, keyed by y, z and x, the two corners touching
+        // the wall behind it are darker
+        assert_eq!(faces[&(5, 1, 1, 1)].ambient_occlusion, [3, 3, 2, 2]);
+        assert_eq!(faces[&(5, 1, 0, 1)].ambient_occlusion, [3; 4]);
+    }
+
+    #[test]
+    fn quads_are_split_between_the_brighter_corners() {
+        let mut mesh = ChunkMeshData::default();
+        let face_data = |ambient_occlusion| FaceData {
+            packed_data: 0,
+            light: 0,
+            ambient_occlusion,
+            translucent: false,
+            lowered: 0,
+        };
+        mesh.push_quad(5, UVec3::ZERO, UVec3::ONE, face_data([0, 3, 3, 3]));
+        mesh.push_quad(5, UVec3::X, UVec3::ONE, face_data([3, 0, 3, 3]));
+
+        // both triangles share the diagonal, which has to leave out the dark corner
+        assert_eq!(mesh.indices[..6], [1, 2, 3, 1, 3, 0]);
+        assert_eq!(mesh.indices[6..], [4, 5, 6, 4, 6, 7]);
+    }
+
+    #[test]
+    fn translucent_quads_are_sorted_back_to_front() {
+        let mut mesh = ChunkMeshData::default();
+        let face_data = FaceData {
+            packed_data: 0,
+            light: 0,
+            ambient_occlusion: [3; 4],
+            translucent: true,
+            lowered: 0,
+        };
+        for z in [4, 12, 0] {
+            mesh.push_quad(5, UVec3::new(0, 0, z), UVec3::ONE, face_data);
+        }
+        assert!(mesh.indices.is_empty());
+
+        let closest_z = |mesh: &ChunkMeshData| {
+            mesh.translucent_indices
+                .chunks_exact(6)
+                .map(|quad| mesh.vertices[quad[0] as usize].position().z)
+                .collect::<Vec<_>>()
+        };
+        mesh.sort_translucent(Vec3::new(0.0, 0.0, 20.0));
+        assert_eq!(
+            closest_z(&mesh)
+                .iter()
+                .map(|z| z / 4 * 4)
+                .collect::<Vec<_>>(),
+            [0, 4, 12]
+        );
+        mesh.sort_translucent(Vec3::new(0.0, 0.0, -5.0));
+        assert_eq!(
+            closest_z(&mesh)
+                .iter()
+                .map(|z| z / 4 * 4)
+                .collect::<Vec<_>>(),
+            [12, 4, 0]
+        );
+    }
+}
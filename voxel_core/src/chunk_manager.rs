@@ -0,0 +1,914 @@
+use std::{collections::VecDeque, sync::Arc};
+
+use ahash::{AHashMap, AHashSet};
+use glam::{IVec3, Vec3};
+
+use crate::{
+    block::Block,
+    chunk::{CHUNK_SIZE, Chunk, ChunkMeshData, MeshingMode},
+    fluid::FluidTicks,
+    frustum::Frustum,
+    generator::WorldGenerator,
+    jobs::{CancelToken, JobSystem},
+    light::{LightChannel, LightUpdates},
+    load_area::{LoadArea, LoadShape},
+    load_queue::{LoadQueue, LoadView},
+    lod::{self, LodNode},
+    time::TimeOfDay,
+    visibility::{self, FaceConnections},
+    world_save::{WorldSave, decode_chunk},
+};
+
+/// What part a [`ChunkManager`] plays when the world is shared over the
+/// network.
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Default)]
+pub enum WorldRole {
+    /// generates, simulates and meshes the world on its own
+    #[default]
+    Standalone,
+    /// generates and simulates the world for the clients connected to it,
+    /// recording every changed block to send out and never meshing
+    Server,
+    /// meshes the chunks a server sends, generating and simulating nothing
+    /// itself
+    Client,
+}
+
+pub struct ChunkManager {
+    pub chunk_map: AHashMap<IVec3, Chunk>,
+    pub chunk_data_load_queue: LoadQueue,
+    pub chunk_mesh_load_queue: LoadQueue,
+    pub chunk_mesh_reload_queue: AHashSet<IVec3>,
+    pub chunk_neighbor_loaded_queue: AHashSet<IVec3>,
+    pub chunks_with_missing_neighbors: AHashSet<IVec3>,
+    /// the chunks kept loaded, around the chunk last passed to
+    /// [`ChunkManager::update_around`]
+    pub load_area: LoadArea,
+    pub meshing_mode: MeshingMode,
+    pub generator: Arc<dyn WorldGenerator>,
+    pub world_save: Option<Arc<WorldSave>>,
+    pub fluid_ticks: FluidTicks,
+    pub time_of_day: TimeOfDay,
+    /// how many levels of detail are drawn beyond the render distance, up to
+    /// [`MAX_LOD_LEVEL`](crate::lod::MAX_LOD_LEVEL)
+    pub lod_levels: u32,
+    /// every level of detail node currently needed, built or not
+    pub lod_nodes: AHashSet<LodNode>,
+    pub lod_load_queue: VecDeque<LodNode>,
+    pub role: WorldRole,
+    /// every block changed since the server last took them, only recorded
+    /// by a [`WorldRole::Server`]
+    pub changed_blocks: Vec<(IVec3, Block)>,
+    /// chunks from the server that couldn't be decoded since the client last
+    /// took them, only recorded by a [`WorldRole::Client`]
+    pub failed_chunks: Vec<IVec3>,
+    jobs: JobSystem<JobResult>,
+    /// chunks being generated, loaded or decoded by the workers, with the id
+    /// of the latest job
+    data_jobs: AHashMap<IVec3, (u64, CancelToken)>,
+    next_data_job: u64,
+    /// blocks changed in chunks that are still being loaded, which are set
+    /// once the chunk is there
+    pending_blocks: AHashMap<IVec3, Vec<(IVec3, Block)>>,
+    /// chunks being meshed by the workers, with the id of the latest job
+    mesh_jobs: AHashMap<IVec3, (u64, CancelToken)>,
+    next_mesh_job: u64,
+    lod_jobs: AHashMap<LodNode, CancelToken>,
+}
+
+/// the most jobs of every kind handed to the workers at once, the rest wait
+/// in the queues where they can still be reordered or dropped
+const MAX_DATA_JOBS: usize = 32;
+const MAX_MESH_JOBS: usize = 32;
+const MAX_LOD_JOBS: usize = 8;
+
+/// What the workers send back.
+enum JobResult {
+    ChunkData {
+        id: u64,
+        chunk: Box<Chunk>,
+    },
+    /// a chunk received from the server that couldn't be decoded
+    ChunkFailed {
+        position: IVec3,
+        id: u64,
+    },
+    ChunkMesh {
+        position: IVec3,
+        id: u64,
+        mesh: Option<ChunkMeshData>,
+        missing_neighbors: bool,
+        connections: FaceConnections,
+    },
+    Lod(LodNode, Option<ChunkMeshData>),
+}
+
+/// Meshes finished by the workers, see [`ChunkManager::update_jobs`].
+#[derive(Default)]
+pub struct FinishedMeshes {
+    pub chunks: Vec<(IVec3, Option<ChunkMeshData>)>,
+    pub lods: Vec<(LodNode, Option<ChunkMeshData>)>,
+}
+
+fn load_or_generate_chunk(
+    generator: &dyn WorldGenerator,
+    world_save: Option<&WorldSave>,
+    position: IVec3,
+) -> Chunk {
+    if let Some(world_save) = world_save {
+        match world_save.load_chunk(position) {
+            Ok(Some(chunk)) => return chunk,
+            Ok(None) => (),
+            Err(e) => log::error!("unable to load chunk {}: {:#}", position, e),
+        }
+    }
+
+    let mut chunk = Chunk::new(position);
+    generator.generate(&mut chunk);
+    chunk
+}
+
+impl ChunkManager {
+    pub fn new(render_distance: i32, generator: Arc<dyn WorldGenerator>) -> Self {
+        Self {
+            chunk_map: AHashMap::new(),
+            chunk_data_load_queue: LoadQueue::default(),
+            chunk_mesh_load_queue: LoadQueue::default(),
+            chunk_mesh_reload_queue: AHashSet::new(),
+            chunk_neighbor_loaded_queue: AHashSet::new(),
+            chunks_with_missing_neighbors: AHashSet::new(),
+            load_area: LoadArea::new(render_distance, LoadShape::default()),
+            meshing_mode: MeshingMode::default(),
+            generator,
+            world_save: None,
+            fluid_ticks: FluidTicks::default(),
+            time_of_day: TimeOfDay::default(),
+            lod_levels: 0,
+            lod_nodes: AHashSet::new(),
+            lod_load_queue: VecDeque::new(),
+            role: WorldRole::default(),
+            changed_blocks: Vec::new(),
+            failed_chunks: Vec::new(),
+            jobs: JobSystem::new(),
+            data_jobs: AHashMap::new(),
+            next_data_job: 0,
+            pending_blocks: AHashMap::new(),
+            mesh_jobs: AHashMap::new(),
+            next_mesh_job: 0,
+            lod_jobs: AHashMap::new(),
+        }
+    }
+
+    pub fn get_block(&self, pos: IVec3) -> Option<Block> {
+        let chunk_pos = Chunk::world_to_chunk_pos(pos);
+
+        if let Some(chunk) = self.chunk_map.get(&chunk_pos) {
+            let inner_pos = Chunk::world_to_local_pos(pos);
+
+            return Some(chunk.blocks.get(
+                CHUNK_SIZE * CHUNK_SIZE * inner_pos.z as usize
+                    + CHUNK_SIZE * inner_pos.y as usize
+                    + inner_pos.x as usize,
+            ));
+        }
+
+        None
+    }
+
+    pub fn set_block(&mut self, position: IVec3, block: Block) {
+        let chunk_pos = Chunk::world_to_chunk_pos(position);
+        let inner_pos = Chunk::world_to_local_pos(position);
+
+        let Some(chunk) = self.chunk_map.get_mut(&chunk_pos) else {
+            // a client decodes the chunks the server sends on the workers, so
+            // changes to them can arrive before they're done
+            if self.data_jobs.contains_key(&chunk_pos) {
+                self.pending_blocks
+                    .entry(chunk_pos)
+                    .or_default()
+                    .push((position, block));
+            }
+            return;
+        };
+
+        if chunk.set_block(inner_pos, block) {
+            self.queue_remesh_around(chunk_pos);
+            self.update_light(&[(position, block)]);
+            self.blocks_changed(&[(position, block)]);
+        }
+    }
+
+    /// Changes a batch of blocks like [`ChunkManager::set_block`], but writes
+    /// each chunk in one go and relights everything they changed together
+    /// instead of once per block. Blocks in chunks that aren't loaded are
+    /// skipped, returns how many were set.
+    pub fn set_blocks(&mut self, blocks: impl IntoIterator<Item = (IVec3, Block)>) -> usize {
+        let mut by_chunk = AHashMap::<IVec3, Vec<(IVec3, Block)>>::new();
+        for (position, block) in blocks {
+            by_chunk
+                .entry(Chunk::world_to_chunk_pos(position))
+                .or_default()
+                .push((position, block));
+        }
+
+        let mut set = 0;
+        let mut changed = Vec::new();
+        for (chunk_pos, blocks) in by_chunk {
+            let Some(chunk) = self.chunk_map.get_mut(&chunk_pos) else {
+                continue;
+            };
+
+            set += blocks.len();
+            let changed_before = changed.len();
+            for (position, block) in blocks {
+                if chunk.set_block(Chunk::world_to_local_pos(position), block) {
+                    changed.push((position, block));
+                }
+            }
+            if changed.len() > changed_before {
+                self.queue_remesh_around(chunk_pos);
+            }
+        }
+
+        self.update_light(&changed);
+        self.blocks_changed(&changed);
+        set
+    }
+
+    /// Queues a chunk whose blocks changed to be remeshed, along with its
+    /// neighbors whose faces along the border might have changed.
+    fn queue_remesh_around(&mut self, chunk_pos: IVec3) {
+        self.chunk_mesh_reload_queue.insert(chunk_pos);
+        for dir in &[
+            IVec3::NEG_X,
+            IVec3::X,
+            IVec3::NEG_Y,
+            IVec3::Y,
+            IVec3::NEG_Z,
+            IVec3::Z,
+        ] {
+            let neighbor_pos = chunk_pos + *dir;
+            if self.chunk_map.contains_key(&neighbor_pos) {
+                self.chunk_mesh_reload_queue.insert(neighbor_pos);
+            }
+        }
+    }
+
+    /// Lets fluids and the players know about blocks that just changed.
+    fn blocks_changed(&mut self, changed: &[(IVec3, Block)]) {
+        for &(position, block) in changed {
+            match self.role {
+                WorldRole::Standalone => self.schedule_fluid_updates(position),
+                WorldRole::Server => {
+                    self.schedule_fluid_updates(position);
+                    self.changed_blocks.push((position, block));
+                }
+                // fluids flow on the server, which sends the result
+                WorldRole::Client => (),
+            }
+        }
+    }
+
+    /// Relights the surroundings of voxels that just changed to the given
+    /// blocks, spreading the light of all of them in a single pass.
+    fn update_light(&mut self, changed: &[(IVec3, Block)]) {
+        let mut updates = LightUpdates::default();
+
+        for &(position, _) in changed {
+            for channel in LightChannel::ALL {
+                updates.remove(&mut self.chunk_map, position, channel);
+            }
+        }
+
+        for &(position, block) in changed {
+            let emission = block.light_emission();
+            if emission > 0 {
+                updates.set(&mut self.chunk_map, position, LightChannel::Block, emission);
+            }
+
+            // let the light around the voxel flow back in, or up to it if it's solid now
+            for dir in [
+                IVec3::NEG_X,
+                IVec3::X,
+                IVec3::NEG_Y,
+                IVec3::Y,
+                IVec3::NEG_Z,
+                IVec3::Z,
+            ] {
+                for channel in LightChannel::ALL {
+                    updates.add(position + dir, channel);
+                }
+            }
+        }
+
+        updates.propagate(&mut self.chunk_map);
+
+        self.chunk_mesh_reload_queue.extend(
+            updates
+                .touched
+                .into_iter()
+                .filter(|pos| self.chunk_map.contains_key(pos)),
+        );
+    }
+
+    /// Switches the mesher used for chunks and queues every loaded chunk to be remeshed.
+    pub fn set_meshing_mode(&mut self, mode: MeshingMode) {
+        if self.meshing_mode == mode {
+            return;
+        }
+
+        self.meshing_mode = mode;
+        self.chunk_mesh_reload_queue.extend(
+            self.chunk_map
+                .values()
+                .filter(|chunk| !chunk.is_empty)
+                .map(|chunk| chunk.position),
+        );
+    }
+
+    pub fn ray_cast(
+        &self,
+        origin: Vec3,
+        yaw: f32,
+        pitch: f32,
+        max_distance: f32,
+    ) -> (IVec3, IVec3) {
+        let (yaw_sin, yaw_cos) = yaw.sin_cos();
+        let (pitch_sin, pitch_cos) = pitch.sin_cos();
+
+        let direction = Vec3::new(yaw_cos * pitch_cos, pitch_sin, yaw_sin * pitch_cos);
+
+        let step = direction.signum();
+        let t_delta = (1.0 / direction).abs().min(Vec3::splat(f32::MAX));
+
+        let mut voxel = origin.floor().as_ivec3();
+
+        let mut t_max = (voxel.as_vec3() + step.max(Vec3::ZERO) - origin) / direction;
+        t_max = t_max.max(Vec3::splat(0.0));
+
+        let mut traveled = 0.0;
+        let mut normal = IVec3::ZERO;
+
+        while traveled < max_distance {
+            // fluids are looked through, so blocks can be placed in them
+            if let Some(block) = self.get_block(voxel)
+                && !block.is_air()
+                && block.fluid().is_none()
+            {
+                break;
+            }
+
+            if t_max.x < t_max.y {
+                if t_max.x < t_max.z {
+                    voxel.x += step.x as i32;
+                    traveled = t_max.x;
+                    t_max.x += t_delta.x;
+                    normal = IVec3::new(-step.x as i32, 0, 0);
+                } else {
+                    voxel.z += step.z as i32;
+                    traveled = t_max.z;
+                    t_max.z += t_delta.z;
+                    normal = IVec3::new(0, 0, -step.z as i32);
+                }
+            } else if t_max.y < t_max.z {
+                voxel.y += step.y as i32;
+                traveled = t_max.y;
+                t_max.y += t_delta.y;
+                normal = IVec3::new(0, -step.y as i32, 0);
+            } else {
+                voxel.z += step.z as i32;
+                traveled = t_max.z;
+                t_max.z += t_delta.z;
+                normal = IVec3::new(0, 0, -step.z as i32);
+            }
+        }
+
+        (voxel, normal)
+    }
+
+    /// Writes every loaded chunk that has been edited since it was last saved.
+    pub fn save_all(&mut self) -> anyhow::Result<()> {
+        let Some(world_save) = &self.world_save else {
+            return Ok(());
+        };
+
+        world_save.save_chunks(self.chunk_map.values().filter(|chunk| chunk.modified))?;
+        for chunk in self.chunk_map.values_mut() {
+            chunk.modified = false;
+        }
+
+        Ok(())
+    }
+
+    /// Takes in whatever the workers finished since the last call and hands
+    /// them more queued work, returning the meshes that are ready to draw. A
+    /// `None` mesh means the chunk or node no longer has any faces.
+    ///
+    /// Generated chunks are added to the world right away, so they're part of
+    /// the next jobs handed out.
+    pub fn update_jobs(&mut self) -> FinishedMeshes {
+        let mut finished = FinishedMeshes::default();
+
+        let results = self.jobs.finished().collect::<Vec<JobResult>>();
+        for result in results {
+            match result {
+                JobResult::ChunkData { id, chunk } => {
+                    // cancelled after the worker had already started on it, or
+                    // the server sent the chunk again in the meantime
+                    let position = chunk.position;
+                    if !self.take_data_job(position, id) {
+                        continue;
+                    }
+
+                    self.add_chunk(*chunk);
+                    for (block_position, block) in
+                        self.pending_blocks.remove(&position).unwrap_or_default()
+                    {
+                        self.set_block(block_position, block);
+                    }
+                }
+                JobResult::ChunkFailed { position, id } => {
+                    if self.take_data_job(position, id) {
+                        self.pending_blocks.remove(&position);
+                        // the server has to send it again
+                        self.failed_chunks.push(position);
+                    }
+                }
+                JobResult::ChunkMesh {
+                    position,
+                    id,
+                    mesh,
+                    missing_neighbors,
+                    connections,
+                } => {
+                    // an edit may have sent off a newer mesh in the meantime
+                    if self
+                        .mesh_jobs
+                        .get(&position)
+                        .is_none_or(|(job, _)| *job != id)
+                    {
+                        continue;
+                    }
+                    self.mesh_jobs.remove(&position);
+
+                    if missing_neighbors {
+                        self.chunks_with_missing_neighbors.insert(position);
+                    } else {
+                        self.chunks_with_missing_neighbors.remove(&position);
+                    }
+
+                    if let Some(chunk) = self.chunk_map.get_mut(&position) {
+                        chunk.face_connections = Some(connections);
+                        finished.chunks.push((position, mesh));
+                    }
+                }
+                JobResult::Lod(node, mesh) => {
+                    if self.lod_jobs.remove(&node).is_some() {
+                        finished.lods.push((node, mesh));
+                    }
+                }
+            }
+        }
+
+        self.spawn_data_jobs();
+        self.spawn_mesh_jobs();
+        self.spawn_lod_jobs();
+
+        finished
+    }
+
+    /// Removes the data job for the chunk if `id` is the latest one, returning
+    /// whether it was.
+    fn take_data_job(&mut self, position: IVec3, id: u64) -> bool {
+        if self
+            .data_jobs
+            .get(&position)
+            .is_none_or(|(job, _)| *job != id)
+        {
+            return false;
+        }
+
+        self.data_jobs.remove(&position);
+        true
+    }
+
+    /// Whether any chunks or meshes are still queued or being worked on.
+    pub fn has_pending_jobs(&self) -> bool {
+        !self.chunk_data_load_queue.is_empty()
+            || !self.chunk_mesh_load_queue.is_empty()
+            || !self.chunk_mesh_reload_queue.is_empty()
+            || !self.chunk_neighbor_loaded_queue.is_empty()
+            || !self.data_jobs.is_empty()
+            || !self.mesh_jobs.is_empty()
+            || self.lod_pending()
+    }
+
+    /// Whether some level of detail nodes are still queued or being built.
+    pub fn lod_pending(&self) -> bool {
+        !self.lod_load_queue.is_empty() || !self.lod_jobs.is_empty()
+    }
+
+    fn spawn_data_jobs(&mut self) {
+        while self.data_jobs.len() < MAX_DATA_JOBS
+            && let Some(position) = self.chunk_data_load_queue.pop()
+        {
+            let id = self.next_data_job;
+            self.next_data_job += 1;
+            let cancel = CancelToken::default();
+            let generator = self.generator.clone();
+            let world_save = self.world_save.clone();
+            self.jobs.spawn(&cancel, move || {
+                let mut chunk =
+                    load_or_generate_chunk(&*generator, world_save.as_deref(), position);
+                // assume the sky reaches everything above y = 0 until the chunk
+                // above loads, which fixes up the guess if it was wrong
+                chunk.compute_initial_light(position.y >= 0);
+                JobResult::ChunkData {
+                    id,
+                    chunk: Box::new(chunk),
+                }
+            });
+            self.data_jobs.insert(position, (id, cancel));
+        }
+    }
+
+    /// Decodes a chunk sent by the server on the workers, then adds it to
+    /// the world like a generated one. Chunks that left the load area on
+    /// their way here are dropped, returning false.
+    pub fn receive_chunk(&mut self, position: IVec3, data: Vec<u8>) -> bool {
+        if !self.load_area.contains(position) {
+            return false;
+        }
+
+        let id = self.next_data_job;
+        self.next_data_job += 1;
+        let cancel = CancelToken::default();
+        self.jobs
+            .spawn(&cancel, move || match decode_chunk(position, &data) {
+                Ok(mut chunk) => {
+                    chunk.compute_initial_light(position.y >= 0);
+                    JobResult::ChunkData {
+                        id,
+                        chunk: Box::new(chunk),
+                    }
+                }
+                Err(e) => {
+                    log::error!(
+                        "unable to decode chunk {} from the server: {:#}",
+                        position,
+                        e
+                    );
+                    JobResult::ChunkFailed { position, id }
+                }
+            });
+
+        if let Some((_, previous)) = self.data_jobs.insert(position, (id, cancel)) {
+            previous.cancel();
+        }
+        // the chunk as it is now already has the changes made before
+        self.pending_blocks.remove(&position);
+
+        true
+    }
+
+    /// Adds a freshly generated chunk to the world, queueing it and the
+    /// neighbors that can now cull their faces against it to be meshed.
+    fn add_chunk(&mut self, chunk: Chunk) {
+        for dir in [
+            IVec3::NEG_X,
+            IVec3::X,
+            IVec3::NEG_Y,
+            IVec3::Y,
+            IVec3::NEG_Z,
+            IVec3::Z,
+        ] {
+            let neighbor_pos = chunk.position + dir;
+            if self.chunk_map.contains_key(&neighbor_pos) {
+                self.chunks_with_missing_neighbors.insert(neighbor_pos);
+                if !self.chunk_mesh_load_queue.contains(&neighbor_pos)
+                    & !self.chunk_mesh_reload_queue.contains(&neighbor_pos)
+                {
+                    self.chunk_neighbor_loaded_queue.insert(neighbor_pos);
+                }
+            }
+        }
+
+        if !chunk.is_empty
+            & !self.chunk_mesh_load_queue.contains(&chunk.position)
+            & !self.chunk_mesh_reload_queue.contains(&chunk.position)
+            & !self.chunk_neighbor_loaded_queue.contains(&chunk.position)
+        {
+            self.chunk_mesh_load_queue.push(chunk.position);
+        }
+
+        let position = chunk.position;
+        self.chunk_map.insert(position, chunk);
+
+        let mut updates = LightUpdates::default();
+        updates.connect_chunk(&mut self.chunk_map, position);
+        updates.propagate(&mut self.chunk_map);
+
+        for pos in updates.touched {
+            if pos != position
+                && self.chunk_map.contains_key(&pos)
+                && !self.chunk_mesh_load_queue.contains(&pos)
+                && !self.chunk_mesh_reload_queue.contains(&pos)
+            {
+                self.chunk_neighbor_loaded_queue.insert(pos);
+            }
+        }
+    }
+
+    /// Sends off meshes for edited chunks first, then for newly loaded ones,
+    /// then for the ones whose neighbors changed.
+    fn spawn_mesh_jobs(&mut self) {
+        // nobody looks at the world on a server
+        if self.role == WorldRole::Server {
+            self.chunk_mesh_load_queue.retain(|_| false);
+            self.chunk_mesh_reload_queue.clear();
+            self.chunk_neighbor_loaded_queue.clear();
+            return;
+        }
+
+        while self.mesh_jobs.len() < MAX_MESH_JOBS {
+            let position = if let Some(&pos) = self.chunk_mesh_reload_queue.iter().next() {
+                self.chunk_mesh_reload_queue.remove(&pos);
+                pos
+            } else if let Some(pos) = self.chunk_mesh_load_queue.pop() {
+                pos
+            } else if let Some(&pos) = self.chunk_neighbor_loaded_queue.iter().next() {
+                self.chunk_neighbor_loaded_queue.remove(&pos);
+                pos
+            } else {
+                break;
+            };
+
+            // unloaded since it was queued
+            let Some(chunk) = self.chunk_map.get(&position) else {
+                continue;
+            };
+
+            // the workers mesh a copy, so the world can keep changing meanwhile
+            let chunk = chunk.clone();
+            let neighbors = [
+                IVec3::NEG_Z, // Front
+                IVec3::Z,     // Back
+                IVec3::NEG_X, // Left
+                IVec3::X,     // Right
+                IVec3::NEG_Y, // Bottom
+                IVec3::Y,     // Top
+            ]
+            .map(|dir| self.chunk_map.get(&(position + dir)).cloned());
+            let mode = self.meshing_mode;
+
+            let id = self.next_mesh_job;
+            self.next_mesh_job += 1;
+            let cancel = CancelToken::default();
+            self.jobs.spawn(&cancel, move || {
+                let (mesh, missing_neighbors) =
+                    chunk.generate_mesh(neighbors.each_ref().map(Option::as_ref), mode);
+                JobResult::ChunkMesh {
+                    position,
+                    id,
+                    mesh,
+                    missing_neighbors,
+                    connections: FaceConnections::of(&chunk),
+                }
+            });
+
+            if let Some((_, previous)) = self.mesh_jobs.insert(position, (id, cancel)) {
+                previous.cancel();
+            }
+        }
+    }
+
+    fn spawn_lod_jobs(&mut self) {
+        while self.lod_jobs.len() < MAX_LOD_JOBS
+            && let Some(node) = self.lod_load_queue.pop_front()
+        {
+            let cancel = CancelToken::default();
+            let generator = self.generator.clone();
+            self.jobs.spawn(&cancel, move || {
+                JobResult::Lod(node, node.generate_mesh(generator.as_ref()))
+            });
+            self.lod_jobs.insert(node, cancel);
+        }
+    }
+
+    /// Works out the level of detail nodes needed around the load area and
+    /// queues the ones that haven't been built yet, closest first.
+    fn update_lod_nodes(&mut self) {
+        let nodes = lod::lod_nodes(&self.load_area, self.lod_levels);
+
+        let queued = self.lod_load_queue.drain(..).collect::<AHashSet<LodNode>>();
+        self.lod_load_queue = nodes
+            .iter()
+            .filter(|node| !self.lod_nodes.contains(node) || queued.contains(node))
+            .copied()
+            .collect();
+        self.lod_nodes = nodes.into_iter().collect();
+
+        let lod_nodes = &self.lod_nodes;
+        self.lod_jobs.retain(|node, cancel| {
+            let needed = lod_nodes.contains(node);
+            if !needed {
+                cancel.cancel();
+            }
+            needed
+        });
+    }
+
+    /// The loaded chunks that can be seen from `camera_position` through
+    /// open space, see [`visibility::visible_chunks`].
+    pub fn visible_chunks(
+        &self,
+        camera_position: Vec3,
+        frustum: Option<&Frustum>,
+    ) -> Option<AHashSet<IVec3>> {
+        visibility::visible_chunks(&self.chunk_map, camera_position, frustum)
+    }
+
+    /// Lets the load queues favor the chunks the player is looking at.
+    /// `direction` should be normalized, or zero to go by distance alone.
+    pub fn set_view(&mut self, direction: Vec3, frustum: Option<Frustum>) {
+        let view = LoadView {
+            center: self.load_area.center,
+            direction,
+            frustum,
+        };
+        self.chunk_data_load_queue.set_view(view.clone());
+        self.chunk_mesh_load_queue.set_view(view);
+    }
+
+    /// Moves the load area to `position`, unloading the chunks that left it
+    /// and queueing the ones that entered it. Returns the positions of the
+    /// chunks that were unloaded or given up on before they were done.
+    pub fn update_around(&mut self, position: IVec3) -> Vec<IVec3> {
+        self.update_around_all(&[position])
+    }
+
+    /// Keeps the chunks around every one of `positions` loaded, like a server
+    /// does for all of its players, unloading the chunks that left all of
+    /// the areas and queueing the ones that entered one. The load queues and
+    /// levels of detail go by the first position. Returns the positions of
+    /// the chunks that were unloaded or given up on before they were done.
+    pub fn update_around_all(&mut self, positions: &[IVec3]) -> Vec<IVec3> {
+        if let Some(&position) = positions.first() {
+            self.load_area.center = position;
+        }
+        let areas = positions
+            .iter()
+            .map(|&center| LoadArea {
+                center,
+                ..self.load_area
+            })
+            .collect::<Vec<LoadArea>>();
+        let contains =
+            |chunk_position: IVec3| areas.iter().any(|area| area.contains(chunk_position));
+
+        let view = LoadView {
+            center: self.load_area.center,
+            ..self.chunk_data_load_queue.view().clone()
+        };
+        self.chunk_data_load_queue.set_view(view.clone());
+        self.chunk_mesh_load_queue.set_view(view);
+
+        self.chunk_data_load_queue
+            .retain(|chunk_position| contains(*chunk_position));
+        self.chunk_mesh_load_queue
+            .retain(|chunk_position| contains(*chunk_position));
+
+        let evicted = self
+            .chunk_map
+            .extract_if(|_, chunk| !contains(chunk.position))
+            .map(|(_, chunk)| chunk)
+            .collect::<Vec<Chunk>>();
+
+        if let Some(world_save) = &self.world_save
+            && let Err(e) = world_save.save_chunks(evicted.iter().filter(|chunk| chunk.modified))
+        {
+            log::error!("unable to save evicted chunks: {:#}", e);
+        }
+
+        let mut unloaded = evicted
+            .iter()
+            .map(|chunk| chunk.position)
+            .collect::<Vec<IVec3>>();
+
+        // jobs for chunks that left the area are dropped, the workers skip
+        // them if they haven't started yet
+        self.data_jobs.retain(|chunk_position, (_, cancel)| {
+            let needed = contains(*chunk_position);
+            if !needed {
+                cancel.cancel();
+                unloaded.push(*chunk_position);
+            }
+            needed
+        });
+        self.pending_blocks
+            .retain(|chunk_position, _| self.data_jobs.contains_key(chunk_position));
+        self.mesh_jobs.retain(|chunk_position, (_, cancel)| {
+            let needed = contains(*chunk_position);
+            if !needed {
+                cancel.cancel();
+            }
+            needed
+        });
+
+        self.chunk_neighbor_loaded_queue
+            .retain(|chunk_position| contains(*chunk_position));
+        self.chunks_with_missing_neighbors
+            .retain(|chunk_position| contains(*chunk_position));
+
+        // a client waits for the server to send its chunks instead
+        if self.role != WorldRole::Client {
+            for chunk_pos in areas.iter().flat_map(LoadArea::chunks) {
+                if !self.chunk_map.contains_key(&chunk_pos)
+                    && !self.data_jobs.contains_key(&chunk_pos)
+                {
+                    self.chunk_data_load_queue.push(chunk_pos);
+                }
+            }
+        }
+
+        self.update_lod_nodes();
+
+        unloaded
+    }
+}
+
+#[cfg(test)]
+impl ChunkManager {
+    /// Waits until everything queued around the load area has been
+    /// generated and meshed.
+    pub(crate) fn finish_jobs(&mut self) {
+        while self.has_pending_jobs() {
+            self.update_jobs();
+            std::thread::sleep(std::time::Duration::from_millis(1));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+    use crate::{generator::FlatGenerator, world_save::encode_chunk};
+
+    #[test]
+    fn chunks_that_left_the_area_while_generating_are_dropped() {
+        let mut chunk_manager = ChunkManager::new(1, Arc::new(FlatGenerator::default()));
+        chunk_manager.update_around(IVec3::ZERO);
+        chunk_manager.update_jobs();
+        assert!(!chunk_manager.data_jobs.is_empty());
+        // let the workers finish before the chunks get cancelled
+        std::thread::sleep(Duration::from_millis(200));
+
+        let far = IVec3::new(50, 0, 0);
+        chunk_manager.update_around(far);
+        assert!(chunk_manager.data_jobs.is_empty());
+        chunk_manager.finish_jobs();
+
+        assert_eq!(chunk_manager.chunk_map.len(), 27);
+        assert!(
+            chunk_manager
+                .chunk_map
+                .keys()
+                .all(|&position| chunk_manager.load_area.contains(position))
+        );
+    }
+
+    #[test]
+    fn received_chunks_keep_changes_made_while_decoding() {
+        let generator = Arc::new(FlatGenerator::default());
+        let mut chunk = Chunk::new(IVec3::ZERO);
+        generator.generate(&mut chunk);
+        let data = encode_chunk(&chunk).unwrap();
+
+        let mut chunk_manager = ChunkManager::new(1, generator);
+        chunk_manager.role = WorldRole::Client;
+        chunk_manager.update_around(IVec3::ZERO);
+
+        let lamp = Block::named("lamp");
+        assert!(chunk_manager.receive_chunk(IVec3::ZERO, data.clone()));
+        chunk_manager.set_block(IVec3::new(1, 10, 1), lamp);
+        // sent again before the first one was decoded, which already has
+        // the change
+        assert!(chunk_manager.receive_chunk(IVec3::ZERO, data));
+        chunk_manager.set_block(IVec3::new(2, 10, 2), lamp);
+        chunk_manager.finish_jobs();
+
+        assert_eq!(
+            chunk_manager.get_block(IVec3::new(1, 10, 1)),
+            Some(Block::AIR)
+        );
+        assert_eq!(chunk_manager.get_block(IVec3::new(2, 10, 2)), Some(lamp));
+        assert!(chunk_manager.failed_chunks.is_empty());
+
+        assert!(chunk_manager.receive_chunk(IVec3::X, b"not a chunk".to_vec()));
+        chunk_manager.finish_jobs();
+        assert_eq!(chunk_manager.failed_chunks, [IVec3::X]);
+        assert!(!chunk_manager.receive_chunk(IVec3::X * 5, Vec::new()));
+    }
+}
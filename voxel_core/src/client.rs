@@ -0,0 +1,208 @@
+use std::{
+    net::ToSocketAddrs,
+    time::{Duration, Instant},
+};
+
+use ahash::AHashMap;
+use anyhow::{Context, bail};
+use glam::{IVec3, Vec3};
+
+use crate::{
+    block::{Block, registry},
+    chunk_manager::{ChunkManager, WorldRole},
+    generator::generator_from_name,
+    net::{ClientMessage, Connection, PROTOCOL_VERSION, ServerMessage, Welcome},
+    time::TimeOfDay,
+};
+
+/// how long to wait for the server to welcome the player
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+/// the position is sent at most this often
+const POSITION_INTERVAL: Duration = Duration::from_millis(50);
+/// the furthest the client loads chunks, however far the server would send them
+pub const MAX_RENDER_DISTANCE: i32 = 16;
+
+/// Another player on the same server, where they were last seen.
+#[derive(Clone, Debug)]
+pub struct RemotePlayer {
+    pub name: String,
+    pub position: Vec3,
+    pub yaw: f32,
+    pub pitch: f32,
+}
+
+/// The connection of a player to a server, which owns the world. The client
+/// keeps a copy of the chunks around the player in a [`WorldRole::Client`]
+/// chunk manager and asks the server for every change it wants to make.
+pub struct Client {
+    connection: Connection<ServerMessage, ClientMessage>,
+    pub welcome: Welcome,
+    pub players: AHashMap<u32, RemotePlayer>,
+    last_position: Option<(Vec3, f32, f32)>,
+    last_position_sent: Instant,
+    teleport: Option<Vec3>,
+}
+
+impl Client {
+    /// Connects to the server and waits for it to let the player in.
+    pub fn connect(address: impl ToSocketAddrs, name: &str) -> anyhow::Result<Self> {
+        let mut connection = Connection::connect(address)?;
+        connection.send(ClientMessage::Hello {
+            version: PROTOCOL_VERSION,
+            name: name.to_string(),
+            render_distance: MAX_RENDER_DISTANCE,
+        });
+
+        let welcome = match connection.receive_timeout(CONNECT_TIMEOUT) {
+            Some(ServerMessage::Welcome(welcome)) => welcome,
+            Some(ServerMessage::Disconnect { reason }) => bail!("refused by the server: {reason}"),
+            Some(message) => bail!("unexpected {message:?} from the server"),
+            None => bail!("the server didn't answer"),
+        };
+        log::info!(
+            "joined {} as player {}",
+            connection.peer_address(),
+            welcome.player_id
+        );
+
+        Ok(Self {
+            connection,
+            welcome,
+            players: AHashMap::new(),
+            last_position: None,
+            last_position_sent: Instant::now(),
+            teleport: None,
+        })
+    }
+
+    /// A chunk manager for the world of the server, loading the area of
+    /// chunks the server sends. The generator of the world is only used for
+    /// the levels of detail.
+    pub fn create_chunk_manager(&self) -> anyhow::Result<ChunkManager> {
+        let welcome = &self.welcome;
+        let generator = generator_from_name(&welcome.generator, welcome.seed)
+            .with_context(|| format!("unknown world generator {}", welcome.generator))?;
+
+        // the server should already keep to the distance it was told, but a
+        // bigger one would load far more chunks than the client can hold
+        let render_distance = welcome.render_distance.clamp(1, MAX_RENDER_DISTANCE);
+        let mut chunk_manager = ChunkManager::new(render_distance, generator);
+        chunk_manager.role = WorldRole::Client;
+        chunk_manager.load_area.shape = welcome.load_shape;
+
+        Ok(chunk_manager)
+    }
+
+    /// Applies everything the server sent since the last call to the world
+    /// and the other players. Fails once the server is gone.
+    pub fn update(&mut self, chunk_manager: &mut ChunkManager) -> anyhow::Result<()> {
+        let mut dropped = Vec::new();
+        for message in self.connection.receive() {
+            match message {
+                ServerMessage::Chunk { position, data } => {
+                    if !chunk_manager.receive_chunk(position, data) {
+                        dropped.push(position);
+                    }
+                }
+                ServerMessage::BlockChanged { position, block } => match registry().get(&block) {
+                    Some(block) => chunk_manager.set_block(position, block),
+                    None => log::warn!("the server placed unknown block \"{}\"", block),
+                },
+                ServerMessage::PlayerJoined { id, name } => {
+                    log::info!("{} joined", name);
+                    self.players.insert(
+                        id,
+                        RemotePlayer {
+                            name,
+                            position: Vec3::ZERO,
+                            yaw: 0.0,
+                            pitch: 0.0,
+                        },
+                    );
+                }
+                ServerMessage::PlayerMoved {
+                    id,
+                    position,
+                    yaw,
+                    pitch,
+                } => {
+                    if let Some(player) = self.players.get_mut(&id) {
+                        player.position = position;
+                        player.yaw = yaw;
+                        player.pitch = pitch;
+                    }
+                }
+                ServerMessage::PlayerLeft { id } => {
+                    if let Some(player) = self.players.remove(&id) {
+                        log::info!("{} left", player.name);
+                    }
+                }
+                ServerMessage::Time { ticks } => {
+                    chunk_manager.time_of_day = TimeOfDay::from_ticks(ticks);
+                }
+                ServerMessage::Teleport { position } => {
+                    self.teleport = Some(position);
+                    // make sure the server hears back where the player ended up
+                    self.last_position = None;
+                }
+                ServerMessage::Disconnect { reason } => {
+                    bail!("disconnected by the server: {reason}")
+                }
+                ServerMessage::Welcome(_) => log::warn!("welcomed by the server twice"),
+            }
+        }
+
+        // the server has to send them again once the player is back, and the
+        // ones that failed to decode right away
+        dropped.append(&mut chunk_manager.failed_chunks);
+        self.unload_chunks(dropped);
+
+        if self.connection.is_closed() {
+            bail!("lost the connection to the server");
+        }
+
+        Ok(())
+    }
+
+    /// Where the server last moved the player to, once.
+    pub fn take_teleport(&mut self) -> Option<Vec3> {
+        self.teleport.take()
+    }
+
+    /// Tells the server where the player is, when that changed and it
+    /// wasn't told too recently.
+    pub fn send_position(&mut self, position: Vec3, yaw: f32, pitch: f32) {
+        if self.last_position == Some((position, yaw, pitch))
+            || self.last_position_sent.elapsed() < POSITION_INTERVAL
+        {
+            return;
+        }
+
+        self.connection.send(ClientMessage::Position {
+            position,
+            yaw,
+            pitch,
+        });
+        self.last_position = Some((position, yaw, pitch));
+        self.last_position_sent = Instant::now();
+    }
+
+    /// Changes the block right away and asks the server to do the same. If
+    /// the server refuses, it sends back the block that's really there.
+    pub fn set_block(&self, chunk_manager: &mut ChunkManager, position: IVec3, block: Block) {
+        chunk_manager.set_block(position, block);
+        self.connection.send(ClientMessage::SetBlock {
+            position,
+            block: block.name().to_string(),
+        });
+    }
+
+    /// Tells the server which chunks the chunk manager unloaded, see
+    /// [`ChunkManager::update_around`].
+    pub fn unload_chunks(&self, positions: Vec<IVec3>) {
+        if !positions.is_empty() {
+            self.connection
+                .send(ClientMessage::UnloadedChunks(positions));
+        }
+    }
+}
@@ -0,0 +1,300 @@
+use std::collections::BTreeMap;
+
+use ahash::AHashSet;
+use glam::IVec3;
+
+use crate::{
+    block::Block,
+    chunk_manager::{ChunkManager, WorldRole},
+};
+
+/// Length of one fluid tick in seconds, fluids flow every
+/// [`FluidInfo::flow_interval`](crate::block::FluidInfo::flow_interval) ticks.
+pub const FLUID_TICK: f32 = 1.0 / 20.0;
+
+/// the most ticks one update catches up on, so a long hitch doesn't flood
+/// the world in one go
+const MAX_TICKS_PER_UPDATE: f32 = 5.0;
+
+const DIRECTIONS: [IVec3; 6] = [
+    IVec3::NEG_X,
+    IVec3::X,
+    IVec3::NEG_Y,
+    IVec3::Y,
+    IVec3::NEG_Z,
+    IVec3::Z,
+];
+
+const HORIZONTAL: [IVec3; 4] = [IVec3::NEG_X, IVec3::X, IVec3::NEG_Z, IVec3::Z];
+
+/// Voxels waiting for their fluid to flow, by the tick they're due at.
+///
+/// Only voxels next to a change ever get scheduled, so chunks where nothing
+/// is flowing cost nothing, however much fluid they hold.
+#[derive(Default)]
+pub struct FluidTicks {
+    tick: u64,
+    accumulator: f32,
+    scheduled: BTreeMap<u64, AHashSet<IVec3>>,
+}
+
+impl FluidTicks {
+    /// Makes the voxel flow `delay` ticks from now.
+    pub fn schedule(&mut self, position: IVec3, delay: u32) {
+        self.scheduled
+            .entry(self.tick + delay.max(1) as u64)
+            .or_default()
+            .insert(position);
+    }
+
+    /// The number of voxels waiting to flow.
+    pub fn pending(&self) -> usize {
+        self.scheduled.values().map(|due| due.len()).sum()
+    }
+
+    /// Advances to the next tick, returning every voxel due by then.
+    fn next_tick(&mut self) -> AHashSet<IVec3> {
+        self.tick += 1;
+
+        let mut due = AHashSet::new();
+        while let Some(entry) = self.scheduled.first_entry()
+            && *entry.key() <= self.tick
+        {
+            due.extend(entry.remove());
+        }
+
+        due
+    }
+}
+
+impl ChunkManager {
+    /// Runs as many fluid ticks as fit into the elapsed time, carrying the
+    /// remainder over to the next update.
+    pub fn update_fluids(&mut self, dt: f32) {
+        // a client only sees the fluids flow on the server
+        if self.role == WorldRole::Client {
+            return;
+        }
+
+        let ticks = &mut self.fluid_ticks;
+        ticks.accumulator = (ticks.accumulator + dt).min(MAX_TICKS_PER_UPDATE * FLUID_TICK);
+        while self.fluid_ticks.accumulator >= FLUID_TICK {
+            self.fluid_ticks.accumulator -= FLUID_TICK;
+            self.tick_fluids();
+        }
+    }
+
+    /// Lets every voxel that is due flow. The new state of every voxel is
+    /// worked out before any of them change, so the result doesn't depend on
+    /// the order they're visited in.
+    pub fn tick_fluids(&mut self) {
+        let mut changes = Vec::new();
+
+        for position in self.fluid_ticks.next_tick() {
+            // chunks unloaded since don't get updated anymore
+            let Some(current) = self.get_block(position) else {
+                continue;
+            };
+
+            match self.next_fluid_state(position) {
+                Some(block) if block != current => changes.push((position, block)),
+                Some(_) => (),
+                // try again once the neighbors have loaded
+                None => {
+                    if let Some(interval) = self.flow_interval_at(position) {
+                        self.fluid_ticks.schedule(position, interval);
+                    }
+                }
+            }
+        }
+
+        for (position, block) in changes {
+            self.set_block(position, block);
+        }
+    }
+
+    /// Schedules a voxel that just changed and its neighbors to flow, if
+    /// there is any fluid that could.
+    pub(crate) fn schedule_fluid_updates(&mut self, position: IVec3) {
+        for position in std::iter::once(position).chain(DIRECTIONS.map(|dir| position + dir)) {
+            if let Some(interval) = self.flow_interval_at(position) {
+                self.fluid_ticks.schedule(position, interval);
+            }
+        }
+    }
+
+    /// The flow interval of the fluid in the voxel, or of a fluid next to it
+    /// that could flow in if it's air.
+    fn flow_interval_at(&self, position: IVec3) -> Option<u32> {
+        let block = self.get_block(position)?;
+        if let Some(fluid) = block.fluid() {
+            return Some(fluid.flow_interval);
+        }
+        if !block.is_air() {
+            return None;
+        }
+
+        std::iter::once(IVec3::Y)
+            .chain(HORIZONTAL)
+            .find_map(|dir| Some(self.get_block(position + dir)?.fluid()?.flow_interval))
+    }
+
+    /// The block the voxel turns into when its fluid flows, or `None` if that
+    /// depends on voxels that haven't loaded yet.
+    ///
+    /// Sources never change. Fluid falls straight down, and only spreads
+    /// sideways from voxels resting on something, one level further from the
+    /// source per block. Flowing fluid with nothing feeding it dries up.
+    fn next_fluid_state(&self, position: IVec3) -> Option<Block> {
+        let current = self.get_block(position)?;
+        match current.fluid() {
+            Some(fluid) if fluid.is_source() => return Some(current),
+            Some(_) => (),
+            None if current.is_air() => (),
+            None => return Some(current),
+        }
+
+        if let Some(fluid) = self.get_block(position + IVec3::Y)?.fluid() {
+            return Some(fluid.with_level(1));
+        }
+
+        let mut next = Block::AIR;
+        let mut next_level = u8::MAX;
+        for dir in HORIZONTAL {
+            let Some(fluid) = self.get_block(position + dir)?.fluid() else {
+                continue;
+            };
+            if fluid.level >= fluid.flow_distance || !self.spreads_sideways(position + dir)? {
+                continue;
+            }
+
+            if fluid.level + 1 < next_level {
+                next_level = fluid.level + 1;
+                next = fluid.with_level(next_level);
+            }
+        }
+
+        Some(next)
+    }
+
+    /// Whether the fluid in the voxel rests on something, rather than on air
+    /// or more flowing fluid it would rather fall into.
+    fn spreads_sideways(&self, position: IVec3) -> Option<bool> {
+        let below = self.get_block(position - IVec3::Y)?;
+        Some(!below.is_air() && below.fluid().is_none_or(|fluid| fluid.is_source()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::generator::FlatGenerator;
+
+    /// the lowest layer of air in a flat world
+    const GROUND: i32 = 4;
+
+    fn flat_world() -> ChunkManager {
+        let mut chunk_manager = ChunkManager::new(1, Arc::new(FlatGenerator::default()));
+        chunk_manager.update_around(IVec3::ZERO);
+        chunk_manager.finish_jobs();
+        chunk_manager
+    }
+
+    fn run_ticks(chunk_manager: &mut ChunkManager, ticks: usize) {
+        for _ in 0..ticks {
+            chunk_manager.tick_fluids();
+        }
+    }
+
+    fn water(level: u8) -> Option<Block> {
+        Some(Block::named("water").fluid().unwrap().with_level(level))
+    }
+
+    #[test]
+    fn sources_spread_one_level_further_per_block() {
+        let mut chunk_manager = flat_world();
+        chunk_manager.set_block(IVec3::new(0, GROUND, 0), Block::named("water"));
+        run_ticks(&mut chunk_manager, 100);
+
+        for distance in 1..=7 {
+            assert_eq!(
+                chunk_manager.get_block(IVec3::new(distance as i32, GROUND, 0)),
+                water(distance)
+            );
+        }
+        assert_eq!(
+            chunk_manager.get_block(IVec3::new(8, GROUND, 0)),
+            Some(Block::AIR)
+        );
+        // levels go by walking distance, not straight line distance
+        assert_eq!(chunk_manager.get_block(IVec3::new(-2, GROUND, 3)), water(5));
+        assert_eq!(chunk_manager.fluid_ticks.pending(), 0);
+    }
+
+    #[test]
+    fn fluids_without_a_source_dry_up() {
+        let mut chunk_manager = flat_world();
+        let source = IVec3::new(3, GROUND, -2);
+        chunk_manager.set_block(source, Block::named("water"));
+        run_ticks(&mut chunk_manager, 100);
+        assert_eq!(chunk_manager.get_block(source + IVec3::X * 4), water(4));
+
+        chunk_manager.set_block(source, Block::AIR);
+        run_ticks(&mut chunk_manager, 100);
+        for x in -8..16 {
+            for z in -16..8 {
+                assert_eq!(
+                    chunk_manager.get_block(IVec3::new(x, GROUND, z)),
+                    Some(Block::AIR)
+                );
+            }
+        }
+        assert_eq!(chunk_manager.fluid_ticks.pending(), 0);
+    }
+
+    #[test]
+    fn fluids_fall_before_spreading() {
+        let mut chunk_manager = flat_world();
+        // a source on top of a pillar, the water running off its side
+        // shouldn't spread any further until it reached the floor
+        for y in GROUND..GROUND + 4 {
+            chunk_manager.set_block(IVec3::new(0, y, 0), Block::named("stone"));
+        }
+        chunk_manager.set_block(IVec3::new(0, GROUND + 4, 0), Block::named("water"));
+        run_ticks(&mut chunk_manager, 100);
+
+        assert_eq!(
+            chunk_manager.get_block(IVec3::new(1, GROUND + 4, 0)),
+            water(1)
+        );
+        assert_eq!(
+            chunk_manager.get_block(IVec3::new(2, GROUND + 4, 0)),
+            Some(Block::AIR)
+        );
+        for y in GROUND..GROUND + 4 {
+            assert_eq!(chunk_manager.get_block(IVec3::new(1, y, 0)), water(1));
+        }
+        assert_eq!(chunk_manager.get_block(IVec3::new(2, GROUND, 0)), water(2));
+        assert_eq!(
+            chunk_manager.get_block(IVec3::new(2, GROUND + 1, 0)),
+            Some(Block::AIR)
+        );
+    }
+
+    #[test]
+    fn long_frames_only_catch_up_a_few_ticks() {
+        let mut chunk_manager = flat_world();
+        chunk_manager.update_fluids(FLUID_TICK * 2.5);
+        assert_eq!(chunk_manager.fluid_ticks.tick, 2);
+        chunk_manager.update_fluids(FLUID_TICK * 0.5);
+        assert_eq!(chunk_manager.fluid_ticks.tick, 3);
+
+        chunk_manager.update_fluids(60.0);
+        assert_eq!(
+            chunk_manager.fluid_ticks.tick,
+            3 + MAX_TICKS_PER_UPDATE as u64
+        );
+    }
+}
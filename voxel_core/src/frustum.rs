@@ -2,6 +2,7 @@ use glam::{Vec3, Vec4};
 
 use crate::camera::{Camera, Projection};
 
+#[derive(Copy, Clone)]
 pub struct Aabb {
     pub min: Vec3,
     pub max: Vec3,
@@ -13,7 +14,7 @@ impl Aabb {
     }
 }
 
-#[derive(Default)]
+#[derive(Clone, Default)]
 pub struct Frustum {
     pub planes: [Vec4; 6],
 }
@@ -96,6 +97,6 @@ impl Frustum {
             }
         }
 
-        return true;
+        true
     }
 }
@@ -0,0 +1,131 @@
+use std::sync::{
+    Arc,
+    atomic::{AtomicBool, Ordering},
+    mpsc::{self, Receiver, Sender},
+};
+
+/// Tells the worker that picks up a job that its result isn't needed anymore.
+#[derive(Clone, Default)]
+pub struct CancelToken(Arc<AtomicBool>);
+
+impl CancelToken {
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+/// Worker threads that live as long as the world, running jobs in the order
+/// they were spawned and sending whatever they produce back over a channel.
+pub struct JobSystem<T> {
+    pool: rayon::ThreadPool,
+    sender: Sender<T>,
+    receiver: Receiver<T>,
+}
+
+impl<T: Send + 'static> JobSystem<T> {
+    /// Starts a system with one worker per core, minus one for the thread
+    /// that hands out the jobs.
+    pub fn new() -> Self {
+        let threads = std::thread::available_parallelism()
+            .map_or(1, |threads| threads.get().saturating_sub(1).max(1));
+        Self::with_threads(threads)
+    }
+
+    pub fn with_threads(threads: usize) -> Self {
+        let pool = rayon::ThreadPoolBuilder::new()
+            .num_threads(threads)
+            .thread_name(|i| format!("world worker {i}"))
+            .build()
+            .expect("unable to start the world workers");
+        let (sender, receiver) = mpsc::channel();
+
+        Self {
+            pool,
+            sender,
+            receiver,
+        }
+    }
+
+    /// Runs `job` on a worker, unless `cancel` is cancelled before a worker
+    /// gets to it.
+    pub fn spawn(&self, cancel: &CancelToken, job: impl FnOnce() -> T + Send + 'static) {
+        let cancel = cancel.clone();
+        let sender = self.sender.clone();
+        self.pool.spawn_fifo(move || {
+            if cancel.is_cancelled() {
+                return;
+            }
+
+            // the receiver only goes away with the system itself
+            let _ = sender.send(job());
+        });
+    }
+
+    /// Every result sent back since the last call, without waiting for more.
+    pub fn finished(&self) -> impl Iterator<Item = T> + '_ {
+        self.receiver.try_iter()
+    }
+}
+
+impl<T: Send + 'static> Default for JobSystem<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{sync::mpsc, time::Duration};
+
+    use super::*;
+
+    /// Collects results until `count` arrived, failing if they stop coming.
+    fn wait_for(jobs: &JobSystem<u32>, count: usize) -> Vec<u32> {
+        let mut results = Vec::new();
+        for _ in 0..1000 {
+            results.extend(jobs.finished());
+            if results.len() >= count {
+                break;
+            }
+            std::thread::sleep(Duration::from_millis(1));
+        }
+        results
+    }
+
+    #[test]
+    fn cancelled_jobs_send_nothing() {
+        let jobs = JobSystem::with_threads(1);
+        // hold up the only worker until the second job is cancelled
+        let (release, blocked) = mpsc::channel();
+        jobs.spawn(&CancelToken::default(), move || {
+            blocked.recv().unwrap();
+            1
+        });
+        let cancel = CancelToken::default();
+        jobs.spawn(&cancel, || 2);
+        jobs.spawn(&CancelToken::default(), || 3);
+
+        cancel.cancel();
+        release.send(()).unwrap();
+        // jobs run in order on the one worker, so the cancelled one is done
+        // with once the last one is
+        assert_eq!(wait_for(&jobs, 2), [1, 3]);
+        assert_eq!(jobs.finished().count(), 0);
+    }
+
+    #[test]
+    fn every_job_runs_once() {
+        let jobs = JobSystem::with_threads(4);
+        for i in 0..100 {
+            jobs.spawn(&CancelToken::default(), move || i);
+        }
+
+        let mut results = wait_for(&jobs, 100);
+        results.sort_unstable();
+        assert_eq!(results, (0..100).collect::<Vec<_>>());
+    }
+}
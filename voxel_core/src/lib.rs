@@ -0,0 +1,30 @@
+//! World simulation for the voxel engine: terrain generation, chunk storage,
+//! meshing into vertex data, ray casting, block editing and the network
+//! protocol between servers and clients. Nothing in here depends on a window
+//! or a GPU, so it can be driven headless.
+
+pub mod biome;
+pub mod block;
+pub mod block_storage;
+pub mod camera;
+pub mod chunk;
+pub mod chunk_manager;
+pub mod client;
+pub mod fluid;
+pub mod frustum;
+pub mod generator;
+pub mod jobs;
+pub mod light;
+pub mod load_area;
+pub mod load_queue;
+pub mod lod;
+pub mod mesh_export;
+pub mod nbt;
+pub mod net;
+pub mod physics;
+pub mod schematic;
+pub mod structure;
+pub mod time;
+pub mod visibility;
+pub mod vox;
+pub mod world_save;
@@ -0,0 +1,614 @@
+use std::collections::VecDeque;
+
+use ahash::{AHashMap, AHashSet};
+use glam::IVec3;
+
+use crate::{
+    block::Block,
+    chunk::{CHUNK_SIZE, Chunk},
+};
+
+pub const MAX_LIGHT: u8 = 15;
+
+const VOLUME: usize = CHUNK_SIZE * CHUNK_SIZE * CHUNK_SIZE;
+
+const DIRECTIONS: [IVec3; 6] = [
+    IVec3::NEG_X,
+    IVec3::X,
+    IVec3::NEG_Y,
+    IVec3::Y,
+    IVec3::NEG_Z,
+    IVec3::Z,
+];
+
+/// The two kinds of light every voxel stores, both ranging from 0 to [`MAX_LIGHT`].
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum LightChannel {
+    /// light coming down from the sky, which travels straight down through
+    /// air without getting any dimmer
+    Sky,
+    /// light given off by blocks like lamps
+    Block,
+}
+
+impl LightChannel {
+    pub const ALL: [Self; 2] = [Self::Sky, Self::Block];
+
+    /// Reads this channel out of the light byte of a voxel.
+    pub fn unpack(self, light: u8) -> u8 {
+        match self {
+            Self::Sky => light >> 4,
+            Self::Block => light & 0x0F,
+        }
+    }
+
+    /// Returns the light byte of a voxel with this channel replaced by `level`.
+    pub fn pack(self, light: u8, level: u8) -> u8 {
+        match self {
+            Self::Sky => (light & 0x0F) | (level << 4),
+            Self::Block => (light & 0xF0) | level,
+        }
+    }
+
+    /// The level light spreading from a voxel at `level` in `direction` arrives
+    /// with in the voxel next to it holding `block`.
+    fn spread(self, level: u8, direction: IVec3, block: Block) -> u8 {
+        if self == Self::Sky && level == MAX_LIGHT && direction == IVec3::NEG_Y && block.is_air() {
+            MAX_LIGHT
+        } else {
+            level.saturating_sub(1)
+        }
+    }
+}
+
+/// The light bytes of a chunk, indexed the same as its blocks.
+///
+/// Chunks up in the sky or deep underground are lit the same everywhere, so
+/// those keep a single byte and only chunks with light and shadow mixed
+/// together pay for a byte per voxel, kept on the heap.
+#[derive(Clone, Debug)]
+pub enum LightStorage {
+    /// every voxel is lit the same
+    Uniform(u8),
+    Mixed(Box<[u8; VOLUME]>),
+}
+
+impl Default for LightStorage {
+    fn default() -> Self {
+        Self::Uniform(0)
+    }
+}
+
+impl LightStorage {
+    pub fn get(&self, index: usize) -> u8 {
+        match self {
+            Self::Uniform(light) => *light,
+            Self::Mixed(light) => light[index],
+        }
+    }
+
+    pub fn set(&mut self, index: usize, light: u8) {
+        match self {
+            Self::Uniform(current) if *current == light => {}
+            Self::Uniform(current) => {
+                let mut mixed = Box::new([*current; VOLUME]);
+                mixed[index] = light;
+                *self = Self::Mixed(mixed);
+            }
+            Self::Mixed(mixed) => mixed[index] = light,
+        }
+    }
+
+    /// Lights every voxel the same.
+    pub fn fill(&mut self, light: u8) {
+        *self = Self::Uniform(light);
+    }
+}
+
+impl Chunk {
+    pub fn get_light(&self, channel: LightChannel, index: usize) -> u8 {
+        channel.unpack(self.light.get(index))
+    }
+
+    pub fn set_light(&mut self, channel: LightChannel, index: usize, level: u8) {
+        self.light
+            .set(index, channel.pack(self.light.get(index), level));
+    }
+
+    /// Lights the chunk as if it was on its own, with sky light coming in from
+    /// the top when `open_to_sky` is set. Light from and to the neighboring
+    /// chunks is exchanged afterwards with [`LightUpdates::connect_chunk`].
+    pub fn compute_initial_light(&mut self, open_to_sky: bool) {
+        if self.is_empty {
+            // nothing to block the sky or give off light, the common case above ground
+            let sky = if open_to_sky { MAX_LIGHT } else { 0 };
+            self.light.fill(LightChannel::Sky.pack(0, sky));
+            return;
+        }
+
+        self.light.fill(0);
+
+        let mut queue = VecDeque::new();
+
+        if open_to_sky {
+            for x in 0..CHUNK_SIZE {
+                for z in 0..CHUNK_SIZE {
+                    let mut level = MAX_LIGHT;
+                    for y in (0..CHUNK_SIZE).rev() {
+                        let index = Chunk::block_index(x, y, z);
+                        let block = self.blocks.get(index);
+                        if block.blocks_light() {
+                            break;
+                        }
+
+                        level = LightChannel::Sky.spread(level, IVec3::NEG_Y, block);
+                        if level == 0 {
+                            break;
+                        }
+
+                        self.set_light(LightChannel::Sky, index, level);
+                        queue.push_back(index);
+                    }
+                }
+            }
+            self.flood_local(LightChannel::Sky, &mut queue);
+        }
+
+        for index in 0..VOLUME {
+            let emission = self.blocks.get(index).light_emission();
+            if emission > 0 {
+                self.set_light(LightChannel::Block, index, emission);
+                queue.push_back(index);
+            }
+        }
+        self.flood_local(LightChannel::Block, &mut queue);
+    }
+
+    /// Spreads light from the queued voxels without leaving the chunk.
+    fn flood_local(&mut self, channel: LightChannel, queue: &mut VecDeque<usize>) {
+        while let Some(index) = queue.pop_front() {
+            let level = self.get_light(channel, index);
+            if level <= 1 {
+                continue;
+            }
+
+            let position = IVec3::new(
+                (index % CHUNK_SIZE) as i32,
+                (index / CHUNK_SIZE % CHUNK_SIZE) as i32,
+                (index / (CHUNK_SIZE * CHUNK_SIZE)) as i32,
+            );
+
+            for direction in DIRECTIONS {
+                let neighbor = position + direction;
+                if neighbor.cmplt(IVec3::ZERO).any()
+                    || neighbor.cmpge(IVec3::splat(CHUNK_SIZE as i32)).any()
+                {
+                    continue;
+                }
+
+                let neighbor_index = Chunk::block_index(
+                    neighbor.x as usize,
+                    neighbor.y as usize,
+                    neighbor.z as usize,
+                );
+                let block = self.blocks.get(neighbor_index);
+                if block.blocks_light() {
+                    continue;
+                }
+
+                let spread = channel.spread(level, direction, block);
+                if spread > self.get_light(channel, neighbor_index) {
+                    self.set_light(channel, neighbor_index, spread);
+                    queue.push_back(neighbor_index);
+                }
+            }
+        }
+    }
+}
+
+fn voxel_index(position: IVec3) -> (IVec3, usize) {
+    let local = Chunk::world_to_local_pos(position);
+    (
+        Chunk::world_to_chunk_pos(position),
+        Chunk::block_index(local.x as usize, local.y as usize, local.z as usize),
+    )
+}
+
+/// Light changes waiting to be spread through the loaded chunks.
+///
+/// Removed light is cleared first, by following the voxels that got their
+/// light from the removed one, and whatever brighter light borders the cleared
+/// area is then spread back into it. Light never spreads into unloaded chunks,
+/// those catch up when they load through [`LightUpdates::connect_chunk`].
+#[derive(Default)]
+pub struct LightUpdates {
+    increase: VecDeque<(IVec3, LightChannel)>,
+    decrease: VecDeque<(IVec3, LightChannel, u8)>,
+    /// every chunk with a voxel whose light changed, or that borders one, so
+    /// all of these need to be remeshed
+    pub touched: AHashSet<IVec3>,
+}
+
+impl LightUpdates {
+    /// Spreads the light of a voxel into its neighbors.
+    pub fn add(&mut self, position: IVec3, channel: LightChannel) {
+        self.increase.push_back((position, channel));
+    }
+
+    /// Sets the light of a voxel and spreads it into its neighbors.
+    pub fn set(
+        &mut self,
+        chunk_map: &mut AHashMap<IVec3, Chunk>,
+        position: IVec3,
+        channel: LightChannel,
+        level: u8,
+    ) {
+        let (chunk_position, index) = voxel_index(position);
+        if let Some(chunk) = chunk_map.get_mut(&chunk_position) {
+            chunk.set_light(channel, index, level);
+            self.touch(position);
+            self.add(position, channel);
+        }
+    }
+
+    /// Darkens a voxel along with every voxel that was lit through it.
+    pub fn remove(
+        &mut self,
+        chunk_map: &mut AHashMap<IVec3, Chunk>,
+        position: IVec3,
+        channel: LightChannel,
+    ) {
+        let (chunk_position, index) = voxel_index(position);
+        if let Some(chunk) = chunk_map.get_mut(&chunk_position) {
+            let level = chunk.get_light(channel, index);
+            if level > 0 {
+                chunk.set_light(channel, index, 0);
+                self.touch(position);
+                self.decrease.push_back((position, channel, level));
+            }
+        }
+    }
+
+    /// Exchanges light between a freshly loaded chunk and its loaded neighbors.
+    ///
+    /// Chunks are lit on their own before being added, guessing whether the sky
+    /// reaches their top. Wherever the chunk above turns out to disagree with
+    /// that guess the sky light is taken back out, then any voxel along the
+    /// shared faces that can brighten the voxel across from it is spread.
+    pub fn connect_chunk(&mut self, chunk_map: &mut AHashMap<IVec3, Chunk>, position: IVec3) {
+        let mut removed = Vec::new();
+        for (upper, lower) in [
+            (position + IVec3::Y, position),
+            (position, position - IVec3::Y),
+        ] {
+            let (Some(upper), Some(lower)) = (chunk_map.get(&upper), chunk_map.get(&lower)) else {
+                continue;
+            };
+
+            for x in 0..CHUNK_SIZE {
+                for z in 0..CHUNK_SIZE {
+                    let above = Chunk::block_index(x, 0, z);
+                    let below = Chunk::block_index(x, CHUNK_SIZE - 1, z);
+                    let expected = LightChannel::Sky.spread(
+                        upper.get_light(LightChannel::Sky, above),
+                        IVec3::NEG_Y,
+                        lower.blocks.get(below),
+                    );
+                    if lower.get_light(LightChannel::Sky, below) == MAX_LIGHT
+                        && expected < MAX_LIGHT
+                    {
+                        removed.push(
+                            lower.world_position
+                                + IVec3::new(x as i32, CHUNK_SIZE as i32 - 1, z as i32),
+                        );
+                    }
+                }
+            }
+        }
+
+        for voxel in removed {
+            self.remove(chunk_map, voxel, LightChannel::Sky);
+        }
+
+        let Some(chunk) = chunk_map.get(&position) else {
+            return;
+        };
+
+        for direction in DIRECTIONS {
+            let Some(neighbor) = chunk_map.get(&(position + direction)) else {
+                continue;
+            };
+
+            // the axis pointing along the direction and the two spanning the shared face
+            let d = direction.abs().max_position();
+            let u = (d + 1) % 3;
+            let v = (d + 2) % 3;
+            let (inside, outside) = if direction[d] > 0 {
+                (CHUNK_SIZE as i32 - 1, 0)
+            } else {
+                (0, CHUNK_SIZE as i32 - 1)
+            };
+
+            for i in 0..CHUNK_SIZE as i32 {
+                for j in 0..CHUNK_SIZE as i32 {
+                    let mut local = IVec3::ZERO;
+                    local[u] = i;
+                    local[v] = j;
+                    local[d] = inside;
+                    let mut across = local;
+                    across[d] = outside;
+
+                    let index =
+                        Chunk::block_index(local.x as usize, local.y as usize, local.z as usize);
+                    let across_index =
+                        Chunk::block_index(across.x as usize, across.y as usize, across.z as usize);
+
+                    for channel in LightChannel::ALL {
+                        let level = chunk.get_light(channel, index);
+                        let across_level = neighbor.get_light(channel, across_index);
+
+                        if channel.spread(level, direction, neighbor.blocks.get(across_index))
+                            > across_level
+                            && !neighbor.blocks.get(across_index).blocks_light()
+                        {
+                            self.add(chunk.world_position + local, channel);
+                        }
+
+                        if channel.spread(across_level, -direction, chunk.blocks.get(index)) > level
+                            && !chunk.blocks.get(index).blocks_light()
+                        {
+                            self.add(neighbor.world_position + across, channel);
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    /// Applies every queued change, stopping at the edge of the loaded chunks.
+    pub fn propagate(&mut self, chunk_map: &mut AHashMap<IVec3, Chunk>) {
+        while let Some((position, channel, old_level)) = self.decrease.pop_front() {
+            for direction in DIRECTIONS {
+                let neighbor = position + direction;
+                let (chunk_position, index) = voxel_index(neighbor);
+                let Some(chunk) = chunk_map.get_mut(&chunk_position) else {
+                    continue;
+                };
+
+                let level = chunk.get_light(channel, index);
+                if level == 0 {
+                    continue;
+                }
+
+                let lit_by_removed = level < old_level
+                    || (channel == LightChannel::Sky
+                        && direction == IVec3::NEG_Y
+                        && level == MAX_LIGHT
+                        && old_level == MAX_LIGHT);
+
+                if lit_by_removed {
+                    chunk.set_light(channel, index, 0);
+                    self.decrease.push_back((neighbor, channel, level));
+
+                    // light sources keep shining even if their surroundings went dark
+                    let emission = chunk.blocks.get(index).light_emission();
+                    if channel == LightChannel::Block && emission > 0 {
+                        chunk.set_light(channel, index, emission);
+                        self.increase.push_back((neighbor, channel));
+                    }
+                    self.touch(neighbor);
+                } else {
+                    // lit from somewhere else, so it can relight the cleared voxels
+                    self.increase.push_back((neighbor, channel));
+                }
+            }
+        }
+
+        while let Some((position, channel)) = self.increase.pop_front() {
+            let (chunk_position, index) = voxel_index(position);
+            let Some(level) = chunk_map
+                .get(&chunk_position)
+                .map(|chunk| chunk.get_light(channel, index))
+            else {
+                continue;
+            };
+
+            if level <= 1 {
+                continue;
+            }
+
+            for direction in DIRECTIONS {
+                let neighbor = position + direction;
+                let (chunk_position, index) = voxel_index(neighbor);
+                let Some(chunk) = chunk_map.get_mut(&chunk_position) else {
+                    continue;
+                };
+
+                let block = chunk.blocks.get(index);
+                if block.blocks_light() {
+                    continue;
+                }
+
+                let spread = channel.spread(level, direction, block);
+                if spread > chunk.get_light(channel, index) {
+                    chunk.set_light(channel, index, spread);
+                    self.increase.push_back((neighbor, channel));
+                    self.touch(neighbor);
+                }
+            }
+        }
+    }
+
+    /// Marks the chunk of a voxel for remeshing, along with the chunks next to
+    /// it if the voxel sits on their border since their faces show its light.
+    fn touch(&mut self, position: IVec3) {
+        let chunk_position = Chunk::world_to_chunk_pos(position);
+        let local = Chunk::world_to_local_pos(position);
+
+        self.touched.insert(chunk_position);
+        for axis in 0..3 {
+            let mut offset = IVec3::ZERO;
+            if local[axis] == 0 {
+                offset[axis] = -1;
+            } else if local[axis] == CHUNK_SIZE as i32 - 1 {
+                offset[axis] = 1;
+            } else {
+                continue;
+            }
+            self.touched.insert(chunk_position + offset);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::{chunk_manager::ChunkManager, generator::VoidGenerator};
+
+    /// Loads the chunks of an empty world around `center`, everything below
+    /// y = 0 starts out dark and everything above it in full sky light.
+    fn empty_world(center: IVec3) -> ChunkManager {
+        let mut chunk_manager = ChunkManager::new(1, Arc::new(VoidGenerator));
+        load_around(&mut chunk_manager, center);
+        chunk_manager
+    }
+
+    fn load_around(chunk_manager: &mut ChunkManager, center: IVec3) {
+        chunk_manager.update_around(center);
+        chunk_manager.finish_jobs();
+    }
+
+    /// The light along a row of voxels going +x from `start`.
+    fn light_along_x(
+        chunk_manager: &ChunkManager,
+        channel: LightChannel,
+        start: IVec3,
+        count: i32,
+    ) -> Vec<u8> {
+        (0..count)
+            .map(|i| {
+                let (chunk_position, index) = voxel_index(start + IVec3::X * i);
+                chunk_manager.chunk_map[&chunk_position].get_light(channel, index)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn evenly_lit_chunks_keep_a_single_byte() {
+        // high enough that every loaded chunk is open sky
+        let chunk_manager = empty_world(IVec3::new(0, 5, 0));
+        for chunk in chunk_manager.chunk_map.values() {
+            assert!(matches!(chunk.light, LightStorage::Uniform(_)));
+        }
+
+        let mut light = LightStorage::default();
+        light.set(5, 0);
+        assert!(matches!(light, LightStorage::Uniform(0)));
+        light.set(5, 0xF0);
+        assert_eq!((light.get(4), light.get(5)), (0, 0xF0));
+        light.fill(0xF0);
+        assert!(matches!(light, LightStorage::Uniform(0xF0)));
+    }
+
+    #[test]
+    fn lamps_light_across_chunk_borders() {
+        let mut chunk_manager = empty_world(IVec3::new(0, -3, 0));
+        // the border between chunk 0 and 1 lies between x = 31 and 32
+        let lamp = IVec3::new(30, -80, 5);
+        let row = IVec3::new(28, -80, 5);
+
+        chunk_manager.set_block(lamp, Block::named("lamp"));
+        assert_eq!(
+            light_along_x(&chunk_manager, LightChannel::Block, row, 6),
+            [13, 14, 15, 14, 13, 12]
+        );
+        assert_eq!(
+            light_along_x(&chunk_manager, LightChannel::Sky, row, 6),
+            [0; 6]
+        );
+
+        chunk_manager.set_block(lamp, Block::AIR);
+        assert_eq!(
+            light_along_x(&chunk_manager, LightChannel::Block, row - IVec3::X * 14, 34),
+            [0; 34]
+        );
+    }
+
+    #[test]
+    fn blocks_across_chunk_borders_cast_shadows() {
+        let mut chunk_manager = empty_world(IVec3::new(0, -3, 0));
+        chunk_manager.set_block(IVec3::new(29, -80, 5), Block::named("lamp"));
+        let row = IVec3::new(31, -80, 5);
+        assert_eq!(
+            light_along_x(&chunk_manager, LightChannel::Block, row, 3),
+            [13, 12, 11]
+        );
+
+        // the light has to go around the stone to get behind it
+        chunk_manager.set_block(IVec3::new(32, -80, 5), Block::named("stone"));
+        assert_eq!(
+            light_along_x(&chunk_manager, LightChannel::Block, row, 3),
+            [13, 0, 9]
+        );
+
+        chunk_manager.set_block(IVec3::new(32, -80, 5), Block::AIR);
+        assert_eq!(
+            light_along_x(&chunk_manager, LightChannel::Block, row, 3),
+            [13, 12, 11]
+        );
+    }
+
+    #[test]
+    fn roofs_across_chunk_borders_shade_the_sky() {
+        let mut chunk_manager = empty_world(IVec3::ZERO);
+        let under = IVec3::new(30, 9, 5);
+        assert_eq!(
+            light_along_x(&chunk_manager, LightChannel::Sky, under, 4),
+            [15; 4]
+        );
+
+        let roof = (28..37).flat_map(|x| (3..8).map(move |z| IVec3::new(x, 10, z)));
+        for position in roof.clone() {
+            chunk_manager.set_block(position, Block::named("stone"));
+        }
+        // lit from the side, three voxels away from the edge of the roof
+        assert_eq!(
+            light_along_x(&chunk_manager, LightChannel::Sky, under, 4),
+            [12; 4]
+        );
+        // all the way down the shaded columns
+        assert_eq!(
+            light_along_x(&chunk_manager, LightChannel::Sky, IVec3::new(31, 0, 5), 2),
+            [12; 2]
+        );
+
+        for position in roof {
+            chunk_manager.set_block(position, Block::AIR);
+        }
+        assert_eq!(
+            light_along_x(&chunk_manager, LightChannel::Sky, under, 4),
+            [15; 4]
+        );
+    }
+
+    #[test]
+    fn light_reaches_chunks_that_load_later() {
+        let mut chunk_manager = empty_world(IVec3::new(0, -3, 0));
+        // on the far side of chunk 1, chunk 2 isn't loaded yet
+        chunk_manager.set_block(IVec3::new(62, -80, 5), Block::named("lamp"));
+        assert!(!chunk_manager.chunk_map.contains_key(&IVec3::new(2, -3, 0)));
+
+        load_around(&mut chunk_manager, IVec3::new(1, -3, 0));
+        assert_eq!(
+            light_along_x(
+                &chunk_manager,
+                LightChannel::Block,
+                IVec3::new(62, -80, 5),
+                3
+            ),
+            [15, 14, 13]
+        );
+    }
+}
@@ -0,0 +1,157 @@
+use glam::IVec3;
+
+/// The shape of the area of chunks kept loaded around the player.
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Default)]
+pub enum LoadShape {
+    /// every chunk within the render distance along all three axes
+    #[default]
+    Cube,
+    /// every chunk within the render distance in a straight line
+    Sphere,
+    /// every chunk within the render distance horizontally and within
+    /// `vertical_radius` vertically, for worlds that are much wider than tall
+    Cylinder { vertical_radius: i32 },
+}
+
+impl LoadShape {
+    /// Parses `cube`, `sphere` or `cylinder:<vertical radius>`.
+    pub fn parse(name: &str) -> Option<Self> {
+        match name.split_once(':') {
+            None if name == "cube" => Some(Self::Cube),
+            None if name == "sphere" => Some(Self::Sphere),
+            Some(("cylinder", vertical_radius)) => Some(Self::Cylinder {
+                vertical_radius: vertical_radius.parse().ok().filter(|&radius| radius >= 0)?,
+            }),
+            _ => None,
+        }
+    }
+}
+
+/// The chunks kept loaded around a center chunk.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct LoadArea {
+    pub center: IVec3,
+    pub radius: i32,
+    pub shape: LoadShape,
+}
+
+impl LoadArea {
+    pub fn new(radius: i32, shape: LoadShape) -> Self {
+        Self {
+            center: IVec3::ZERO,
+            radius,
+            shape,
+        }
+    }
+
+    /// How far the area reaches vertically.
+    pub fn vertical_radius(&self) -> i32 {
+        match self.shape {
+            LoadShape::Cube | LoadShape::Sphere => self.radius,
+            LoadShape::Cylinder { vertical_radius } => vertical_radius,
+        }
+    }
+
+    pub fn contains(&self, chunk_position: IVec3) -> bool {
+        let offset = chunk_position - self.center;
+        // the radius plus a half, so the sides of a round area aren't a
+        // single chunk sticking out
+        let round = self.radius * (self.radius + 1);
+
+        match self.shape {
+            LoadShape::Cube => offset.abs().max_element() <= self.radius,
+            LoadShape::Sphere => offset.length_squared() <= round,
+            LoadShape::Cylinder { vertical_radius } => {
+                offset.x * offset.x + offset.z * offset.z <= round
+                    && offset.y.abs() <= vertical_radius
+            }
+        }
+    }
+
+    /// The box around the area, min inclusive and max exclusive.
+    pub fn bounds(&self) -> (IVec3, IVec3) {
+        let half_extent = IVec3::new(self.radius, self.vertical_radius(), self.radius);
+        (self.center - half_extent, self.center + half_extent + 1)
+    }
+
+    /// Every chunk in the area.
+    pub fn chunks(&self) -> impl Iterator<Item = IVec3> + '_ {
+        let (min, max) = self.bounds();
+        (min.x..max.x)
+            .flat_map(move |x| {
+                (min.y..max.y).flat_map(move |y| (min.z..max.z).map(move |z| IVec3::new(x, y, z)))
+            })
+            .filter(|&chunk_position| self.contains(chunk_position))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn shapes_parse() {
+        assert_eq!(LoadShape::parse("cube"), Some(LoadShape::Cube));
+        assert_eq!(LoadShape::parse("sphere"), Some(LoadShape::Sphere));
+        assert_eq!(
+            LoadShape::parse("cylinder:3"),
+            Some(LoadShape::Cylinder { vertical_radius: 3 })
+        );
+
+        for name in [
+            "",
+            "ball",
+            "cube:1",
+            "cylinder",
+            "cylinder:",
+            "cylinder:-1",
+            "cylinder:x",
+        ] {
+            assert_eq!(LoadShape::parse(name), None, "{name:?}");
+        }
+    }
+
+    #[test]
+    fn areas_contain_their_chunks() {
+        let center = IVec3::new(10, -4, 7);
+        for (shape, count) in [
+            (LoadShape::Cube, 125),
+            (LoadShape::Sphere, 81),
+            (LoadShape::Cylinder { vertical_radius: 1 }, 63),
+        ] {
+            let area = LoadArea {
+                center,
+                ..LoadArea::new(2, shape)
+            };
+            assert_eq!(area.chunks().count(), count, "{shape:?}");
+            assert!(area.chunks().all(|chunk| area.contains(chunk)));
+
+            assert!(area.contains(center));
+            assert!(area.contains(center + IVec3::new(2, 0, 0)));
+            assert!(!area.contains(center + IVec3::new(3, 0, 0)));
+        }
+
+        let corner = center + IVec3::splat(2);
+        assert!(
+            LoadArea {
+                center,
+                ..LoadArea::new(2, LoadShape::Cube)
+            }
+            .contains(corner)
+        );
+        assert!(
+            !LoadArea {
+                center,
+                ..LoadArea::new(2, LoadShape::Sphere)
+            }
+            .contains(corner)
+        );
+
+        let cylinder = LoadArea {
+            center,
+            ..LoadArea::new(2, LoadShape::Cylinder { vertical_radius: 1 })
+        };
+        assert!(cylinder.contains(center + IVec3::new(2, 1, 1)));
+        assert!(!cylinder.contains(center + IVec3::new(0, 2, 0)));
+    }
+}
@@ -0,0 +1,238 @@
+use std::{cmp::Reverse, collections::BinaryHeap};
+
+use ahash::AHashSet;
+use glam::{IVec3, Vec3};
+
+use crate::{
+    chunk::CHUNK_SIZE,
+    frustum::{Aabb, Frustum},
+};
+
+/// how much further away chunks right behind the view count as, compared to
+/// the ones straight ahead
+const BEHIND_WEIGHT: f32 = 1.0;
+/// how much further away chunks outside the view frustum count as
+const OUTSIDE_FRUSTUM_WEIGHT: f32 = 1.5;
+/// chunks this close to the center always go by distance alone, the player
+/// needs them whichever way they're looking
+const NEARBY_DISTANCE: f32 = 1.5;
+/// the view has to turn by more than this (the cosine of about 15 degrees)
+/// before the queue is reordered
+const REORDER_ANGLE_COS: f32 = 0.966;
+
+/// Where the player is and what they can see, which decides the order
+/// chunks are loaded in.
+#[derive(Clone, Default)]
+pub struct LoadView {
+    /// the chunk the player is in
+    pub center: IVec3,
+    /// the direction the player is looking in, zero for no preference
+    pub direction: Vec3,
+    pub frustum: Option<Frustum>,
+}
+
+impl LoadView {
+    /// Lower loads sooner. Goes by the distance to the center, made longer
+    /// the further the chunk is from the view direction and when it is
+    /// outside the frustum.
+    pub fn priority(&self, chunk_position: IVec3) -> u32 {
+        let offset = (chunk_position - self.center).as_vec3();
+        let distance = offset.length();
+
+        let mut cost = distance;
+        if distance > NEARBY_DISTANCE {
+            let facing = offset.dot(self.direction) / distance;
+            cost *= 1.0 + BEHIND_WEIGHT * (1.0 - facing) / 2.0;
+
+            if let Some(frustum) = &self.frustum {
+                let min = (chunk_position * CHUNK_SIZE as i32).as_vec3();
+                if !frustum.contains_aabb(&Aabb::new(min, min + CHUNK_SIZE as f32)) {
+                    cost *= OUTSIDE_FRUSTUM_WEIGHT;
+                }
+            }
+        }
+
+        // sixteenths of a chunk are plenty to tell chunks apart
+        (cost * 16.0) as u32
+    }
+}
+
+/// Chunk positions waiting to be processed, handed out by [`LoadView`]
+/// priority.
+///
+/// Membership lives in a set, so checking for and removing positions doesn't
+/// touch the heap. Removed positions stay in the heap until they come up and
+/// get skipped, or the heap is rebuilt.
+#[derive(Default)]
+pub struct LoadQueue {
+    view: LoadView,
+    heap: BinaryHeap<Reverse<(u32, [i32; 3])>>,
+    members: AHashSet<IVec3>,
+}
+
+impl LoadQueue {
+    pub fn len(&self) -> usize {
+        self.members.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.members.is_empty()
+    }
+
+    pub fn contains(&self, position: &IVec3) -> bool {
+        self.members.contains(position)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &IVec3> {
+        self.members.iter()
+    }
+
+    /// Queues the position, unless it already is.
+    pub fn push(&mut self, position: IVec3) {
+        if self.members.insert(position) {
+            self.heap
+                .push(Reverse((self.view.priority(position), position.to_array())));
+        }
+    }
+
+    /// Takes out the position that should be processed next.
+    pub fn pop(&mut self) -> Option<IVec3> {
+        while let Some(Reverse((_, position))) = self.heap.pop() {
+            let position = IVec3::from_array(position);
+            if self.members.remove(&position) {
+                return Some(position);
+            }
+        }
+
+        None
+    }
+
+    pub fn remove(&mut self, position: &IVec3) -> bool {
+        let removed = self.members.remove(position);
+        self.compact();
+        removed
+    }
+
+    /// Keeps only the positions `keep` returns true for.
+    pub fn retain(&mut self, mut keep: impl FnMut(&IVec3) -> bool) {
+        self.members.retain(|position| keep(position));
+        self.compact();
+    }
+
+    pub fn view(&self) -> &LoadView {
+        &self.view
+    }
+
+    /// Switches to a new view, reordering the queue if the player moved to
+    /// another chunk or turned far enough for the order to matter.
+    pub fn set_view(&mut self, view: LoadView) {
+        let turned = view.direction.dot(self.view.direction) < REORDER_ANGLE_COS
+            && view.direction != self.view.direction;
+        if view.center != self.view.center || turned {
+            self.view = view;
+            self.rebuild();
+        }
+    }
+
+    /// Drops the removed positions from the heap once they make up most of it.
+    fn compact(&mut self) {
+        if self.heap.len() > 2 * self.members.len() {
+            self.rebuild();
+        }
+    }
+
+    fn rebuild(&mut self) {
+        self.heap = self
+            .members
+            .iter()
+            .map(|&position| Reverse((self.view.priority(position), position.to_array())))
+            .collect();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn drain(queue: &mut LoadQueue) -> Vec<IVec3> {
+        std::iter::from_fn(|| queue.pop()).collect()
+    }
+
+    #[test]
+    fn closer_chunks_come_first() {
+        let mut queue = LoadQueue::default();
+        for position in [
+            IVec3::new(0, 5, 0),
+            IVec3::new(-2, 0, 1),
+            IVec3::ZERO,
+            IVec3::new(3, 3, 3),
+            IVec3::new(0, 0, 1),
+        ] {
+            queue.push(position);
+        }
+        assert_eq!(queue.len(), 5);
+
+        let popped = drain(&mut queue);
+        assert_eq!(popped[0], IVec3::ZERO);
+        assert!(
+            popped
+                .windows(2)
+                .all(|pair| pair[0].length_squared() <= pair[1].length_squared())
+        );
+        assert!(queue.is_empty());
+    }
+
+    #[test]
+    fn chunks_in_view_come_first() {
+        let mut queue = LoadQueue::default();
+        let behind = IVec3::new(-4, 0, 0);
+        let beside = IVec3::new(0, 0, 4);
+        let ahead = IVec3::new(4, 0, 0);
+        for position in [behind, beside, ahead] {
+            queue.push(position);
+        }
+
+        queue.set_view(LoadView {
+            direction: Vec3::X,
+            ..LoadView::default()
+        });
+        assert_eq!(drain(&mut queue), [ahead, beside, behind]);
+
+        // turning around reorders what's already queued
+        for position in [behind, beside, ahead] {
+            queue.push(position);
+        }
+        queue.set_view(LoadView {
+            direction: Vec3::NEG_X,
+            ..LoadView::default()
+        });
+        assert_eq!(drain(&mut queue), [behind, beside, ahead]);
+    }
+
+    #[test]
+    fn removed_chunks_can_be_queued_again() {
+        let mut queue = LoadQueue::default();
+        for x in -3..=3 {
+            queue.push(IVec3::new(x, 0, 0));
+        }
+
+        queue.retain(|position| position.x >= 0);
+        assert_eq!(queue.len(), 4);
+        assert!(!queue.contains(&IVec3::new(-1, 0, 0)));
+        assert!(queue.remove(&IVec3::new(2, 0, 0)));
+        assert!(!queue.remove(&IVec3::new(2, 0, 0)));
+
+        queue.push(IVec3::new(2, 0, 0));
+        queue.push(IVec3::new(-1, 0, 0));
+        queue.push(IVec3::new(-1, 0, 0));
+        assert_eq!(queue.len(), 5);
+
+        let mut popped = drain(&mut queue);
+        popped.sort_by_key(|position| position.x);
+        assert_eq!(
+            popped,
+            (-1..=3).map(|x| IVec3::new(x, 0, 0)).collect::<Vec<_>>()
+        );
+        assert!(queue.is_empty());
+    }
+}
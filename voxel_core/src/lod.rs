@@ -0,0 +1,462 @@
+use std::ops::Range;
+
+use glam::IVec3;
+
+use crate::{
+    block::Block,
+    chunk::{CHUNK_SIZE, Chunk, ChunkMeshData, MeshingMode},
+    generator::WorldGenerator,
+    light::{LightChannel, MAX_LIGHT},
+    load_area::LoadArea,
+};
+
+/// The coarsest level of detail, where every cell covers 2^3 = 8 voxels per axis.
+pub const MAX_LOD_LEVEL: u32 = 3;
+
+/// A cube of 2^`level` chunks per axis, drawn as a single mesh of
+/// `CHUNK_SIZE`^3 cells that each cover 2^`level` voxels per axis.
+///
+/// Every level surrounds the one below it, with the chunks loaded at full
+/// detail as level 0, so the number of nodes per level stays the same while
+/// the distance they cover doubles.
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+pub struct LodNode {
+    /// chunk position of the lowest corner, a multiple of 2^`level`
+    pub position: IVec3,
+    pub level: u32,
+    pub hole: LodHole,
+}
+
+/// The chunks of a [`LodNode`] and the one chunk border around it that the
+/// finer levels draw instead.
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+pub enum LodHole {
+    /// a box of chunks, min inclusive and max exclusive, with an empty box
+    /// for nodes the finer levels don't reach
+    Box(IVec3, IVec3),
+    /// one bit per chunk of the 4^3 chunks around a level 1 node, set for the
+    /// ones loaded at full detail, which don't have to form a box
+    Loaded(u64),
+}
+
+/// Chunks per axis of a level 1 node and its border.
+const LEVEL_1_PADDED: i32 = 4;
+
+/// The chunks covered by everything up to the given level of detail around
+/// the load area, min inclusive and max exclusive. Level 0 is the box around
+/// the chunks loaded at full detail, coarser levels snap to their own node
+/// size so their nodes only change when the center moved far enough.
+pub fn lod_region(area: &LoadArea, level: u32) -> (IVec3, IVec3) {
+    if level == 0 {
+        return area.bounds();
+    }
+
+    let size = 1 << level;
+    let snapped = area.center.div_euclid(IVec3::splat(size)) * size;
+    // vertically the levels only need to reach a little further than the
+    // level below, the extra keeps each level around the previous one
+    // wherever the center is within the snapped node
+    let half_height = (area.vertical_radius() + 2 * size + size - 1) / size * size;
+    let half_extent = IVec3::new(area.radius * size, half_height, area.radius * size);
+
+    (snapped - half_extent, snapped + half_extent)
+}
+
+/// Every node needed to draw `levels` levels of detail beyond the chunks
+/// loaded in `area`, closest first. Needs a render distance of at least 2 for
+/// the levels to nest.
+pub fn lod_nodes(area: &LoadArea, levels: u32) -> Vec<LodNode> {
+    if area.radius < 2 {
+        return Vec::new();
+    }
+
+    let mut nodes = Vec::new();
+    for level in 1..=levels.min(MAX_LOD_LEVEL) {
+        let size = 1 << level;
+        let region = lod_region(area, level);
+        let inner = lod_region(area, level - 1);
+
+        for x in (region.0.x..region.1.x).step_by(size as usize) {
+            for y in (region.0.y..region.1.y).step_by(size as usize) {
+                for z in (region.0.z..region.1.z).step_by(size as usize) {
+                    let position = IVec3::new(x, y, z);
+                    let hole = if level == 1 {
+                        level_1_hole(area, position)
+                    } else {
+                        let (min, max) = intersect((position - 1, position + size + 1), inner);
+                        LodHole::Box(min, max)
+                    };
+                    let node = LodNode {
+                        position,
+                        level,
+                        hole,
+                    };
+
+                    let mut own_chunks = (0..size * size * size)
+                        .map(|i| position + IVec3::new(i % size, i / size % size, i / size / size));
+                    if !own_chunks.all(|chunk| node.is_hole(chunk)) {
+                        nodes.push(node);
+                    }
+                }
+            }
+        }
+    }
+
+    nodes.sort_by_key(|node| {
+        let center_of_node = node.position * 2 + node.size();
+        (center_of_node - area.center * 2).length_squared()
+    });
+    nodes
+}
+
+/// The loaded chunks around a level 1 node at `position`.
+fn level_1_hole(area: &LoadArea, position: IVec3) -> LodHole {
+    let mut loaded = 0;
+    for i in 0..LEVEL_1_PADDED.pow(3) {
+        let offset = IVec3::new(
+            i % LEVEL_1_PADDED,
+            i / LEVEL_1_PADDED % LEVEL_1_PADDED,
+            i / LEVEL_1_PADDED / LEVEL_1_PADDED,
+        );
+        if area.contains(position - 1 + offset) {
+            loaded |= 1 << i;
+        }
+    }
+
+    LodHole::Loaded(loaded)
+}
+
+/// The overlap of two boxes, with boxes that don't overlap all turning into
+/// the same empty box.
+fn intersect(a: (IVec3, IVec3), b: (IVec3, IVec3)) -> (IVec3, IVec3) {
+    let min = a.0.max(b.0);
+    let max = a.1.min(b.1);
+    if min.cmplt(max).all() {
+        (min, max)
+    } else {
+        (IVec3::ZERO, IVec3::ZERO)
+    }
+}
+
+impl LodNode {
+    /// Size of the node in chunks, and of every cell in voxels.
+    pub fn size(&self) -> i32 {
+        1 << self.level
+    }
+
+    pub fn world_position(&self) -> IVec3 {
+        self.position * CHUNK_SIZE as i32
+    }
+
+    /// Whether the chunk is drawn by a finer level instead of this node. Only
+    /// meaningful for the node and the one chunk border around it.
+    pub fn is_hole(&self, chunk_position: IVec3) -> bool {
+        match self.hole {
+            LodHole::Box(min, max) => {
+                chunk_position.cmpge(min).all() && chunk_position.cmplt(max).all()
+            }
+            LodHole::Loaded(loaded) => {
+                let offset = chunk_position - (self.position - 1);
+                if offset.cmplt(IVec3::ZERO).any()
+                    || offset.cmpge(IVec3::splat(LEVEL_1_PADDED)).any()
+                {
+                    return false;
+                }
+
+                let index = offset.x + LEVEL_1_PADDED * (offset.y + LEVEL_1_PADDED * offset.z);
+                loaded & (1 << index) != 0
+            }
+        }
+    }
+
+    /// Samples the world from the generator and meshes it, returning `None`
+    /// if there is nothing to draw. The mesh is in cell units, so it has to be
+    /// scaled up by [`LodNode::size`].
+    ///
+    /// Cells left to the finer levels count as air, so the faces bordering
+    /// them are drawn and cover the seams where the terrain of two levels
+    /// doesn't quite line up. The viewer is always on the side of the finer
+    /// level, so the finer level doesn't need to do the same.
+    ///
+    /// Only the generator is sampled, never the saved or loaded chunks, so
+    /// edits to the world don't show up at a distance until they're close
+    /// enough to be loaded at full detail.
+    pub fn generate_mesh(&self, generator: &dyn WorldGenerator) -> Option<ChunkMeshData> {
+        let mut samples = LodSamples::new(*self);
+        generator.generate_lod(&mut samples);
+
+        // the hole is made of whole chunks, which always line up with cells
+        let cells_per_chunk = CHUNK_SIZE as i32 / self.size();
+        let padded = self.size() + 2;
+        for i in 0..padded * padded * padded {
+            let chunk_position = self.position - 1
+                + IVec3::new(i % padded, i / padded % padded, i / padded / padded);
+            if !self.is_hole(chunk_position) {
+                continue;
+            }
+
+            let min = (chunk_position - self.position) * cells_per_chunk;
+            let max = min + cells_per_chunk;
+            let min = min.max(IVec3::splat(LodSamples::CELLS.start));
+            let max = max.min(IVec3::splat(LodSamples::CELLS.end));
+            for x in min.x..max.x {
+                for y in min.y..max.y {
+                    for z in min.z..max.z {
+                        samples.set(IVec3::new(x, y, z), Block::AIR);
+                    }
+                }
+            }
+        }
+
+        let first = samples.blocks[0];
+        if samples.blocks.iter().all(|&block| block == first) {
+            return None;
+        }
+
+        let chunk = samples.to_chunk(IVec3::ZERO);
+        let neighbors = [
+            IVec3::NEG_Z,
+            IVec3::Z,
+            IVec3::NEG_X,
+            IVec3::X,
+            IVec3::NEG_Y,
+            IVec3::Y,
+        ]
+        .map(|direction| samples.to_chunk(direction));
+
+        chunk
+            .generate_mesh(neighbors.each_ref().map(Some), MeshingMode::Greedy)
+            .0
+            .filter(|mesh_data| !mesh_data.is_empty())
+    }
+}
+
+/// The blocks of a [`LodNode`] as filled in by
+/// [`WorldGenerator::generate_lod`], including a one cell border around it
+/// used to cull the faces at its sides.
+pub struct LodSamples {
+    node: LodNode,
+    blocks: Vec<Block>,
+}
+
+impl LodSamples {
+    /// Cell coordinates along every axis, including the border.
+    pub const CELLS: Range<i32> = -1..CHUNK_SIZE as i32 + 1;
+
+    const WIDTH: usize = CHUNK_SIZE + 2;
+
+    pub fn new(node: LodNode) -> Self {
+        Self {
+            node,
+            blocks: vec![Block::AIR; Self::WIDTH * Self::WIDTH * Self::WIDTH],
+        }
+    }
+
+    pub fn node(&self) -> &LodNode {
+        &self.node
+    }
+
+    /// Size of every cell in voxels.
+    pub fn scale(&self) -> i32 {
+        self.node.size()
+    }
+
+    /// The voxel in the middle of a cell, which the whole cell takes after.
+    pub fn sample_position(&self, cell: IVec3) -> IVec3 {
+        self.node.world_position() + cell * self.scale() + self.scale() / 2
+    }
+
+    pub fn get(&self, cell: IVec3) -> Block {
+        self.blocks[Self::index(cell)]
+    }
+
+    pub fn set(&mut self, cell: IVec3, block: Block) {
+        self.blocks[Self::index(cell)] = block;
+    }
+
+    fn index(cell: IVec3) -> usize {
+        let cell = (cell + 1).as_uvec3();
+        Self::WIDTH * Self::WIDTH * cell.z as usize
+            + Self::WIDTH * cell.y as usize
+            + cell.x as usize
+    }
+
+    /// A fully sky lit chunk holding the cells `offset` chunks away from the
+    /// node, which for neighbors only includes the border layer.
+    fn to_chunk(&self, offset: IVec3) -> Chunk {
+        let mut chunk = Chunk::new(offset);
+        chunk.light.fill(LightChannel::Sky.pack(0, MAX_LIGHT));
+
+        let origin = offset * CHUNK_SIZE as i32;
+        let min = (IVec3::splat(Self::CELLS.start) - origin).max(IVec3::ZERO);
+        let max = (IVec3::splat(Self::CELLS.end) - origin).min(IVec3::splat(CHUNK_SIZE as i32));
+        for x in min.x..max.x {
+            for y in min.y..max.y {
+                for z in min.z..max.z {
+                    let local = IVec3::new(x, y, z);
+                    chunk.set_block(local, self.get(origin + local));
+                }
+            }
+        }
+
+        chunk
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ahash::AHashMap;
+
+    use super::*;
+    use crate::load_area::LoadShape;
+
+    #[test]
+    fn levels_cover_everything_around_the_loaded_chunks_once() {
+        for (center, radius, shape) in [
+            (IVec3::ZERO, 2, LoadShape::Cube),
+            (IVec3::new(5, -3, -11), 3, LoadShape::Sphere),
+            (
+                IVec3::new(-1, 7, 2),
+                4,
+                LoadShape::Cylinder { vertical_radius: 2 },
+            ),
+        ] {
+            let area = LoadArea {
+                center,
+                ..LoadArea::new(radius, shape)
+            };
+            let nodes = lod_nodes(&area, MAX_LOD_LEVEL);
+            let mut drawn = AHashMap::new();
+            for node in &nodes {
+                assert_eq!(
+                    node.position.rem_euclid(IVec3::splat(node.size())),
+                    IVec3::ZERO
+                );
+                for x in 0..node.size() {
+                    for y in 0..node.size() {
+                        for z in 0..node.size() {
+                            let chunk = node.position + IVec3::new(x, y, z);
+                            if !node.is_hole(chunk) {
+                                *drawn.entry(chunk).or_insert(0) += 1;
+                            }
+                        }
+                    }
+                }
+            }
+
+            let outer = lod_region(&area, MAX_LOD_LEVEL);
+            for x in outer.0.x..outer.1.x {
+                for y in outer.0.y..outer.1.y {
+                    for z in outer.0.z..outer.1.z {
+                        let chunk = IVec3::new(x, y, z);
+                        let expected = if area.contains(chunk) { 0 } else { 1 };
+                        assert_eq!(
+                            drawn.remove(&chunk).unwrap_or(0),
+                            expected,
+                            "{chunk} with {shape:?}"
+                        );
+                    }
+                }
+            }
+            assert!(drawn.is_empty());
+
+            assert_eq!(nodes.first().unwrap().level, 1);
+            assert_eq!(nodes.last().unwrap().level, MAX_LOD_LEVEL);
+        }
+
+        assert!(lod_nodes(&LoadArea::new(1, LoadShape::Cube), MAX_LOD_LEVEL).is_empty());
+    }
+
+    /// Stone below y = 0 and air above it, in distant samples only.
+    struct Ground;
+
+    impl WorldGenerator for Ground {
+        fn generate(&self, _chunk: &mut Chunk) {}
+
+        fn generate_lod(&self, samples: &mut LodSamples) {
+            for x in LodSamples::CELLS {
+                for y in LodSamples::CELLS {
+                    for z in LodSamples::CELLS {
+                        let cell = IVec3::new(x, y, z);
+                        if samples.sample_position(cell).y < 0 {
+                            samples.set(cell, Block::named("stone"));
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    fn node(position: IVec3, hole: LodHole) -> LodNode {
+        LodNode {
+            position,
+            level: 1,
+            hole,
+        }
+    }
+
+    /// The heights of every vertex of the mesh, in cells.
+    fn vertex_heights(node: LodNode) -> Vec<u32> {
+        let mesh = node.generate_mesh(&Ground).unwrap();
+        let mut heights = mesh
+            .vertices
+            .iter()
+            .map(|vertex| vertex.position().y)
+            .collect::<Vec<_>>();
+        heights.sort_unstable();
+        heights.dedup();
+        heights
+    }
+
+    #[test]
+    fn cells_take_after_the_voxel_in_their_middle() {
+        let samples = LodSamples::new(LodNode {
+            position: IVec3::new(4, -4, 8),
+            level: 2,
+            hole: LodHole::Box(IVec3::ZERO, IVec3::ZERO),
+        });
+        assert_eq!(samples.scale(), 4);
+        assert_eq!(
+            samples.sample_position(IVec3::ZERO),
+            IVec3::new(130, -126, 258)
+        );
+        assert_eq!(
+            samples.sample_position(IVec3::NEG_ONE),
+            IVec3::new(126, -130, 254)
+        );
+    }
+
+    #[test]
+    fn only_the_surface_is_meshed() {
+        let no_hole = LodHole::Box(IVec3::ZERO, IVec3::ZERO);
+        // underground including the border, and in the air above the ground
+        assert!(
+            node(IVec3::new(0, -4, 0), no_hole)
+                .generate_mesh(&Ground)
+                .is_none()
+        );
+        assert!(
+            node(IVec3::new(2, 0, -2), no_hole)
+                .generate_mesh(&Ground)
+                .is_none()
+        );
+
+        // the top of the node, where the border above is air
+        assert_eq!(vertex_heights(node(IVec3::new(0, -2, 0), no_hole)), [32]);
+    }
+
+    #[test]
+    fn holes_are_left_to_the_finer_levels() {
+        // the upper chunks of the node are drawn at full detail, so the
+        // ground shows up as the top of the lower ones
+        let hole = LodHole::Box(IVec3::new(-1, -1, -1), IVec3::new(3, 1, 3));
+        assert_eq!(vertex_heights(node(IVec3::new(0, -2, 0), hole)), [16]);
+
+        let whole_node = LodHole::Box(IVec3::new(-1, -3, -1), IVec3::new(3, 1, 3));
+        assert!(
+            node(IVec3::new(0, -2, 0), whole_node)
+                .generate_mesh(&Ground)
+                .is_none()
+        );
+    }
+}
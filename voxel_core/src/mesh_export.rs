@@ -0,0 +1,500 @@
+use std::{
+    borrow::Cow,
+    fmt::Write as _,
+    fs,
+    io::{BufWriter, Write},
+    path::Path,
+};
+
+use anyhow::{Context, bail};
+use glam::{IVec3, Vec2, Vec3};
+
+use crate::{
+    block::Block,
+    chunk::{CHUNK_SIZE, Chunk, FACE_NORMALS, MeshingMode},
+    chunk_manager::ChunkManager,
+};
+
+/// tiles along each side of the atlas, the same as in the shader
+const ATLAS_TILES: u32 = 16;
+
+/// The faces of a part of the world in world space, ready to be written out
+/// for other tools.
+///
+/// Every quad covers a single block face so its texture coordinates can map
+/// straight into one tile of the atlas.
+#[derive(Default)]
+pub struct ExportMesh {
+    pub positions: Vec<Vec3>,
+    pub normals: Vec<Vec3>,
+    /// texture coordinates into the atlas, with the origin in the top left
+    pub uvs: Vec<Vec2>,
+    /// triangles of opaque and cutout faces
+    pub indices: Vec<u32>,
+    /// triangles of translucent faces
+    pub translucent_indices: Vec<u32>,
+}
+
+impl ExportMesh {
+    /// Meshes every loaded chunk, or only the blocks inside the box between
+    /// two opposite corners. Blocks outside the box count as air so the
+    /// surface is closed where it is cut.
+    pub fn from_world(chunk_manager: &ChunkManager, region: Option<(IVec3, IVec3)>) -> Self {
+        let region = region.map(|(a, b)| (a.min(b), a.max(b)));
+        let chunk = |position: IVec3| {
+            let chunk = chunk_manager.chunk_map.get(&position)?;
+            Some(match region {
+                Some((min, max)) => mask_chunk(chunk, min, max),
+                None => Cow::Borrowed(chunk),
+            })
+        };
+
+        let mut chunk_positions = chunk_manager
+            .chunk_map
+            .keys()
+            .copied()
+            .filter(|&position| {
+                region.is_none_or(|(min, max)| {
+                    let chunk_min = position * CHUNK_SIZE as i32;
+                    let chunk_max = chunk_min + CHUNK_SIZE as i32 - 1;
+                    chunk_min.cmple(max).all() && chunk_max.cmpge(min).all()
+                })
+            })
+            .collect::<Vec<IVec3>>();
+        // the same world always gives the same file
+        chunk_positions.sort_by_key(|position| position.to_array());
+
+        let mut mesh = Self::default();
+        for position in chunk_positions {
+            let Some(center) = chunk(position) else {
+                continue;
+            };
+            let neighbors = FACE_NORMALS.map(|normal| chunk(position + normal));
+            let (Some(chunk_mesh), _) = center.generate_mesh(
+                neighbors.each_ref().map(|neighbor| neighbor.as_deref()),
+                MeshingMode::Naive,
+            ) else {
+                continue;
+            };
+
+            let base = mesh.positions.len() as u32;
+            for quad in chunk_mesh.vertices.chunks_exact(4) {
+                let face = quad[0].face();
+                let tile = quad[0].texture() as u32;
+                let tile = Vec2::new((tile % ATLAS_TILES) as f32, (tile / ATLAS_TILES) as f32);
+
+                let face_uvs = quad
+                    .iter()
+                    .map(|vertex| face_uv(face, vertex.position().as_vec3()))
+                    .collect::<Vec<Vec2>>();
+                let min_uv = face_uvs.iter().copied().reduce(Vec2::min).unwrap();
+                for (vertex, &face_uv) in quad.iter().zip(&face_uvs) {
+                    let lowered = vertex.lowered() as f32 / 16.0;
+                    mesh.positions.push(
+                        (center.world_position + vertex.position().as_ivec3()).as_vec3()
+                            - Vec3::new(0.0, lowered, 0.0),
+                    );
+                    mesh.normals.push(FACE_NORMALS[face].as_vec3());
+                    mesh.uvs
+                        .push((tile + face_uv - min_uv) / ATLAS_TILES as f32);
+                }
+            }
+            mesh.indices
+                .extend(chunk_mesh.indices.iter().map(|index| base + index));
+            mesh.translucent_indices.extend(
+                chunk_mesh
+                    .translucent_indices
+                    .iter()
+                    .map(|index| base + index),
+            );
+        }
+
+        mesh
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.indices.is_empty() && self.translucent_indices.is_empty()
+    }
+
+    /// Writes the mesh as Wavefront OBJ, glTF or binary glTF depending on
+    /// the extension of `path`, textured with the atlas at `atlas_path`.
+    pub fn save(&self, path: impl AsRef<Path>, atlas_path: impl AsRef<Path>) -> anyhow::Result<()> {
+        let path = path.as_ref();
+        let atlas_path = atlas_path.as_ref();
+        if self.is_empty() {
+            bail!("there are no faces to export");
+        }
+        if let Some(directory) = path.parent() {
+            fs::create_dir_all(directory)?;
+        }
+
+        match path.extension().and_then(|extension| extension.to_str()) {
+            Some("obj") => self.save_obj(path, atlas_path),
+            Some("gltf") => self.save_gltf(path, atlas_path, false),
+            Some("glb") => self.save_gltf(path, atlas_path, true),
+            _ => bail!("unknown mesh format {path:?}, expected .obj, .gltf or .glb"),
+        }
+        .with_context(|| format!("unable to export {path:?}"))
+    }
+
+    /// Writes the OBJ file with a material library and a copy of the atlas
+    /// next to it, named after it.
+    fn save_obj(&self, path: &Path, atlas_path: &Path) -> anyhow::Result<()> {
+        let stem = path
+            .file_stem()
+            .and_then(|stem| stem.to_str())
+            .context("invalid file name")?;
+        let material_name = format!("{stem}.mtl");
+        let atlas_name = format!("{stem}_atlas.png");
+        fs::copy(atlas_path, path.with_file_name(&atlas_name))
+            .with_context(|| format!("unable to copy the atlas {atlas_path:?}"))?;
+        fs::write(
+            path.with_file_name(&material_name),
+            format!(
+                "newmtl blocks\nKd 1 1 1\nmap_Kd {atlas_name}\nmap_d {atlas_name}\n\n\
+                 newmtl translucent\nKd 1 1 1\nmap_Kd {atlas_name}\nmap_d {atlas_name}\n"
+            ),
+        )?;
+
+        let mut file = BufWriter::new(fs::File::create(path)?);
+        writeln!(file, "mtllib {material_name}")?;
+        for position in &self.positions {
+            writeln!(file, "v {} {} {}", position.x, position.y, position.z)?;
+        }
+        for normal in &self.normals {
+            writeln!(file, "vn {} {} {}", normal.x, normal.y, normal.z)?;
+        }
+        // OBJ puts the origin of texture coordinates in the bottom left
+        for uv in &self.uvs {
+            writeln!(file, "vt {} {}", uv.x, 1.0 - uv.y)?;
+        }
+
+        for (material, indices) in [
+            ("blocks", &self.indices),
+            ("translucent", &self.translucent_indices),
+        ] {
+            if indices.is_empty() {
+                continue;
+            }
+            writeln!(file, "g {material}\nusemtl {material}")?;
+            for triangle in indices.chunks_exact(3) {
+                let [a, b, c] = [triangle[0] + 1, triangle[1] + 1, triangle[2] + 1];
+                writeln!(file, "f {a}/{a}/{a} {b}/{b}/{b} {c}/{c}/{c}")?;
+            }
+        }
+        file.flush()?;
+
+        Ok(())
+    }
+
+    /// Writes a glTF 2.0 file with the atlas embedded, either as a JSON file
+    /// with the data inlined as base64 or as a binary `.glb`.
+    fn save_gltf(&self, path: &Path, atlas_path: &Path, binary: bool) -> anyhow::Result<()> {
+        let atlas = fs::read(atlas_path)
+            .with_context(|| format!("unable to read the atlas {atlas_path:?}"))?;
+
+        let mut buffer = Vec::new();
+        let mut views = Vec::new();
+        let mut add_view = |bytes: &[u8], target: Option<u32>| {
+            views.push((buffer.len(), bytes.len(), target));
+            buffer.extend_from_slice(bytes);
+            // every view starts aligned to 4 bytes
+            buffer.resize(buffer.len().next_multiple_of(4), 0);
+            views.len() - 1
+        };
+        const ARRAY_BUFFER: Option<u32> = Some(34962);
+        const ELEMENT_ARRAY_BUFFER: Option<u32> = Some(34963);
+        let positions = add_view(bytemuck::cast_slice(&self.positions), ARRAY_BUFFER);
+        let normals = add_view(bytemuck::cast_slice(&self.normals), ARRAY_BUFFER);
+        let uvs = add_view(bytemuck::cast_slice(&self.uvs), ARRAY_BUFFER);
+        // glTF doesn't allow empty views, so there's none for a kind of face
+        // the mesh doesn't have
+        let mut add_indices = |indices: &[u32]| {
+            (!indices.is_empty())
+                .then(|| add_view(bytemuck::cast_slice(indices), ELEMENT_ARRAY_BUFFER))
+        };
+        let indices = add_indices(&self.indices);
+        let translucent_indices = add_indices(&self.translucent_indices);
+        let image = add_view(&atlas, None);
+
+        let min = self.positions.iter().copied().reduce(Vec3::min).unwrap();
+        let max = self.positions.iter().copied().reduce(Vec3::max).unwrap();
+        let vertex_count = self.positions.len();
+        let mut accessors = vec![
+            format!(
+                r#"{{"bufferView":{positions},"componentType":5126,"count":{vertex_count},"type":"VEC3","min":[{},{},{}],"max":[{},{},{}]}}"#,
+                min.x, min.y, min.z, max.x, max.y, max.z
+            ),
+            format!(
+                r#"{{"bufferView":{normals},"componentType":5126,"count":{vertex_count},"type":"VEC3"}}"#
+            ),
+            format!(
+                r#"{{"bufferView":{uvs},"componentType":5126,"count":{vertex_count},"type":"VEC2"}}"#
+            ),
+        ];
+
+        // cutout blocks like leaves are cut at the same alpha as the shader
+        // does, translucent ones like water are blended
+        let mut primitives = Vec::new();
+        for (view, indices, material) in [
+            (indices, &self.indices, 0),
+            (translucent_indices, &self.translucent_indices, 1),
+        ] {
+            let Some(view) = view else {
+                continue;
+            };
+            accessors.push(format!(
+                r#"{{"bufferView":{view},"componentType":5125,"count":{},"type":"SCALAR"}}"#,
+                indices.len()
+            ));
+            primitives.push(format!(
+                r#"{{"attributes":{{"POSITION":0,"NORMAL":1,"TEXCOORD_0":2}},"indices":{},"material":{material}}}"#,
+                accessors.len() - 1
+            ));
+        }
+
+        let buffer_uri = if binary {
+            String::new()
+        } else {
+            format!(
+                r#","uri":"data:application/octet-stream;base64,{}""#,
+                base64(&buffer)
+            )
+        };
+        let mut json = String::new();
+        write!(
+            json,
+            concat!(
+                r#"{{"asset":{{"version":"2.0","generator":"voxel_engine"}},"#,
+                r#""scene":0,"scenes":[{{"nodes":[0]}}],"nodes":[{{"mesh":0}}],"#,
+                r#""meshes":[{{"primitives":[{primitives}]}}],"#,
+                r#""materials":["#,
+                r#"{{"name":"blocks","pbrMetallicRoughness":{{"baseColorTexture":{{"index":0}},"metallicFactor":0,"roughnessFactor":1}},"alphaMode":"MASK","alphaCutoff":0.5}},"#,
+                r#"{{"name":"translucent","pbrMetallicRoughness":{{"baseColorTexture":{{"index":0}},"metallicFactor":0,"roughnessFactor":1}},"alphaMode":"BLEND"}}],"#,
+                r#""textures":[{{"sampler":0,"source":0}}],"#,
+                r#""samplers":[{{"magFilter":9728,"minFilter":9728}}],"#,
+                r#""images":[{{"bufferView":{image},"mimeType":"image/png"}}],"#,
+                r#""accessors":[{accessors}],"#,
+                r#""bufferViews":[{views}],"#,
+                r#""buffers":[{{"byteLength":{length}{buffer_uri}}}]}}"#,
+            ),
+            primitives = primitives.join(","),
+            image = image,
+            accessors = accessors.join(","),
+            views = views
+                .iter()
+                .map(|(offset, length, target)| {
+                    let target =
+                        target.map_or(String::new(), |target| format!(r#","target":{target}"#));
+                    format!(r#"{{"buffer":0,"byteOffset":{offset},"byteLength":{length}{target}}}"#)
+                })
+                .collect::<Vec<String>>()
+                .join(","),
+            length = buffer.len(),
+            buffer_uri = buffer_uri,
+        )?;
+
+        if !binary {
+            fs::write(path, json)?;
+            return Ok(());
+        }
+
+        // a 12 byte header, then the JSON padded with spaces and the buffer
+        // padded with zeros, each in a chunk of its own
+        let mut json = json.into_bytes();
+        json.resize(json.len().next_multiple_of(4), b' ');
+        let mut file = BufWriter::new(fs::File::create(path)?);
+        file.write_all(b"glTF")?;
+        file.write_all(&2u32.to_le_bytes())?;
+        file.write_all(&((12 + 8 + json.len() + 8 + buffer.len()) as u32).to_le_bytes())?;
+        file.write_all(&(json.len() as u32).to_le_bytes())?;
+        file.write_all(b"JSON")?;
+        file.write_all(&json)?;
+        file.write_all(&(buffer.len() as u32).to_le_bytes())?;
+        file.write_all(b"BIN\0")?;
+        file.write_all(&buffer)?;
+        file.flush()?;
+
+        Ok(())
+    }
+}
+
+/// A copy of the chunk with every block outside the box between `min` and
+/// `max` turned to air, or the chunk itself if it lies fully inside.
+fn mask_chunk(chunk: &Chunk, min: IVec3, max: IVec3) -> Cow<'_, Chunk> {
+    let chunk_max = chunk.world_position + CHUNK_SIZE as i32 - 1;
+    if chunk.world_position.cmpge(min).all() && chunk_max.cmple(max).all() {
+        return Cow::Borrowed(chunk);
+    }
+
+    let mut masked = chunk.clone();
+    for z in 0..CHUNK_SIZE as i32 {
+        for y in 0..CHUNK_SIZE as i32 {
+            for x in 0..CHUNK_SIZE as i32 {
+                let local = IVec3::new(x, y, z);
+                let world = chunk.world_position + local;
+                if world.cmplt(min).any() || world.cmpgt(max).any() {
+                    masked.set_block(local, Block::AIR);
+                }
+            }
+        }
+    }
+
+    Cow::Owned(masked)
+}
+
+/// The position of a vertex along its face, oriented like the texture
+/// coordinates in the shader so blocks look the same as in the engine.
+fn face_uv(face: usize, position: Vec3) -> Vec2 {
+    match face {
+        0 => Vec2::new(-position.x, -position.y),
+        1 => Vec2::new(position.x, -position.y),
+        2 => Vec2::new(position.z, -position.y),
+        3 => Vec2::new(-position.z, -position.y),
+        4 => Vec2::new(-position.x, position.z),
+        _ => Vec2::new(-position.x, -position.z),
+    }
+}
+
+fn base64(bytes: &[u8]) -> String {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+    let mut encoded = String::with_capacity(bytes.len().div_ceil(3) * 4);
+    for group in bytes.chunks(3) {
+        let value = (group[0] as u32) << 16
+            | (*group.get(1).unwrap_or(&0) as u32) << 8
+            | *group.get(2).unwrap_or(&0) as u32;
+        for i in 0..4 {
+            if i <= group.len() {
+                encoded.push(ALPHABET[(value >> (18 - 6 * i)) as usize & 0x3F] as char);
+            } else {
+                encoded.push('=');
+            }
+        }
+    }
+
+    encoded
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::generator::generator_from_name;
+
+    /// A world of one chunk holding a 4 by 3 slab of stone, and an ice
+    /// block if `ice` is set.
+    fn slab_world(ice: bool) -> ChunkManager {
+        let mut chunk_manager = ChunkManager::new(1, generator_from_name("void", 0).unwrap());
+        let mut chunk = Chunk::new(IVec3::ZERO);
+        for x in 0..4 {
+            for z in 0..3 {
+                chunk.set_block(IVec3::new(x, 0, z), Block::named("stone"));
+            }
+        }
+        if ice {
+            chunk.set_block(IVec3::new(10, 0, 10), Block::named("ice"));
+        }
+        chunk.compute_initial_light(true);
+        chunk_manager.chunk_map.insert(chunk.position, chunk);
+        chunk_manager
+    }
+
+    fn temp_directory(name: &str) -> std::path::PathBuf {
+        let directory =
+            std::env::temp_dir().join(format!("voxel_mesh_export_{name}_{}", std::process::id()));
+        let _ = fs::remove_dir_all(&directory);
+        fs::create_dir_all(&directory).unwrap();
+        directory
+    }
+
+    #[test]
+    fn every_face_maps_into_its_tile() {
+        let mesh = ExportMesh::from_world(&slab_world(false), None);
+        // the top and bottom of every block and the sides around the slab
+        let quads = 12 + 12 + 2 * (4 + 3);
+        assert_eq!(mesh.positions.len(), quads * 4);
+        assert_eq!(mesh.normals.len(), quads * 4);
+        assert_eq!(mesh.uvs.len(), quads * 4);
+        assert_eq!(mesh.indices.len(), quads * 6);
+        assert!(mesh.translucent_indices.is_empty());
+
+        let stone = Block::named("stone");
+        let tile_size = 1.0 / ATLAS_TILES as f32;
+        for (normals, uvs) in mesh.normals.chunks_exact(4).zip(mesh.uvs.chunks_exact(4)) {
+            let face = FACE_NORMALS
+                .iter()
+                .position(|normal| normal.as_vec3() == normals[0])
+                .unwrap();
+            let tile = stone.texture(face) as u32;
+            let tile_min =
+                Vec2::new((tile % ATLAS_TILES) as f32, (tile / ATLAS_TILES) as f32) * tile_size;
+
+            let min = uvs.iter().copied().reduce(Vec2::min).unwrap();
+            let max = uvs.iter().copied().reduce(Vec2::max).unwrap();
+            assert!(min.abs_diff_eq(tile_min, 1e-6), "face {face}: {min}");
+            assert!(
+                max.abs_diff_eq(tile_min + tile_size, 1e-6),
+                "face {face}: {max}"
+            );
+        }
+
+        // cutting out a region keeps the surface closed
+        let region = ExportMesh::from_world(
+            &slab_world(false),
+            Some((IVec3::new(0, 0, 0), IVec3::new(1, 5, 0))),
+        );
+        assert_eq!(region.positions.len(), 10 * 4);
+    }
+
+    #[test]
+    fn meshes_are_written_out() {
+        let directory = temp_directory("written");
+        let atlas = directory.join("atlas.png");
+        fs::write(&atlas, b"not really a png").unwrap();
+
+        let mesh = ExportMesh::from_world(&slab_world(false), None);
+        mesh.save(directory.join("slab.obj"), &atlas).unwrap();
+        let obj = fs::read_to_string(directory.join("slab.obj")).unwrap();
+        let lines = |prefix: &str| obj.lines().filter(|line| line.starts_with(prefix)).count();
+        assert_eq!(lines("v "), mesh.positions.len());
+        assert_eq!(lines("vt "), mesh.uvs.len());
+        assert_eq!(lines("vn "), mesh.normals.len());
+        assert_eq!(lines("f "), mesh.indices.len() / 3);
+        assert!(!obj.contains("usemtl translucent"));
+        assert!(directory.join("slab.mtl").exists());
+        assert!(directory.join("slab_atlas.png").exists());
+
+        // without translucent faces there is no view for them either, glTF
+        // doesn't allow empty ones
+        mesh.save(directory.join("slab.gltf"), &atlas).unwrap();
+        let gltf = fs::read_to_string(directory.join("slab.gltf")).unwrap();
+        assert!(!gltf.contains(r#""byteLength":0"#));
+        assert_eq!(gltf.matches(r#"{"buffer":0,"#).count(), 5);
+        assert_eq!(gltf.matches(r#""type":"SCALAR""#).count(), 1);
+        assert!(gltf.contains(&format!(
+            r#""count":{},"type":"SCALAR""#,
+            mesh.indices.len()
+        )));
+
+        let mesh = ExportMesh::from_world(&slab_world(true), None);
+        assert_eq!(mesh.translucent_indices.len(), 6 * 6);
+        mesh.save(directory.join("slab.glb"), &atlas).unwrap();
+        let glb = fs::read(directory.join("slab.glb")).unwrap();
+        assert_eq!(&glb[..4], b"glTF");
+        assert_eq!(
+            u32::from_le_bytes(glb[8..12].try_into().unwrap()) as usize,
+            glb.len()
+        );
+        let json_length = u32::from_le_bytes(glb[12..16].try_into().unwrap()) as usize;
+        let json = std::str::from_utf8(&glb[20..20 + json_length]).unwrap();
+        assert_eq!(json.matches(r#"{"buffer":0,"#).count(), 6);
+        assert_eq!(json.matches(r#""type":"SCALAR""#).count(), 2);
+
+        assert!(
+            ExportMesh::default()
+                .save(directory.join("empty.obj"), &atlas)
+                .is_err()
+        );
+        fs::remove_dir_all(directory).unwrap();
+    }
+}
@@ -0,0 +1,159 @@
+use ahash::AHashMap;
+use anyhow::{Context, bail};
+
+/// Compounds and lists can't nest deeper than this, which keeps a malicious
+/// file from overflowing the stack.
+const MAX_DEPTH: usize = 512;
+
+/// A value of Minecraft's Named Binary Tag format, which Sponge schematics
+/// are stored in.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Tag {
+    Byte(i8),
+    Short(i16),
+    Int(i32),
+    Long(i64),
+    Float(f32),
+    Double(f64),
+    ByteArray(Vec<u8>),
+    String(String),
+    List(Vec<Tag>),
+    Compound(AHashMap<String, Tag>),
+    IntArray(Vec<i32>),
+    LongArray(Vec<i64>),
+}
+
+impl Tag {
+    /// The value of `name` in a compound.
+    pub fn get(&self, name: &str) -> Option<&Tag> {
+        match self {
+            Self::Compound(entries) => entries.get(name),
+            _ => None,
+        }
+    }
+
+    /// The value of a whole number of any width.
+    pub fn as_int(&self) -> Option<i64> {
+        match *self {
+            Self::Byte(value) => Some(value as i64),
+            Self::Short(value) => Some(value as i64),
+            Self::Int(value) => Some(value as i64),
+            Self::Long(value) => Some(value),
+            _ => None,
+        }
+    }
+}
+
+/// Reads an uncompressed NBT document, returning the name of the root tag
+/// and the tag itself.
+pub fn read(bytes: &[u8]) -> anyhow::Result<(String, Tag)> {
+    let mut reader = Reader(bytes);
+    let id = reader.u8()?;
+    if id != 10 {
+        bail!("the root tag isn't a compound");
+    }
+    let name = reader.string()?;
+    let tag = reader.payload(id, 0)?;
+
+    Ok((name, tag))
+}
+
+/// Reads big endian values one after the other.
+struct Reader<'a>(&'a [u8]);
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, length: usize) -> anyhow::Result<&'a [u8]> {
+        if self.0.len() < length {
+            bail!("NBT data ends early");
+        }
+        let (bytes, rest) = self.0.split_at(length);
+        self.0 = rest;
+        Ok(bytes)
+    }
+
+    fn array<const N: usize>(&mut self) -> anyhow::Result<[u8; N]> {
+        Ok(self.take(N)?.try_into().unwrap())
+    }
+
+    fn u8(&mut self) -> anyhow::Result<u8> {
+        Ok(self.take(1)?[0])
+    }
+
+    /// The length of an array or list, which may not claim more elements
+    /// than there are bytes left.
+    fn length(&mut self, element_size: usize) -> anyhow::Result<usize> {
+        let length = i32::from_be_bytes(self.array()?);
+        let length = usize::try_from(length).context("negative NBT length")?;
+        if length * element_size.max(1) > self.0.len() {
+            bail!("NBT length {length} runs past the end");
+        }
+        Ok(length)
+    }
+
+    fn string(&mut self) -> anyhow::Result<String> {
+        let length = u16::from_be_bytes(self.array()?) as usize;
+        // Java writes a modified UTF-8, which only differs for characters
+        // block names don't use
+        Ok(String::from_utf8_lossy(self.take(length)?).into_owned())
+    }
+
+    fn payload(&mut self, id: u8, depth: usize) -> anyhow::Result<Tag> {
+        if depth > MAX_DEPTH {
+            bail!("NBT nested too deep");
+        }
+
+        let tag = match id {
+            1 => Tag::Byte(self.u8()? as i8),
+            2 => Tag::Short(i16::from_be_bytes(self.array()?)),
+            3 => Tag::Int(i32::from_be_bytes(self.array()?)),
+            4 => Tag::Long(i64::from_be_bytes(self.array()?)),
+            5 => Tag::Float(f32::from_be_bytes(self.array()?)),
+            6 => Tag::Double(f64::from_be_bytes(self.array()?)),
+            7 => {
+                let length = self.length(1)?;
+                Tag::ByteArray(self.take(length)?.to_vec())
+            }
+            8 => Tag::String(self.string()?),
+            9 => {
+                let element_id = self.u8()?;
+                let length = self.length(0)?;
+                let mut elements = Vec::with_capacity(length.min(1024));
+                for _ in 0..length {
+                    elements.push(self.payload(element_id, depth + 1)?);
+                }
+                Tag::List(elements)
+            }
+            10 => {
+                let mut entries = AHashMap::new();
+                loop {
+                    let id = self.u8()?;
+                    if id == 0 {
+                        break;
+                    }
+                    let name = self.string()?;
+                    entries.insert(name, self.payload(id, depth + 1)?);
+                }
+                Tag::Compound(entries)
+            }
+            11 => {
+                let length = self.length(4)?;
+                Tag::IntArray(
+                    (0..length)
+                        .map(|_| Ok(i32::from_be_bytes(self.array()?)))
+                        .collect::<anyhow::Result<_>>()?,
+                )
+            }
+            12 => {
+                let length = self.length(8)?;
+                Tag::LongArray(
+                    (0..length)
+                        .map(|_| Ok(i64::from_be_bytes(self.array()?)))
+                        .collect::<anyhow::Result<_>>()?,
+                )
+            }
+            _ => bail!("unknown NBT tag {id}"),
+        };
+
+        Ok(tag)
+    }
+}
@@ -0,0 +1,632 @@
+use std::{
+    io::{self, BufReader, BufWriter, Read, Write},
+    net::{Shutdown, SocketAddr, TcpStream},
+    sync::mpsc::{self, Receiver, RecvTimeoutError, Sender, TryRecvError},
+    thread::JoinHandle,
+    time::Duration,
+};
+
+use anyhow::{Context, bail};
+use glam::{IVec3, Vec3};
+
+use crate::load_area::LoadShape;
+
+/// the port servers listen on unless told otherwise
+pub const DEFAULT_PORT: u16 = 24650;
+
+/// Bumped whenever a message changes, clients and servers only talk to the
+/// same version.
+pub const PROTOCOL_VERSION: u16 = 3;
+
+/// the largest message either side accepts, far more than a chunk takes
+const MAX_MESSAGE_SIZE: usize = 16 * 1024 * 1024;
+/// how long writing to the other side may stall before it counts as gone
+const WRITE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// What a server tells a player that just joined.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Welcome {
+    pub player_id: u32,
+    /// the seed and generator of the world, so the client can build the
+    /// levels of detail beyond the chunks it's sent on its own
+    pub seed: u32,
+    pub generator: String,
+    /// the area of chunks the server sends around the player, which the
+    /// client keeps loaded
+    pub render_distance: i32,
+    pub load_shape: LoadShape,
+    pub spawn: Vec3,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum ClientMessage {
+    /// the first message of every client, with the furthest it loads chunks
+    Hello {
+        version: u16,
+        name: String,
+        render_distance: i32,
+    },
+    Position {
+        position: Vec3,
+        yaw: f32,
+        pitch: f32,
+    },
+    /// asks to change a block, which the server may refuse
+    SetBlock { position: IVec3, block: String },
+    /// chunks the client dropped, which the server sends again once the
+    /// player comes back to them
+    UnloadedChunks(Vec<IVec3>),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum ServerMessage {
+    Welcome(Welcome),
+    /// the blocks of a chunk, compressed like in a world save
+    Chunk {
+        position: IVec3,
+        data: Vec<u8>,
+    },
+    BlockChanged {
+        position: IVec3,
+        block: String,
+    },
+    PlayerJoined {
+        id: u32,
+        name: String,
+    },
+    PlayerMoved {
+        id: u32,
+        position: Vec3,
+        yaw: f32,
+        pitch: f32,
+    },
+    PlayerLeft {
+        id: u32,
+    },
+    /// the time of day, sent every now and then to keep the clocks together
+    Time {
+        ticks: u32,
+    },
+    /// moves the player, who can't walk there on their own
+    Teleport {
+        position: Vec3,
+    },
+    /// the last message before the server closes the connection
+    Disconnect {
+        reason: String,
+    },
+}
+
+/// A message that can be sent over a [`Connection`]. Every field is written
+/// out in little endian after a tag byte telling the variant apart.
+pub trait Message: Sized + Send + 'static {
+    fn encode(&self, out: &mut Vec<u8>);
+    fn decode(bytes: &[u8]) -> anyhow::Result<Self>;
+}
+
+impl Message for ClientMessage {
+    fn encode(&self, out: &mut Vec<u8>) {
+        match self {
+            Self::Hello {
+                version,
+                name,
+                render_distance,
+            } => {
+                out.push(0);
+                out.extend_from_slice(&version.to_le_bytes());
+                put_string(out, name);
+                put_u32(out, *render_distance as u32);
+            }
+            Self::Position {
+                position,
+                yaw,
+                pitch,
+            } => {
+                out.push(1);
+                put_vec3(out, *position);
+                put_f32(out, *yaw);
+                put_f32(out, *pitch);
+            }
+            Self::SetBlock { position, block } => {
+                out.push(2);
+                put_ivec3(out, *position);
+                put_string(out, block);
+            }
+            Self::UnloadedChunks(positions) => {
+                out.push(3);
+                put_u32(out, positions.len() as u32);
+                for &position in positions {
+                    put_ivec3(out, position);
+                }
+            }
+        }
+    }
+
+    fn decode(bytes: &[u8]) -> anyhow::Result<Self> {
+        let mut reader = Reader(bytes);
+        let message = match reader.u8()? {
+            0 => Self::Hello {
+                version: u16::from_le_bytes(reader.take(2)?.try_into().unwrap()),
+                name: reader.string()?,
+                render_distance: reader.u32()? as i32,
+            },
+            1 => Self::Position {
+                position: reader.vec3()?,
+                yaw: reader.f32()?,
+                pitch: reader.f32()?,
+            },
+            2 => Self::SetBlock {
+                position: reader.ivec3()?,
+                block: reader.string()?,
+            },
+            3 => {
+                let count = reader.u32()? as usize;
+                // every position takes 12 bytes, which bounds the count
+                if count > reader.0.len() / 12 {
+                    bail!("too many unloaded chunks");
+                }
+                Self::UnloadedChunks(
+                    (0..count)
+                        .map(|_| reader.ivec3())
+                        .collect::<Result<_, _>>()?,
+                )
+            }
+            tag => bail!("unknown client message {tag}"),
+        };
+        reader.finish()?;
+
+        Ok(message)
+    }
+}
+
+impl Message for ServerMessage {
+    fn encode(&self, out: &mut Vec<u8>) {
+        match self {
+            Self::Welcome(welcome) => {
+                out.push(0);
+                put_u32(out, welcome.player_id);
+                put_u32(out, welcome.seed);
+                put_string(out, &welcome.generator);
+                put_u32(out, welcome.render_distance as u32);
+                match welcome.load_shape {
+                    LoadShape::Cube => out.push(0),
+                    LoadShape::Sphere => out.push(1),
+                    LoadShape::Cylinder { vertical_radius } => {
+                        out.push(2);
+                        put_u32(out, vertical_radius as u32);
+                    }
+                }
+                put_vec3(out, welcome.spawn);
+            }
+            Self::Chunk { position, data } => {
+                out.push(1);
+                put_ivec3(out, *position);
+                put_u32(out, data.len() as u32);
+                out.extend_from_slice(data);
+            }
+            Self::BlockChanged { position, block } => {
+                out.push(2);
+                put_ivec3(out, *position);
+                put_string(out, block);
+            }
+            Self::PlayerJoined { id, name } => {
+                out.push(3);
+                put_u32(out, *id);
+                put_string(out, name);
+            }
+            Self::PlayerMoved {
+                id,
+                position,
+                yaw,
+                pitch,
+            } => {
+                out.push(4);
+                put_u32(out, *id);
+                put_vec3(out, *position);
+                put_f32(out, *yaw);
+                put_f32(out, *pitch);
+            }
+            Self::PlayerLeft { id } => {
+                out.push(5);
+                put_u32(out, *id);
+            }
+            Self::Disconnect { reason } => {
+                out.push(6);
+                put_string(out, reason);
+            }
+            Self::Time { ticks } => {
+                out.push(7);
+                put_u32(out, *ticks);
+            }
+            Self::Teleport { position } => {
+                out.push(8);
+                put_vec3(out, *position);
+            }
+        }
+    }
+
+    fn decode(bytes: &[u8]) -> anyhow::Result<Self> {
+        let mut reader = Reader(bytes);
+        let message = match reader.u8()? {
+            0 => Self::Welcome(Welcome {
+                player_id: reader.u32()?,
+                seed: reader.u32()?,
+                generator: reader.string()?,
+                render_distance: reader.u32()? as i32,
+                load_shape: match reader.u8()? {
+                    0 => LoadShape::Cube,
+                    1 => LoadShape::Sphere,
+                    2 => LoadShape::Cylinder {
+                        vertical_radius: reader.u32()? as i32,
+                    },
+                    shape => bail!("unknown load shape {shape}"),
+                },
+                spawn: reader.vec3()?,
+            }),
+            1 => {
+                let position = reader.ivec3()?;
+                let length = reader.u32()? as usize;
+                Self::Chunk {
+                    position,
+                    data: reader.take(length)?.to_vec(),
+                }
+            }
+            2 => Self::BlockChanged {
+                position: reader.ivec3()?,
+                block: reader.string()?,
+            },
+            3 => Self::PlayerJoined {
+                id: reader.u32()?,
+                name: reader.string()?,
+            },
+            4 => Self::PlayerMoved {
+                id: reader.u32()?,
+                position: reader.vec3()?,
+                yaw: reader.f32()?,
+                pitch: reader.f32()?,
+            },
+            5 => Self::PlayerLeft { id: reader.u32()? },
+            6 => Self::Disconnect {
+                reason: reader.string()?,
+            },
+            7 => Self::Time {
+                ticks: reader.u32()?,
+            },
+            8 => Self::Teleport {
+                position: reader.vec3()?,
+            },
+            tag => bail!("unknown server message {tag}"),
+        };
+        reader.finish()?;
+
+        Ok(message)
+    }
+}
+
+fn put_u32(out: &mut Vec<u8>, value: u32) {
+    out.extend_from_slice(&value.to_le_bytes());
+}
+
+fn put_f32(out: &mut Vec<u8>, value: f32) {
+    out.extend_from_slice(&value.to_le_bytes());
+}
+
+fn put_vec3(out: &mut Vec<u8>, value: Vec3) {
+    for component in value.to_array() {
+        put_f32(out, component);
+    }
+}
+
+fn put_ivec3(out: &mut Vec<u8>, value: IVec3) {
+    for component in value.to_array() {
+        put_u32(out, component as u32);
+    }
+}
+
+/// Strings go out as their length in a u16 followed by their UTF-8 bytes,
+/// longer ones are cut short.
+fn put_string(out: &mut Vec<u8>, value: &str) {
+    let mut length = value.len().min(u16::MAX as usize);
+    while !value.is_char_boundary(length) {
+        length -= 1;
+    }
+    out.extend_from_slice(&(length as u16).to_le_bytes());
+    out.extend_from_slice(&value.as_bytes()[..length]);
+}
+
+/// Reads the fields of a message one after the other.
+struct Reader<'a>(&'a [u8]);
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, length: usize) -> anyhow::Result<&'a [u8]> {
+        if self.0.len() < length {
+            bail!("message ends early");
+        }
+        let (bytes, rest) = self.0.split_at(length);
+        self.0 = rest;
+        Ok(bytes)
+    }
+
+    fn u8(&mut self) -> anyhow::Result<u8> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u32(&mut self) -> anyhow::Result<u32> {
+        Ok(u32::from_le_bytes(self.take(4)?.try_into().unwrap()))
+    }
+
+    fn f32(&mut self) -> anyhow::Result<f32> {
+        Ok(f32::from_le_bytes(self.take(4)?.try_into().unwrap()))
+    }
+
+    fn vec3(&mut self) -> anyhow::Result<Vec3> {
+        Ok(Vec3::new(self.f32()?, self.f32()?, self.f32()?))
+    }
+
+    fn ivec3(&mut self) -> anyhow::Result<IVec3> {
+        Ok(IVec3::new(
+            self.u32()? as i32,
+            self.u32()? as i32,
+            self.u32()? as i32,
+        ))
+    }
+
+    fn string(&mut self) -> anyhow::Result<String> {
+        let length = u16::from_le_bytes(self.take(2)?.try_into().unwrap());
+        Ok(std::str::from_utf8(self.take(length as usize)?)?.to_string())
+    }
+
+    /// Makes sure nothing is left over, which would mean the two sides
+    /// disagree on the message.
+    fn finish(&self) -> anyhow::Result<()> {
+        if !self.0.is_empty() {
+            bail!("{} bytes left over after the message", self.0.len());
+        }
+        Ok(())
+    }
+}
+
+/// Messages are framed by their length in a u32.
+fn read_message<M: Message>(reader: &mut impl Read) -> anyhow::Result<M> {
+    let mut length = [0; 4];
+    reader.read_exact(&mut length)?;
+    let length = u32::from_le_bytes(length) as usize;
+    if length > MAX_MESSAGE_SIZE {
+        bail!("message of {length} bytes is too large");
+    }
+
+    let mut bytes = vec![0; length];
+    reader.read_exact(&mut bytes)?;
+    M::decode(&bytes)
+}
+
+fn write_message(writer: &mut impl Write, message: &impl Message) -> io::Result<()> {
+    let mut bytes = vec![0; 4];
+    message.encode(&mut bytes);
+    let length = (bytes.len() - 4) as u32;
+    bytes[..4].copy_from_slice(&length.to_le_bytes());
+    writer.write_all(&bytes)
+}
+
+/// A TCP connection that receives `In` messages and sends `Out` messages.
+///
+/// Reading and writing happen on threads of their own, so neither side ever
+/// waits on the network. Dropping the connection sends whatever is still
+/// queued in the background, then closes it.
+pub struct Connection<In, Out> {
+    peer_address: SocketAddr,
+    outgoing: Sender<Out>,
+    writer: JoinHandle<()>,
+    incoming: Receiver<In>,
+    closed: bool,
+}
+
+impl<In: Message, Out: Message> Connection<In, Out> {
+    pub fn new(stream: TcpStream) -> io::Result<Self> {
+        stream.set_nodelay(true)?;
+        stream.set_write_timeout(Some(WRITE_TIMEOUT))?;
+        let peer_address = stream.peer_addr()?;
+
+        let (incoming_sender, incoming) = mpsc::channel();
+        let reader = stream.try_clone()?;
+        std::thread::Builder::new()
+            .name(format!("{peer_address} reader"))
+            .spawn(move || {
+                let mut reader = BufReader::new(reader);
+                loop {
+                    match read_message::<In>(&mut reader) {
+                        Ok(message) => {
+                            if incoming_sender.send(message).is_err() {
+                                break;
+                            }
+                        }
+                        Err(e) => {
+                            log::debug!("connection to {} closed: {:#}", peer_address, e);
+                            break;
+                        }
+                    }
+                }
+            })?;
+
+        let (outgoing, outgoing_receiver) = mpsc::channel::<Out>();
+        let writer = std::thread::Builder::new()
+            .name(format!("{peer_address} writer"))
+            .spawn(move || {
+                let mut writer = BufWriter::new(stream);
+                let result = (|| {
+                    while let Ok(message) = outgoing_receiver.recv() {
+                        write_message(&mut writer, &message)?;
+                        // batch up whatever else is waiting before flushing
+                        while let Ok(message) = outgoing_receiver.try_recv() {
+                            write_message(&mut writer, &message)?;
+                        }
+                        writer.flush()?;
+                    }
+                    io::Result::Ok(())
+                })();
+                if let Err(e) = result {
+                    log::debug!("unable to write to {}: {}", peer_address, e);
+                }
+
+                // also stops the reader
+                let _ = writer.get_ref().shutdown(Shutdown::Both);
+            })?;
+
+        Ok(Self {
+            peer_address,
+            outgoing,
+            writer,
+            incoming,
+            closed: false,
+        })
+    }
+
+    pub fn connect(address: impl std::net::ToSocketAddrs) -> anyhow::Result<Self> {
+        let stream = TcpStream::connect(address).context("unable to connect")?;
+        Ok(Self::new(stream)?)
+    }
+
+    pub fn peer_address(&self) -> SocketAddr {
+        self.peer_address
+    }
+
+    /// Queues a message to be sent. Messages to a closed connection are
+    /// dropped, [`Connection::receive`] tells when that happened.
+    pub fn send(&self, message: Out) {
+        let _ = self.outgoing.send(message);
+    }
+
+    /// Every message received since the last call, without waiting for more.
+    pub fn receive(&mut self) -> Vec<In> {
+        let mut messages = Vec::new();
+        loop {
+            match self.incoming.try_recv() {
+                Ok(message) => messages.push(message),
+                Err(TryRecvError::Empty) => break,
+                Err(TryRecvError::Disconnected) => {
+                    self.closed = true;
+                    break;
+                }
+            }
+        }
+
+        messages
+    }
+
+    /// Waits up to `timeout` for the next message.
+    pub fn receive_timeout(&mut self, timeout: Duration) -> Option<In> {
+        match self.incoming.recv_timeout(timeout) {
+            Ok(message) => Some(message),
+            Err(RecvTimeoutError::Timeout) => None,
+            Err(RecvTimeoutError::Disconnected) => {
+                self.closed = true;
+                None
+            }
+        }
+    }
+
+    /// Sends whatever is still queued and closes the connection, waiting
+    /// until that's done.
+    pub fn close(self) {
+        let Self {
+            outgoing, writer, ..
+        } = self;
+        drop(outgoing);
+        let _ = writer.join();
+    }
+
+    /// Whether the other side is gone and everything it sent was received.
+    pub fn is_closed(&self) -> bool {
+        self.closed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn round_trip<M: Message + PartialEq + std::fmt::Debug>(messages: &[M]) {
+        for message in messages {
+            let mut bytes = Vec::new();
+            message.encode(&mut bytes);
+            assert_eq!(&M::decode(&bytes).unwrap(), message);
+
+            // cut short or with something left over it isn't a message
+            assert!(M::decode(&bytes[..bytes.len() - 1]).is_err(), "{message:?}");
+            bytes.push(0);
+            assert!(M::decode(&bytes).is_err(), "{message:?}");
+        }
+    }
+
+    #[test]
+    fn client_messages_round_trip() {
+        round_trip(&[
+            ClientMessage::Hello {
+                version: PROTOCOL_VERSION,
+                name: "ünïcode name".to_string(),
+                render_distance: 12,
+            },
+            ClientMessage::Position {
+                position: Vec3::new(1.5, -20.25, 1e6),
+                yaw: -3.0,
+                pitch: 0.5,
+            },
+            ClientMessage::SetBlock {
+                position: IVec3::new(-1, i32::MAX, i32::MIN),
+                block: "stone".to_string(),
+            },
+            ClientMessage::UnloadedChunks(vec![IVec3::ZERO, IVec3::new(-3, 2, 100)]),
+        ]);
+        assert!(ClientMessage::decode(&[200]).is_err());
+    }
+
+    #[test]
+    fn server_messages_round_trip() {
+        let welcome = Welcome {
+            player_id: 7,
+            seed: u32::MAX,
+            generator: "default".to_string(),
+            render_distance: 8,
+            load_shape: LoadShape::Sphere,
+            spawn: Vec3::new(0.5, 64.0, -0.5),
+        };
+        round_trip(&[
+            ServerMessage::Welcome(welcome.clone()),
+            ServerMessage::Welcome(Welcome {
+                load_shape: LoadShape::Cylinder { vertical_radius: 3 },
+                ..welcome.clone()
+            }),
+            ServerMessage::Welcome(Welcome {
+                load_shape: LoadShape::Cube,
+                ..welcome
+            }),
+            ServerMessage::Chunk {
+                position: IVec3::new(1, -2, 3),
+                data: (0..=255).collect(),
+            },
+            ServerMessage::BlockChanged {
+                position: IVec3::new(4, 5, -6),
+                block: "water".to_string(),
+            },
+            ServerMessage::PlayerJoined {
+                id: 1,
+                name: "someone".to_string(),
+            },
+            ServerMessage::PlayerMoved {
+                id: 1,
+                position: Vec3::new(10.0, 20.0, 30.0),
+                yaw: 1.0,
+                pitch: -1.0,
+            },
+            ServerMessage::PlayerLeft { id: 1 },
+            ServerMessage::Time { ticks: 12000 },
+            ServerMessage::Teleport {
+                position: Vec3::NEG_ONE,
+            },
+            ServerMessage::Disconnect {
+                reason: "bye".to_string(),
+            },
+        ]);
+        assert!(ServerMessage::decode(&[200]).is_err());
+    }
+}
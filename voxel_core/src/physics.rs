@@ -0,0 +1,298 @@
+use glam::{IVec3, Vec3};
+
+use crate::{chunk_manager::ChunkManager, frustum::Aabb};
+
+/// Physics always advances in steps of this many seconds, so the outcome of a
+/// given sequence of inputs doesn't depend on the frame rate.
+pub const PHYSICS_TIMESTEP: f32 = 1.0 / 60.0;
+
+/// the most time one update catches up on, so a long hitch doesn't stall the
+/// game running hundreds of steps
+const MAX_FRAME_TIME: f32 = 0.25;
+
+const GRAVITY: f32 = 32.0;
+const TERMINAL_VELOCITY: f32 = 60.0;
+const JUMP_VELOCITY: f32 = 9.0;
+const WALK_SPEED: f32 = 4.3;
+/// obstacles up to this high are stepped onto instead of blocking
+const STEP_HEIGHT: f32 = 1.0;
+
+/// keeps the box from counting the voxels it is only touching as overlapping
+const EPSILON: f32 = 1e-4;
+
+/// Anything players can collide with.
+pub trait CollisionWorld {
+    fn is_solid(&self, position: IVec3) -> bool;
+}
+
+impl CollisionWorld for ChunkManager {
+    /// Unloaded chunks count as solid, so players wait for the ground to load
+    /// rather than falling through it.
+    fn is_solid(&self, position: IVec3) -> bool {
+        self.get_block(position)
+            .is_none_or(|block| block.info().solid)
+    }
+}
+
+impl<F: Fn(IVec3) -> bool> CollisionWorld for F {
+    fn is_solid(&self, position: IVec3) -> bool {
+        self(position)
+    }
+}
+
+/// What the player wants to do during a step.
+#[derive(Copy, Clone, Debug, Default)]
+pub struct PlayerInput {
+    /// horizontal direction to walk in, the length is clamped to 1
+    pub movement: Vec3,
+    pub jump: bool,
+}
+
+/// A walking player with a box shaped body that collides with solid voxels.
+#[derive(Clone, Debug)]
+pub struct Player {
+    /// center of the bottom of the bounding box
+    pub position: Vec3,
+    pub velocity: Vec3,
+    pub on_ground: bool,
+    /// time that has passed but hasn't been simulated yet
+    accumulator: f32,
+}
+
+impl Player {
+    pub const WIDTH: f32 = 0.6;
+    pub const HEIGHT: f32 = 1.8;
+    pub const EYE_HEIGHT: f32 = 1.62;
+
+    pub fn new(position: Vec3) -> Self {
+        Self {
+            position,
+            velocity: Vec3::ZERO,
+            on_ground: false,
+            accumulator: 0.0,
+        }
+    }
+
+    pub fn eye_position(&self) -> Vec3 {
+        self.position + Vec3::Y * Self::EYE_HEIGHT
+    }
+
+    pub fn bounding_box(&self) -> Aabb {
+        let half_width = Self::WIDTH / 2.0;
+        Aabb::new(
+            self.position - Vec3::new(half_width, 0.0, half_width),
+            self.position + Vec3::new(half_width, Self::HEIGHT, half_width),
+        )
+    }
+
+    /// Runs as many fixed steps as fit into the elapsed time, carrying the
+    /// remainder over to the next update.
+    pub fn update(&mut self, world: &impl CollisionWorld, input: PlayerInput, dt: f32) {
+        self.accumulator = (self.accumulator + dt).min(MAX_FRAME_TIME);
+        while self.accumulator >= PHYSICS_TIMESTEP {
+            self.step(world, input);
+            self.accumulator -= PHYSICS_TIMESTEP;
+        }
+    }
+
+    /// Advances the player by exactly one [`PHYSICS_TIMESTEP`].
+    pub fn step(&mut self, world: &impl CollisionWorld, input: PlayerInput) {
+        let movement = input.movement.with_y(0.0).clamp_length_max(1.0) * WALK_SPEED;
+        self.velocity.x = movement.x;
+        self.velocity.z = movement.z;
+
+        if input.jump && self.on_ground {
+            self.velocity.y = JUMP_VELOCITY;
+        }
+        self.velocity.y = (self.velocity.y - GRAVITY * PHYSICS_TIMESTEP).max(-TERMINAL_VELOCITY);
+
+        let fall = self.velocity.y * PHYSICS_TIMESTEP;
+        let moved = self.move_axis(world, 1, fall);
+        if moved != fall {
+            self.on_ground = fall < 0.0;
+            self.velocity.y = 0.0;
+        } else {
+            self.on_ground = false;
+        }
+
+        self.move_horizontally(
+            world,
+            self.velocity.x * PHYSICS_TIMESTEP,
+            self.velocity.z * PHYSICS_TIMESTEP,
+        );
+    }
+
+    /// Moves along x and z, stepping up onto low obstacles while on the ground.
+    fn move_horizontally(&mut self, world: &impl CollisionWorld, dx: f32, dz: f32) {
+        let start = self.position;
+        self.move_axis(world, 0, dx);
+        self.move_axis(world, 2, dz);
+
+        let wanted = dx.hypot(dz);
+        let moved = (self.position - start).with_y(0.0).length();
+        if !self.on_ground || moved >= wanted - EPSILON {
+            return;
+        }
+
+        // try the same move again from higher up, then drop back down on
+        // whatever is there
+        let blocked = self.position;
+        self.position = start;
+        let raised = self.move_axis(world, 1, STEP_HEIGHT);
+        self.move_axis(world, 0, dx);
+        self.move_axis(world, 2, dz);
+        self.move_axis(world, 1, -raised);
+
+        let stepped = (self.position - start).with_y(0.0).length();
+        if stepped <= moved + EPSILON {
+            self.position = blocked;
+        }
+    }
+
+    /// Moves the bounding box up to `amount` along one axis, stopping at the
+    /// first solid voxel in the way. Returns how far it actually moved.
+    fn move_axis(&mut self, world: &impl CollisionWorld, axis: usize, amount: f32) -> f32 {
+        if amount == 0.0 {
+            return 0.0;
+        }
+
+        let bounding_box = self.bounding_box();
+        let mut min = bounding_box.min;
+        let mut max = bounding_box.max;
+        if amount > 0.0 {
+            max[axis] += amount;
+        } else {
+            min[axis] += amount;
+        }
+
+        let min = (min + EPSILON).floor().as_ivec3();
+        let max = (max - EPSILON).floor().as_ivec3();
+
+        let mut allowed = amount;
+        for x in min.x..=max.x {
+            for y in min.y..=max.y {
+                for z in min.z..=max.z {
+                    let voxel = IVec3::new(x, y, z);
+                    if !world.is_solid(voxel) {
+                        continue;
+                    }
+
+                    // only voxels ahead of the box can stop it, ones it
+                    // already overlaps are left for it to move out of
+                    let near_side = voxel[axis] as f32;
+                    if amount > 0.0 && near_side >= bounding_box.max[axis] - EPSILON {
+                        allowed = allowed.min((near_side - bounding_box.max[axis]).max(0.0));
+                    } else if amount < 0.0 && near_side + 1.0 <= bounding_box.min[axis] + EPSILON {
+                        allowed = allowed.max((near_side + 1.0 - bounding_box.min[axis]).min(0.0));
+                    }
+                }
+            }
+        }
+
+        self.position[axis] += allowed;
+        allowed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const WALK_X: PlayerInput = PlayerInput {
+        movement: Vec3::X,
+        jump: false,
+    };
+
+    fn floor(position: IVec3) -> bool {
+        position.y < 0
+    }
+
+    /// A player standing on the ground at the origin of a block.
+    fn standing(world: &impl CollisionWorld) -> Player {
+        let mut player = Player::new(Vec3::new(0.5, 0.0, 0.5));
+        player.step(world, PlayerInput::default());
+        assert!(player.on_ground);
+        player
+    }
+
+    fn walk(player: &mut Player, world: &impl CollisionWorld, input: PlayerInput, steps: usize) {
+        for _ in 0..steps {
+            player.step(world, input);
+        }
+    }
+
+    #[test]
+    fn falling_players_land() {
+        let mut player = Player::new(Vec3::new(0.5, 5.0, 0.5));
+        walk(&mut player, &floor, PlayerInput::default(), 10);
+        assert!(!player.on_ground);
+        assert!(player.velocity.y < 0.0);
+
+        walk(&mut player, &floor, PlayerInput::default(), 120);
+        assert!(player.on_ground);
+        assert_eq!(player.velocity.y, 0.0);
+        assert!(player.position.y.abs() < 1e-3, "{}", player.position);
+    }
+
+    #[test]
+    fn walls_are_slid_along() {
+        let world = |position: IVec3| position.y < 0 || position.x >= 3;
+        let mut player = standing(&world);
+        let input = PlayerInput {
+            movement: Vec3::new(1.0, 0.0, 1.0),
+            jump: false,
+        };
+        walk(&mut player, &world, input, 60);
+
+        let against_wall = 3.0 - Player::WIDTH / 2.0;
+        assert!((player.position.x - against_wall).abs() < 1e-3);
+        // the diagonal walk keeps its speed along the wall
+        assert!(player.position.z > 0.5 + WALK_SPEED * 0.7 - 0.01);
+        assert!(player.on_ground);
+    }
+
+    #[test]
+    fn one_block_ledges_are_stepped_onto() {
+        let world = |position: IVec3| position.y < 0 || (position.y < 1 && position.x >= 2);
+        let mut player = standing(&world);
+        walk(&mut player, &world, WALK_X, 60);
+
+        assert!(player.position.x > 3.0);
+        assert!((player.position.y - 1.0).abs() < 1e-3);
+        assert!(player.on_ground);
+    }
+
+    #[test]
+    fn two_block_ledges_block() {
+        let world = |position: IVec3| position.y < 0 || (position.y < 2 && position.x >= 2);
+        let mut player = standing(&world);
+        walk(&mut player, &world, WALK_X, 60);
+
+        assert!((player.position.x - (2.0 - Player::WIDTH / 2.0)).abs() < 1e-3);
+        assert!(player.position.y.abs() < 1e-3);
+    }
+
+    #[test]
+    fn frame_rate_does_not_change_the_outcome() {
+        let world = |position: IVec3| position.y < 0 || (position.y < 1 && position.x >= 2);
+        let input = PlayerInput {
+            movement: Vec3::new(1.0, 0.0, 0.3),
+            jump: true,
+        };
+        // half a step past a whole number of steps, so rounding while adding
+        // up frame times can't change how many steps run
+        let duration = 90.5 * PHYSICS_TIMESTEP;
+
+        let outcomes = [9, 60, 144, 1000].map(|frames| {
+            let mut player = standing(&world);
+            for _ in 0..frames {
+                player.update(&world, input, duration / frames as f32);
+            }
+            (player.position, player.velocity, player.on_ground)
+        });
+        for outcome in &outcomes[1..] {
+            assert_eq!(*outcome, outcomes[0]);
+        }
+        assert!(outcomes[0].0.x > 3.0);
+    }
+}
@@ -0,0 +1,644 @@
+use std::{
+    fs,
+    io::{Read, Write},
+    path::Path,
+};
+
+use ahash::{AHashMap, AHashSet};
+use anyhow::{Context, bail};
+use flate2::{
+    Compression,
+    read::{GzDecoder, ZlibDecoder},
+    write::ZlibEncoder,
+};
+use glam::{IVec3, UVec3};
+
+use crate::{
+    block::{Block, registry},
+    chunk_manager::ChunkManager,
+    nbt::{self, Tag},
+};
+
+const MAGIC: &[u8; 4] = b"VXSC";
+const FORMAT_VERSION: u8 = 1;
+
+/// the most blocks along any axis of a schematic
+pub const MAX_SIZE: u32 = 1024;
+/// the most blocks in a schematic, so a corrupt size can't ask for gigabytes
+const MAX_VOLUME: u64 = 64 * 1024 * 1024;
+
+/// Minecraft blocks that go by another name in the default block pack.
+const SPONGE_ALIASES: [(&str, &str); 8] = [
+    ("grass_block", "grass"),
+    ("dirt_path", "dirt"),
+    ("coarse_dirt", "dirt"),
+    ("cobblestone", "stone"),
+    ("snow_block", "snow"),
+    ("packed_ice", "ice"),
+    ("glowstone", "lamp"),
+    ("sea_lantern", "lamp"),
+];
+
+/// How a schematic is turned when it's pasted. The mirroring happens first,
+/// along the x axis, and together with the rotation covers every way the
+/// schematic can face.
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Default)]
+pub struct Transform {
+    /// quarter turns around the y axis, clockwise seen from above
+    pub rotation: u8,
+    pub mirror: bool,
+}
+
+impl Transform {
+    /// The size of the box the schematic fills once turned.
+    pub fn size(&self, size: UVec3) -> UVec3 {
+        if self.rotation % 2 == 1 {
+            UVec3::new(size.z, size.y, size.x)
+        } else {
+            size
+        }
+    }
+
+    /// Where the block at `position` in a schematic of `size` ends up.
+    pub fn apply(&self, position: UVec3, size: UVec3) -> UVec3 {
+        let mut position = position;
+        let mut size = size;
+        if self.mirror {
+            position.x = size.x - 1 - position.x;
+        }
+        for _ in 0..self.rotation % 4 {
+            position = UVec3::new(size.z - 1 - position.z, position.y, position.x);
+            size = UVec3::new(size.z, size.y, size.x);
+        }
+
+        position
+    }
+}
+
+/// The blocks of a box of the world, to be saved and pasted somewhere else.
+///
+/// Blocks are stored x first, then z, then y, the same order Sponge
+/// schematics use.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Schematic {
+    size: UVec3,
+    blocks: Vec<Block>,
+}
+
+impl Schematic {
+    /// A schematic of the given size filled with air.
+    pub fn new(size: UVec3) -> anyhow::Result<Self> {
+        check_size(size)?;
+        Ok(Self {
+            size,
+            blocks: vec![Block::AIR; (size.x * size.y * size.z) as usize],
+        })
+    }
+
+    pub fn size(&self) -> UVec3 {
+        self.size
+    }
+
+    fn index(&self, position: UVec3) -> usize {
+        ((position.y * self.size.z + position.z) * self.size.x + position.x) as usize
+    }
+
+    pub fn get(&self, position: UVec3) -> Block {
+        self.blocks[self.index(position)]
+    }
+
+    pub fn set(&mut self, position: UVec3, block: Block) {
+        let index = self.index(position);
+        self.blocks[index] = block;
+    }
+
+    /// Copies the box between two opposite corners, both inclusive. Fails if
+    /// part of it isn't loaded.
+    pub fn copy(chunk_manager: &ChunkManager, a: IVec3, b: IVec3) -> anyhow::Result<Self> {
+        let min = a.min(b);
+        let mut schematic = Self::new((a.max(b) - min + 1).as_uvec3())?;
+
+        for y in 0..schematic.size.y {
+            for z in 0..schematic.size.z {
+                for x in 0..schematic.size.x {
+                    let position = UVec3::new(x, y, z);
+                    let world_position = min + position.as_ivec3();
+                    let block = chunk_manager
+                        .get_block(world_position)
+                        .with_context(|| format!("{world_position} isn't loaded"))?;
+                    schematic.set(position, block);
+                }
+            }
+        }
+
+        Ok(schematic)
+    }
+
+    /// Writes every block of the schematic, air included, into the box that
+    /// starts at `origin`, turned by `transform`. Goes through
+    /// [`ChunkManager::set_blocks`] so light and meshes follow along. Blocks
+    /// that land in chunks that aren't loaded are skipped, returns how many
+    /// were placed.
+    pub fn paste(
+        &self,
+        chunk_manager: &mut ChunkManager,
+        origin: IVec3,
+        transform: Transform,
+    ) -> usize {
+        let blocks = (0..self.size.y).flat_map(|y| {
+            (0..self.size.z).flat_map(move |z| {
+                (0..self.size.x).map(move |x| {
+                    let position = UVec3::new(x, y, z);
+                    let world_position = origin + transform.apply(position, self.size).as_ivec3();
+                    (world_position, self.get(position))
+                })
+            })
+        });
+        chunk_manager.set_blocks(blocks)
+    }
+
+    /// Loads a schematic in our own format or a Sponge `.schem`, telling
+    /// them apart by their first bytes.
+    pub fn load(path: impl AsRef<Path>) -> anyhow::Result<Self> {
+        let path = path.as_ref();
+        let bytes = fs::read(path).with_context(|| format!("unable to read {path:?}"))?;
+
+        if bytes.starts_with(MAGIC) {
+            Self::decode(&bytes)
+        } else if bytes.starts_with(&[0x1f, 0x8b]) {
+            Self::from_sponge(&bytes)
+        } else {
+            bail!("{path:?} isn't a schematic")
+        }
+        .with_context(|| format!("unable to load schematic {path:?}"))
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> anyhow::Result<()> {
+        let path = path.as_ref();
+        if let Some(directory) = path.parent() {
+            fs::create_dir_all(directory)?;
+        }
+        fs::write(path, self.encode()?).with_context(|| format!("unable to write {path:?}"))
+    }
+
+    /// Our own format is the magic, then a zlib stream of the size, a palette
+    /// of block names and the palette index of every block, like chunks in a
+    /// world save.
+    pub fn encode(&self) -> anyhow::Result<Vec<u8>> {
+        let mut palette = Vec::new();
+        let mut palette_indices = AHashMap::new();
+        let mut indices = Vec::with_capacity(self.blocks.len() * 2);
+        for &block in &self.blocks {
+            let index = *palette_indices.entry(block).or_insert_with(|| {
+                palette.push(block);
+                palette.len() as u16 - 1
+            });
+            indices.extend_from_slice(&index.to_le_bytes());
+        }
+
+        let mut encoder = ZlibEncoder::new(MAGIC.to_vec(), Compression::default());
+        encoder.write_all(&[FORMAT_VERSION])?;
+        for length in self.size.to_array() {
+            encoder.write_all(&(length as u16).to_le_bytes())?;
+        }
+        encoder.write_all(&(palette.len() as u16).to_le_bytes())?;
+        for block in palette {
+            let name = block.name().as_bytes();
+            encoder.write_all(&[u8::try_from(name.len()).context("block name too long")?])?;
+            encoder.write_all(name)?;
+        }
+        encoder.write_all(&indices)?;
+
+        Ok(encoder.finish()?)
+    }
+
+    pub fn decode(bytes: &[u8]) -> anyhow::Result<Self> {
+        let compressed = bytes.strip_prefix(MAGIC).context("not a schematic")?;
+        let mut data = Vec::new();
+        ZlibDecoder::new(compressed).read_to_end(&mut data)?;
+
+        let mut cursor = data.as_slice();
+        let version = take(&mut cursor, 1)?[0];
+        if version != FORMAT_VERSION {
+            bail!("unsupported schematic version {version}");
+        }
+        let size = UVec3::new(
+            read_u16(&mut cursor)? as u32,
+            read_u16(&mut cursor)? as u32,
+            read_u16(&mut cursor)? as u32,
+        );
+        let mut schematic = Self::new(size)?;
+
+        let palette_length = read_u16(&mut cursor)?;
+        let mut names = Vec::with_capacity(palette_length as usize);
+        for _ in 0..palette_length {
+            let length = take(&mut cursor, 1)?[0] as usize;
+            names.push(std::str::from_utf8(take(&mut cursor, length)?)?.to_string());
+        }
+        let palette = resolve_palette(names.iter().map(String::as_str), |name| {
+            registry().get(name)
+        });
+
+        let indices = take(&mut cursor, schematic.blocks.len() * 2)?;
+        for (block, index) in schematic.blocks.iter_mut().zip(indices.chunks_exact(2)) {
+            let index = u16::from_le_bytes([index[0], index[1]]);
+            *block = *palette
+                .get(index as usize)
+                .with_context(|| format!("palette index {index} out of range"))?;
+        }
+
+        Ok(schematic)
+    }
+
+    /// Reads a gzipped Sponge schematic, version 1 to 3. Minecraft block
+    /// names are matched to blocks of the registry by their name without the
+    /// namespace and block states, blocks nothing matches turn into air.
+    pub fn from_sponge(bytes: &[u8]) -> anyhow::Result<Self> {
+        let mut data = Vec::new();
+        GzDecoder::new(bytes).read_to_end(&mut data)?;
+        let (_, root) = nbt::read(&data)?;
+        // version 3 wraps everything in another compound
+        let root = root.get("Schematic").unwrap_or(&root);
+
+        let dimension = |name: &str| {
+            root.get(name)
+                .and_then(Tag::as_int)
+                // stored as signed shorts but meant unsigned
+                .map(|length| length as u16 as u32)
+                .with_context(|| format!("missing {name}"))
+        };
+        let size = UVec3::new(
+            dimension("Width")?,
+            dimension("Height")?,
+            dimension("Length")?,
+        );
+        let mut schematic = Self::new(size)?;
+
+        let (palette, data) = match root.get("Blocks") {
+            Some(blocks) => (blocks.get("Palette"), blocks.get("Data")),
+            None => (root.get("Palette"), root.get("BlockData")),
+        };
+        let Some(Tag::Compound(palette)) = palette else {
+            bail!("missing block palette");
+        };
+        let Some(Tag::ByteArray(data)) = data else {
+            bail!("missing block data");
+        };
+
+        // ids are meant to count up from 0, anything beyond the palette is
+        // refused rather than sizing the palette after what the file claims
+        let mut names = vec!["minecraft:air"; palette.len()];
+        for (name, id) in palette {
+            let id = id.as_int().context("invalid palette id")?;
+            let Some(entry) = usize::try_from(id).ok().and_then(|id| names.get_mut(id)) else {
+                bail!("palette id {id} of {name} out of range");
+            };
+            *entry = name.as_str();
+        }
+        let palette = resolve_palette(names.into_iter(), sponge_block);
+
+        let mut data = data.as_slice();
+        for block in schematic.blocks.iter_mut() {
+            let id = read_varint(&mut data)?;
+            *block = *palette
+                .get(id as usize)
+                .with_context(|| format!("palette id {id} out of range"))?;
+        }
+
+        Ok(schematic)
+    }
+}
+
+fn take<'a>(cursor: &mut &'a [u8], length: usize) -> anyhow::Result<&'a [u8]> {
+    if cursor.len() < length {
+        bail!("schematic data ends early");
+    }
+    let (bytes, rest) = cursor.split_at(length);
+    *cursor = rest;
+    Ok(bytes)
+}
+
+fn read_u16(cursor: &mut &[u8]) -> anyhow::Result<u16> {
+    Ok(u16::from_le_bytes(take(cursor, 2)?.try_into().unwrap()))
+}
+
+fn check_size(size: UVec3) -> anyhow::Result<()> {
+    if size.cmpgt(UVec3::splat(MAX_SIZE)).any()
+        || size.x as u64 * size.y as u64 * size.z as u64 > MAX_VOLUME
+    {
+        bail!("schematic of {size} is too large");
+    }
+    Ok(())
+}
+
+/// Looks up the blocks of a palette, warning once about every name that
+/// doesn't match a block and using air for it.
+fn resolve_palette<'a>(
+    names: impl Iterator<Item = &'a str>,
+    lookup: impl Fn(&str) -> Option<Block>,
+) -> Vec<Block> {
+    let mut unknown = AHashSet::new();
+    let palette = names
+        .map(|name| {
+            lookup(name).unwrap_or_else(|| {
+                unknown.insert(name.to_string());
+                Block::AIR
+            })
+        })
+        .collect();
+
+    if !unknown.is_empty() {
+        let mut unknown = unknown.into_iter().collect::<Vec<String>>();
+        unknown.sort();
+        log::warn!(
+            "unknown blocks in schematic, replacing them with air: {}",
+            unknown.join(", ")
+        );
+    }
+
+    palette
+}
+
+/// Matches a Minecraft block like `minecraft:oak_log[axis=y]` to a block of
+/// the registry.
+fn sponge_block(name: &str) -> Option<Block> {
+    let name = name.split('[').next().unwrap_or(name);
+    let name = name.rsplit(':').next().unwrap_or(name);
+    let registry = registry();
+
+    if let Some(block) = registry.get(name) {
+        return Some(block);
+    }
+    if let Some((_, alias)) = SPONGE_ALIASES.iter().find(|(from, _)| *from == name) {
+        return registry.get(alias);
+    }
+
+    // every kind of wood is the same here
+    let wood = [
+        ("_log", "log"),
+        ("_wood", "log"),
+        ("_planks", "plank"),
+        ("_leaves", "leaves"),
+    ];
+    wood.iter()
+        .find(|(suffix, _)| name.ends_with(suffix))
+        .and_then(|(_, block)| registry.get(block))
+}
+
+/// Sponge block data is a palette id per block in the LEB128 varint encoding.
+fn read_varint(data: &mut &[u8]) -> anyhow::Result<u32> {
+    let mut value = 0u32;
+    for shift in (0..35).step_by(7) {
+        let (&byte, rest) = data.split_first().context("block data ends early")?;
+        *data = rest;
+        value |= ((byte & 0x7f) as u32) << shift;
+        if byte & 0x80 == 0 {
+            return Ok(value);
+        }
+    }
+
+    bail!("varint too long")
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use flate2::write::GzEncoder;
+
+    use super::*;
+    use crate::{chunk::CHUNK_SIZE, generator::VoidGenerator, light::LightChannel};
+
+    /// Writes the start of a named NBT tag.
+    fn tag(out: &mut Vec<u8>, id: u8, name: &str) {
+        out.push(id);
+        out.extend_from_slice(&(name.len() as u16).to_be_bytes());
+        out.extend_from_slice(name.as_bytes());
+    }
+
+    fn int(out: &mut Vec<u8>, name: &str, value: i32) {
+        tag(out, 3, name);
+        out.extend_from_slice(&value.to_be_bytes());
+    }
+
+    fn short(out: &mut Vec<u8>, name: &str, value: i16) {
+        tag(out, 2, name);
+        out.extend_from_slice(&value.to_be_bytes());
+    }
+
+    fn palette(out: &mut Vec<u8>, name: &str, entries: &[(&str, i32)]) {
+        tag(out, 10, name);
+        for &(block, id) in entries {
+            int(out, block, id);
+        }
+        out.push(0);
+    }
+
+    fn byte_array(out: &mut Vec<u8>, name: &str, bytes: &[u8]) {
+        tag(out, 7, name);
+        out.extend_from_slice(&(bytes.len() as i32).to_be_bytes());
+        out.extend_from_slice(bytes);
+    }
+
+    fn gzip(bytes: &[u8]) -> Vec<u8> {
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(bytes).unwrap();
+        encoder.finish().unwrap()
+    }
+
+    /// A 3 wide, 2 high and 2 long Sponge schematic of the given version.
+    fn sponge(version: i32, entries: &[(&str, i32)], data: &[u8]) -> Vec<u8> {
+        let mut out = Vec::new();
+        tag(&mut out, 10, if version == 3 { "" } else { "Schematic" });
+        if version == 3 {
+            tag(&mut out, 10, "Schematic");
+        }
+        int(&mut out, "Version", version);
+        short(&mut out, "Width", 3);
+        short(&mut out, "Height", 2);
+        short(&mut out, "Length", 2);
+        if version == 3 {
+            tag(&mut out, 10, "Blocks");
+            palette(&mut out, "Palette", entries);
+            byte_array(&mut out, "Data", data);
+            out.push(0);
+            out.push(0);
+        } else {
+            palette(&mut out, "Palette", entries);
+            byte_array(&mut out, "BlockData", data);
+        }
+        out.push(0);
+
+        gzip(&out)
+    }
+
+    const PALETTE: [(&str, i32); 3] = [
+        ("minecraft:stone", 1),
+        ("minecraft:air", 0),
+        ("minecraft:oak_log[axis=y]", 2),
+    ];
+    /// the bottom layer stone but for one log, the top air but for one stone
+    const DATA: [u8; 12] = [1, 1, 1, 1, 2, 1, 0, 0, 0, 1, 0, 0];
+
+    #[test]
+    fn sponge_schematics_load() {
+        let stone = Block::named("stone");
+        let log = Block::named("log");
+        for version in [2, 3] {
+            let schematic = Schematic::from_sponge(&sponge(version, &PALETTE, &DATA)).unwrap();
+            assert_eq!(schematic.size(), UVec3::new(3, 2, 2));
+            assert_eq!(schematic.get(UVec3::new(0, 0, 0)), stone);
+            assert_eq!(schematic.get(UVec3::new(1, 0, 1)), log);
+            assert_eq!(schematic.get(UVec3::new(0, 1, 0)), Block::AIR);
+            assert_eq!(schematic.get(UVec3::new(0, 1, 1)), stone);
+        }
+    }
+
+    #[test]
+    fn broken_sponge_schematics_are_refused() {
+        for version in [2, 3] {
+            let out_of_palette = [("minecraft:stone", 0), ("minecraft:air", 5)];
+            assert!(Schematic::from_sponge(&sponge(version, &out_of_palette, &DATA)).is_err());
+            let negative = [("minecraft:stone", 0), ("minecraft:air", -1)];
+            assert!(Schematic::from_sponge(&sponge(version, &negative, &DATA)).is_err());
+
+            let mut out_of_range = DATA;
+            out_of_range[3] = 3;
+            assert!(Schematic::from_sponge(&sponge(version, &PALETTE, &out_of_range)).is_err());
+            assert!(Schematic::from_sponge(&sponge(version, &PALETTE, &DATA[..11])).is_err());
+        }
+    }
+
+    #[test]
+    fn schematics_round_trip() {
+        let mut schematic = Schematic::new(UVec3::new(5, 3, 7)).unwrap();
+        for (i, name) in ["stone", "log", "water", "lamp"].into_iter().enumerate() {
+            schematic.set(
+                UVec3::new(i as u32, i as u32 % 3, 6 - i as u32),
+                Block::named(name),
+            );
+        }
+
+        let bytes = schematic.encode().unwrap();
+        assert_eq!(Schematic::decode(&bytes).unwrap(), schematic);
+        assert!(Schematic::decode(&bytes[..bytes.len() / 2]).is_err());
+        assert!(Schematic::decode(&bytes[4..]).is_err());
+    }
+
+    #[test]
+    fn pastes_light_the_world_like_single_blocks() {
+        let world = || {
+            let mut chunk_manager = ChunkManager::new(1, Arc::new(VoidGenerator));
+            chunk_manager.update_around(IVec3::ZERO);
+            chunk_manager.finish_jobs();
+            chunk_manager
+        };
+        let mut pasted = world();
+        let mut placed = world();
+
+        // a hollow stone box with a lamp inside, across the chunk borders at 32
+        let size = UVec3::splat(7);
+        let mut schematic = Schematic::new(size).unwrap();
+        for y in 0..size.y {
+            for z in 0..size.z {
+                for x in 0..size.x {
+                    let position = UVec3::new(x, y, z);
+                    if position.cmpeq(UVec3::ZERO).any() || position.cmpeq(size - 1).any() {
+                        schematic.set(position, Block::named("stone"));
+                    }
+                }
+            }
+        }
+        schematic.set(UVec3::splat(3), Block::named("lamp"));
+        // and an empty one taking it out again
+        let empty = Schematic::new(size).unwrap();
+
+        let origin = IVec3::new(29, 2, 29);
+        for schematic in [&schematic, &empty] {
+            let count = schematic.paste(&mut pasted, origin, Transform::default());
+            assert_eq!(count, 7 * 7 * 7);
+            for y in 0..size.y {
+                for z in 0..size.z {
+                    for x in 0..size.x {
+                        let position = UVec3::new(x, y, z);
+                        placed.set_block(origin + position.as_ivec3(), schematic.get(position));
+                    }
+                }
+            }
+
+            for (position, chunk) in &placed.chunk_map {
+                let other = &pasted.chunk_map[position];
+                assert!(chunk.blocks.iter().eq(other.blocks.iter()), "{position}");
+                for channel in LightChannel::ALL {
+                    assert!(
+                        (0..CHUNK_SIZE * CHUNK_SIZE * CHUNK_SIZE).all(|index| {
+                            chunk.get_light(channel, index) == other.get_light(channel, index)
+                        }),
+                        "{channel:?} light of {position}"
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn transforms_move_every_block_into_the_turned_box() {
+        let size = UVec3::new(2, 3, 5);
+        let positions = (0..size.y)
+            .flat_map(|y| {
+                (0..size.z).flat_map(move |z| (0..size.x).map(move |x| UVec3::new(x, y, z)))
+            })
+            .collect::<Vec<UVec3>>();
+
+        for mirror in [false, true] {
+            for rotation in 0..4 {
+                let transform = Transform { rotation, mirror };
+                let turned_size = transform.size(size);
+                let turned = positions
+                    .iter()
+                    .map(|&position| transform.apply(position, size))
+                    .collect::<AHashSet<UVec3>>();
+
+                // every block lands on its own spot inside the box
+                assert_eq!(turned.len(), positions.len(), "{transform:?}");
+                assert!(
+                    turned
+                        .iter()
+                        .all(|position| position.cmplt(turned_size).all())
+                );
+
+                // and turning a quarter at a time ends up in the same place
+                let turn = Transform {
+                    rotation: 1,
+                    mirror: false,
+                };
+                for &position in &positions {
+                    let mut stepped = Transform {
+                        rotation: 0,
+                        mirror,
+                    }
+                    .apply(position, size);
+                    let mut stepped_size = size;
+                    for _ in 0..rotation {
+                        stepped = turn.apply(stepped, stepped_size);
+                        stepped_size = turn.size(stepped_size);
+                    }
+                    assert_eq!(stepped, transform.apply(position, size));
+                }
+            }
+        }
+
+        // a quarter turn clockwise seen from above takes east to south
+        let turn = Transform {
+            rotation: 1,
+            mirror: false,
+        };
+        assert_eq!(turn.size(size), UVec3::new(5, 3, 2));
+        assert_eq!(turn.apply(UVec3::new(1, 0, 0), size), UVec3::new(4, 0, 1));
+        let mirror = Transform {
+            rotation: 0,
+            mirror: true,
+        };
+        assert_eq!(mirror.apply(UVec3::new(0, 2, 4), size), UVec3::new(1, 2, 4));
+    }
+}
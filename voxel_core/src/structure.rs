@@ -0,0 +1,257 @@
+use glam::IVec3;
+
+use crate::{
+    block::Block,
+    chunk::{CHUNK_SIZE, Chunk},
+};
+
+/// A fixed arrangement of blocks placed relative to an origin, such as a tree.
+#[derive(Clone, Debug)]
+pub struct StructureTemplate {
+    blocks: Vec<(IVec3, Block)>,
+    min: IVec3,
+    max: IVec3,
+}
+
+impl StructureTemplate {
+    pub fn new(blocks: Vec<(IVec3, Block)>) -> Self {
+        let min = blocks
+            .iter()
+            .map(|(offset, _)| *offset)
+            .reduce(IVec3::min)
+            .unwrap_or_default();
+        let max = blocks
+            .iter()
+            .map(|(offset, _)| *offset)
+            .reduce(IVec3::max)
+            .unwrap_or_default();
+
+        Self { blocks, min, max }
+    }
+
+    /// A trunk topped with a rounded blob of leaves, with the trunk starting
+    /// at the origin.
+    pub fn tree(trunk_height: i32, leaf_radius: i32, log: Block, leaves: Block) -> Self {
+        let mut blocks = Vec::new();
+
+        let top = trunk_height - 1;
+        for y in top - leaf_radius..=top + 1 {
+            // the top layer is narrower so the canopy looks rounded
+            let radius = if y > top {
+                leaf_radius - 1
+            } else {
+                leaf_radius
+            };
+            for x in -radius..=radius {
+                for z in -radius..=radius {
+                    let is_corner = x.abs() == radius && z.abs() == radius;
+                    if is_corner && radius > 1 || (x == 0 && z == 0 && y <= top) {
+                        continue;
+                    }
+                    blocks.push((IVec3::new(x, y, z), leaves));
+                }
+            }
+        }
+
+        for y in 0..trunk_height {
+            blocks.push((IVec3::new(0, y, 0), log));
+        }
+
+        Self::new(blocks)
+    }
+
+    /// The smallest and largest offset of any block, both inclusive.
+    pub fn bounds(&self) -> (IVec3, IVec3) {
+        (self.min, self.max)
+    }
+
+    pub fn blocks(&self) -> &[(IVec3, Block)] {
+        &self.blocks
+    }
+
+    /// Writes the blocks of the template placed at `origin` that fall inside
+    /// `chunk`, leaving everything outside of it for the neighboring chunks to
+    /// fill in when they generate. Only air, and transparent blocks like leaves
+    /// when placing a block that isn't, are replaced so structures don't cut
+    /// into the terrain, and the outcome where two structures overlap doesn't
+    /// depend on which one is placed first.
+    pub fn place_in_chunk(&self, chunk: &mut Chunk, origin: IVec3) {
+        let chunk_min = chunk.world_position;
+        let chunk_max = chunk_min + CHUNK_SIZE as i32 - 1;
+
+        if (origin + self.max).cmplt(chunk_min).any() || (origin + self.min).cmpgt(chunk_max).any()
+        {
+            return;
+        }
+
+        for &(offset, block) in &self.blocks {
+            let local = origin + offset - chunk_min;
+            if local.cmplt(IVec3::ZERO).any() || local.cmpge(IVec3::splat(CHUNK_SIZE as i32)).any()
+            {
+                continue;
+            }
+
+            let index = Chunk::block_index(local.x as usize, local.y as usize, local.z as usize);
+            let existing = chunk.blocks.get(index);
+            if existing.is_air() || (existing.info().transparent && !block.info().transparent) {
+                chunk.blocks.set(index, block);
+                chunk.is_empty = false;
+            }
+        }
+    }
+}
+
+/// Hashes a column position together with the world seed, so that every
+/// chunk makes the same placement decisions no matter when it is generated.
+pub fn hash_column(seed: u32, salt: u32, x: i32, z: i32) -> u64 {
+    let mut hash = ((seed as u64) << 32) | salt as u64;
+    for value in [x as u32 as u64, z as u32 as u64] {
+        // splitmix64
+        hash = hash.wrapping_add(value).wrapping_add(0x9e3779b97f4a7c15);
+        hash = (hash ^ (hash >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+        hash = (hash ^ (hash >> 27)).wrapping_mul(0x94d049bb133111eb);
+        hash ^= hash >> 31;
+    }
+
+    hash
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn blocks_of(chunk: &Chunk) -> Vec<(IVec3, Block)> {
+        let mut blocks = Vec::new();
+        for z in 0..CHUNK_SIZE {
+            for y in 0..CHUNK_SIZE {
+                for x in 0..CHUNK_SIZE {
+                    let block = chunk.blocks.get(Chunk::block_index(x, y, z));
+                    if block != Block::AIR {
+                        let local = IVec3::new(x as i32, y as i32, z as i32);
+                        blocks.push((chunk.world_position + local, block));
+                    }
+                }
+            }
+        }
+        blocks
+    }
+
+    fn tree(trunk_height: i32, leaf_radius: i32) -> StructureTemplate {
+        StructureTemplate::tree(
+            trunk_height,
+            leaf_radius,
+            Block::named("log"),
+            Block::named("leaves"),
+        )
+    }
+
+    #[test]
+    fn trees_stand_on_their_trunk() {
+        let tree = tree(5, 2);
+        let (min, max) = tree.bounds();
+        assert_eq!(min, IVec3::new(-2, 0, -2));
+        assert_eq!(max, IVec3::new(2, 5, 2));
+        for y in 0..5 {
+            assert!(
+                tree.blocks()
+                    .contains(&(IVec3::new(0, y, 0), Block::named("log")))
+            );
+        }
+        // the trunk isn't covered by leaves at the same spot
+        assert_eq!(
+            tree.blocks()
+                .iter()
+                .filter(|(offset, _)| offset.x == 0 && offset.z == 0 && offset.y < 5)
+                .count(),
+            5
+        );
+    }
+
+    #[test]
+    fn placements_are_split_between_chunks() {
+        let tree = tree(6, 2);
+        // right next to the corner of four chunks
+        let origin = IVec3::new(31, 28, 0);
+
+        let mut placed = Vec::new();
+        for position in [
+            IVec3::new(0, 0, 0),
+            IVec3::new(1, 0, 0),
+            IVec3::new(0, 1, 0),
+            IVec3::new(1, 1, 0),
+            IVec3::new(0, 0, -1),
+            IVec3::new(1, 0, -1),
+            IVec3::new(0, 1, -1),
+            IVec3::new(1, 1, -1),
+        ] {
+            let mut chunk = Chunk::new(position);
+            tree.place_in_chunk(&mut chunk, origin);
+            placed.extend(blocks_of(&chunk));
+        }
+        let far_away = {
+            let mut chunk = Chunk::new(IVec3::new(3, 0, 0));
+            tree.place_in_chunk(&mut chunk, origin);
+            chunk
+        };
+        assert!(far_away.is_empty);
+
+        let mut expected = tree
+            .blocks()
+            .iter()
+            .map(|&(offset, block)| (origin + offset, block))
+            .collect::<Vec<_>>();
+        expected.sort_by_key(|(position, _)| position.to_array());
+        placed.sort_by_key(|(position, _)| position.to_array());
+        assert_eq!(placed, expected);
+    }
+
+    #[test]
+    fn overlapping_structures_dont_depend_on_the_placement_order() {
+        let small = tree(4, 1);
+        let large = tree(6, 2);
+        let (a, b) = (IVec3::new(10, 3, 10), IVec3::new(11, 1, 10));
+
+        let mut ground = Chunk::new(IVec3::ZERO);
+        for x in 0..CHUNK_SIZE {
+            for z in 0..CHUNK_SIZE {
+                ground
+                    .blocks
+                    .set(Chunk::block_index(x, 0, z), Block::named("stone"));
+            }
+        }
+        // a rock right where the leaves of the small tree go
+        ground
+            .blocks
+            .set(Chunk::block_index(9, 6, 9), Block::named("stone"));
+
+        let mut small_first = Chunk::new(IVec3::ZERO);
+        small_first.blocks = ground.blocks.clone();
+        small.place_in_chunk(&mut small_first, a);
+        large.place_in_chunk(&mut small_first, b);
+
+        let mut large_first = Chunk::new(IVec3::ZERO);
+        large_first.blocks = ground.blocks.clone();
+        large.place_in_chunk(&mut large_first, b);
+        small.place_in_chunk(&mut large_first, a);
+
+        assert!(small_first.blocks.iter().eq(large_first.blocks.iter()));
+        // logs win over leaves, and nothing cuts into the terrain
+        assert_eq!(
+            small_first.blocks.get(Chunk::block_index(11, 5, 10)),
+            Block::named("log")
+        );
+        assert_eq!(
+            small_first.blocks.get(Chunk::block_index(9, 6, 9)),
+            Block::named("stone")
+        );
+    }
+
+    #[test]
+    fn column_hashes_depend_on_everything() {
+        let hash = hash_column(1, 0, 5, -7);
+        assert_eq!(hash, hash_column(1, 0, 5, -7));
+        assert_ne!(hash, hash_column(2, 0, 5, -7));
+        assert_ne!(hash, hash_column(1, 1, 5, -7));
+        assert_ne!(hash, hash_column(1, 0, -7, 5));
+    }
+}
@@ -0,0 +1,99 @@
+use std::f32::consts::TAU;
+
+/// ticks in a day, at 20 ticks a second a day lasts 20 minutes
+pub const DAY_LENGTH: u32 = 24_000;
+const TICKS_PER_SECOND: f32 = 20.0;
+
+/// how much of the sky light is left in the middle of the night
+const MIN_DAYLIGHT: f32 = 0.15;
+
+/// The time of day, in ticks since midnight. The sun rises at a quarter of
+/// [`DAY_LENGTH`] and sets at three quarters.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct TimeOfDay {
+    ticks: f32,
+}
+
+impl TimeOfDay {
+    pub const SUNRISE: Self = Self::from_ticks(DAY_LENGTH / 4);
+    pub const NOON: Self = Self::from_ticks(DAY_LENGTH / 2);
+    pub const SUNSET: Self = Self::from_ticks(DAY_LENGTH * 3 / 4);
+    pub const MIDNIGHT: Self = Self::from_ticks(0);
+
+    /// Wraps around to the same time on any other day.
+    pub const fn from_ticks(ticks: u32) -> Self {
+        Self {
+            ticks: (ticks % DAY_LENGTH) as f32,
+        }
+    }
+
+    pub fn ticks(&self) -> u32 {
+        self.ticks as u32
+    }
+
+    /// Parses a number of ticks, or one of `sunrise`, `day`, `noon`,
+    /// `sunset`, `night` and `midnight`.
+    pub fn parse(text: &str) -> Option<Self> {
+        match text {
+            "sunrise" => Some(Self::SUNRISE),
+            // a while after the sun is up
+            "day" => Some(Self::from_ticks(DAY_LENGTH / 3)),
+            "noon" => Some(Self::NOON),
+            "sunset" => Some(Self::SUNSET),
+            "night" => Some(Self::from_ticks(DAY_LENGTH * 5 / 6)),
+            "midnight" => Some(Self::MIDNIGHT),
+            _ => text.parse().ok().map(Self::from_ticks),
+        }
+    }
+
+    /// Moves the time on by `dt` seconds.
+    pub fn advance(&mut self, dt: f32) {
+        self.ticks = (self.ticks + dt * TICKS_PER_SECOND) % DAY_LENGTH as f32;
+    }
+
+    /// How bright the sky light is, from 1 at noon down to a dim glow at
+    /// night, half way there at sunrise and sunset.
+    pub fn daylight(&self) -> f32 {
+        let sun_height = -(self.ticks / DAY_LENGTH as f32 * TAU).cos();
+        (0.5 + 1.5 * sun_height).clamp(MIN_DAYLIGHT, 1.0)
+    }
+}
+
+impl Default for TimeOfDay {
+    fn default() -> Self {
+        Self::from_ticks(DAY_LENGTH / 3)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn times_parse_by_name_or_ticks() {
+        assert_eq!(TimeOfDay::parse("noon"), Some(TimeOfDay::NOON));
+        assert_eq!(TimeOfDay::parse("midnight").unwrap().ticks(), 0);
+        assert_eq!(TimeOfDay::parse("6000"), Some(TimeOfDay::SUNRISE));
+        // later days wrap around to the same time
+        assert_eq!(TimeOfDay::parse("30000"), Some(TimeOfDay::SUNRISE));
+
+        for text in ["", "Noon", "-1", "1.5", "tomorrow"] {
+            assert_eq!(TimeOfDay::parse(text), None, "{text:?}");
+        }
+    }
+
+    #[test]
+    fn days_wrap_around() {
+        let mut time = TimeOfDay::SUNSET;
+        time.advance(DAY_LENGTH as f32 / TICKS_PER_SECOND / 2.0);
+        assert_eq!(time.ticks(), DAY_LENGTH / 4);
+    }
+
+    #[test]
+    fn daylight_follows_the_sun() {
+        assert_eq!(TimeOfDay::NOON.daylight(), 1.0);
+        assert_eq!(TimeOfDay::MIDNIGHT.daylight(), MIN_DAYLIGHT);
+        assert!((TimeOfDay::SUNRISE.daylight() - 0.5).abs() < 1e-3);
+        assert!((TimeOfDay::SUNSET.daylight() - 0.5).abs() < 1e-3);
+    }
+}
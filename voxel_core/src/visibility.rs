@@ -0,0 +1,276 @@
+use std::collections::VecDeque;
+
+use ahash::{AHashMap, AHashSet};
+use glam::{IVec3, Vec3};
+
+use crate::{
+    block::{Block, RenderLayer},
+    chunk::{CHUNK_SIZE, Chunk, FACE_NORMALS},
+    frustum::{Aabb, Frustum},
+};
+
+const VOLUME: usize = CHUNK_SIZE * CHUNK_SIZE * CHUNK_SIZE;
+
+/// Which faces of a chunk can be seen from which other faces, through voxels
+/// that don't block the view. Faces are in the order of `FACE_NORMALS`.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct FaceConnections([u8; 6]);
+
+impl FaceConnections {
+    /// every face sees every other one, like in a chunk of air
+    pub const ALL: Self = Self([0b111111; 6]);
+    /// no face sees another one, like in a chunk of stone
+    pub const NONE: Self = Self([0; 6]);
+
+    pub fn connects(&self, a: usize, b: usize) -> bool {
+        self.0[a] & (1 << b) != 0
+    }
+
+    /// Connects every pair of the faces in the `faces` bitmask.
+    fn connect_all(&mut self, faces: u8) {
+        for face in 0..6 {
+            if faces & (1 << face) != 0 {
+                self.0[face] |= faces;
+            }
+        }
+    }
+
+    /// Flood fills the see through voxels of the chunk, connecting every
+    /// face a single open region touches. Regions that don't touch the
+    /// border can't be seen from outside, so only fills starting at the
+    /// border matter.
+    pub fn of(chunk: &Chunk) -> Self {
+        if let Some(block) = chunk.blocks.uniform() {
+            return if is_see_through(block) {
+                Self::ALL
+            } else {
+                Self::NONE
+            };
+        }
+
+        let see_through = (0..VOLUME)
+            .map(|index| is_see_through(chunk.blocks.get(index)))
+            .collect::<Vec<bool>>();
+        let mut visited = vec![false; VOLUME];
+        let mut stack = Vec::new();
+        let mut connections = Self::NONE;
+
+        for start in 0..VOLUME {
+            if visited[start] || !see_through[start] || border_faces(start) == 0 {
+                continue;
+            }
+
+            let mut faces = 0;
+            visited[start] = true;
+            stack.push(start);
+            while let Some(index) = stack.pop() {
+                faces |= border_faces(index);
+
+                for neighbor in neighbors(index) {
+                    if !visited[neighbor] && see_through[neighbor] {
+                        visited[neighbor] = true;
+                        stack.push(neighbor);
+                    }
+                }
+            }
+
+            connections.connect_all(faces);
+            if connections == Self::ALL {
+                break;
+            }
+        }
+
+        connections
+    }
+}
+
+/// Whether the view passes through the block, which it does through
+/// everything but opaque blocks.
+fn is_see_through(block: Block) -> bool {
+    block.is_air() || block.render_layer() != RenderLayer::Opaque
+}
+
+/// Bitmask of the chunk faces the voxel at `index` touches.
+fn border_faces(index: usize) -> u8 {
+    let last = CHUNK_SIZE - 1;
+    let (x, y, z) = (
+        index % CHUNK_SIZE,
+        index / CHUNK_SIZE % CHUNK_SIZE,
+        index / (CHUNK_SIZE * CHUNK_SIZE),
+    );
+
+    [z == 0, z == last, x == 0, x == last, y == 0, y == last]
+        .into_iter()
+        .enumerate()
+        .fold(0, |faces, (face, touches)| faces | (touches as u8) << face)
+}
+
+/// Indices of the voxels next to the one at `index` within the chunk.
+fn neighbors(index: usize) -> impl Iterator<Item = usize> {
+    let (x, y, z) = (
+        index % CHUNK_SIZE,
+        index / CHUNK_SIZE % CHUNK_SIZE,
+        index / (CHUNK_SIZE * CHUNK_SIZE),
+    );
+    let last = CHUNK_SIZE - 1;
+    let row = CHUNK_SIZE;
+    let layer = CHUNK_SIZE * CHUNK_SIZE;
+
+    [
+        (z > 0).then(|| index - layer),
+        (z < last).then(|| index + layer),
+        (x > 0).then(|| index - 1),
+        (x < last).then(|| index + 1),
+        (y > 0).then(|| index - row),
+        (y < last).then(|| index + row),
+    ]
+    .into_iter()
+    .flatten()
+}
+
+/// Walks the loaded chunks outward from the one the camera is in, only
+/// stepping from one chunk into the next through faces the chunk connects to
+/// the face it was entered through, and never back towards the camera.
+/// Chunks that can't be reached that way are enclosed, like caves seen from
+/// the surface, and don't need to be drawn. Chunks that haven't been meshed
+/// yet are treated as open.
+///
+/// Returns `None` when the camera isn't in a loaded chunk, as nothing can be
+/// ruled out from there.
+pub fn visible_chunks(
+    chunk_map: &AHashMap<IVec3, Chunk>,
+    camera_position: Vec3,
+    frustum: Option<&Frustum>,
+) -> Option<AHashSet<IVec3>> {
+    let start = Chunk::world_to_chunk_pos(camera_position.floor().as_ivec3());
+    if !chunk_map.contains_key(&start) {
+        return None;
+    }
+
+    let mut visible = AHashSet::from([start]);
+    // the chunk, the face it was entered through and the directions taken
+    // to get there
+    let mut queue = VecDeque::from([(start, None::<usize>, 0u8)]);
+
+    while let Some((position, entered_through, directions)) = queue.pop_front() {
+        let connections = chunk_map
+            .get(&position)
+            .and_then(|chunk| chunk.face_connections)
+            .unwrap_or(FaceConnections::ALL);
+
+        for (face, normal) in FACE_NORMALS.iter().enumerate() {
+            // the opposite face is always next to it in the face order
+            let backwards = face ^ 1;
+            if directions & (1 << backwards) != 0
+                || entered_through.is_some_and(|from| !connections.connects(from, face))
+            {
+                continue;
+            }
+
+            let next = position + normal;
+            if visible.contains(&next) || !chunk_map.contains_key(&next) {
+                continue;
+            }
+
+            if let Some(frustum) = frustum {
+                let min = (next * CHUNK_SIZE as i32).as_vec3();
+                if !frustum.contains_aabb(&Aabb::new(min, min + CHUNK_SIZE as f32)) {
+                    continue;
+                }
+            }
+
+            visible.insert(next);
+            queue.push_back((next, Some(backwards), directions | (1 << face)));
+        }
+    }
+
+    Some(visible)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn stone_chunk(position: IVec3) -> Chunk {
+        let mut chunk = Chunk::new(position);
+        chunk.blocks.fill(Block::named("stone"));
+        chunk.is_empty = false;
+        chunk
+    }
+
+    /// A chunk map with the given chunks, meshed so their connections are known.
+    fn meshed(chunks: impl IntoIterator<Item = Chunk>) -> AHashMap<IVec3, Chunk> {
+        chunks
+            .into_iter()
+            .map(|mut chunk| {
+                chunk.face_connections = Some(FaceConnections::of(&chunk));
+                (chunk.position, chunk)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn uniform_chunks_connect_all_or_nothing() {
+        assert_eq!(
+            FaceConnections::of(&Chunk::new(IVec3::ZERO)),
+            FaceConnections::ALL
+        );
+        assert_eq!(
+            FaceConnections::of(&stone_chunk(IVec3::ZERO)),
+            FaceConnections::NONE
+        );
+    }
+
+    #[test]
+    fn tunnels_connect_their_ends() {
+        let mut chunk = stone_chunk(IVec3::ZERO);
+        for x in 0..CHUNK_SIZE as i32 {
+            for y in 10..12 {
+                for z in 10..12 {
+                    chunk.set_block(IVec3::new(x, y, z), Block::AIR);
+                }
+            }
+        }
+        // a cave that doesn't reach the border can't be seen through
+        chunk.set_block(IVec3::new(20, 20, 20), Block::AIR);
+
+        let connections = FaceConnections::of(&chunk);
+        let ends = [2, 3];
+        for a in 0..6 {
+            for b in 0..6 {
+                let expected = ends.contains(&a) && ends.contains(&b);
+                assert_eq!(connections.connects(a, b), expected, "faces {a} and {b}");
+            }
+        }
+    }
+
+    #[test]
+    fn sealed_chunks_are_culled() {
+        // air, then a wall of stone, then air again along x
+        let mut chunk_map = meshed([
+            Chunk::new(IVec3::ZERO),
+            stone_chunk(IVec3::X),
+            Chunk::new(IVec3::X * 2),
+        ]);
+        let camera = Vec3::splat(16.0);
+
+        let visible = visible_chunks(&chunk_map, camera, None).unwrap();
+        assert_eq!(visible, AHashSet::from([IVec3::ZERO, IVec3::X]));
+
+        // until the wall is meshed nothing is known about what's behind it
+        chunk_map.get_mut(&IVec3::X).unwrap().face_connections = None;
+        let visible = visible_chunks(&chunk_map, camera, None).unwrap();
+        assert!(visible.contains(&(IVec3::X * 2)));
+    }
+
+    #[test]
+    fn the_camera_chunk_is_always_visible() {
+        let chunk_map = meshed([stone_chunk(IVec3::ZERO), stone_chunk(IVec3::Y)]);
+
+        let visible = visible_chunks(&chunk_map, Vec3::splat(16.0), None).unwrap();
+        assert_eq!(visible, AHashSet::from([IVec3::ZERO, IVec3::Y]));
+
+        // outside the loaded chunks nothing can be culled
+        assert!(visible_chunks(&chunk_map, Vec3::splat(-16.0), None).is_none());
+    }
+}
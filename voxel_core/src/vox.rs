@@ -0,0 +1,961 @@
+//! MagicaVoxel `.vox` files, read into and written from [`Schematic`]s.
+//!
+//! MagicaVoxel has z pointing up where the world has y, so models are turned
+//! on their way in and out.
+
+use std::{fs, path::Path};
+
+use ahash::{AHashMap, AHashSet};
+use anyhow::{Context, bail};
+use glam::{I64Vec3, IVec3, Mat3, UVec3, Vec3};
+
+use crate::{
+    block::{Block, registry},
+    chunk::FACE_NORMALS,
+    schematic::{self, Schematic},
+};
+
+const MAGIC: &[u8; 4] = b"VOX ";
+const VERSION: i32 = 150;
+/// the most voxels along any axis of a single model
+const MAX_MODEL_SIZE: u32 = 256;
+/// scene graphs deeper than this are taken to loop back on themselves
+const MAX_SCENE_DEPTH: usize = 64;
+/// the most scene nodes visited while placing the models, since groups
+/// sharing their children can multiply far beyond the number of nodes
+const MAX_SCENE_NODES: usize = 1 << 16;
+/// the most voxels of all the placed models together, a full model of the
+/// largest size
+const MAX_VOXELS: usize = (MAX_MODEL_SIZE * MAX_MODEL_SIZE * MAX_MODEL_SIZE) as usize;
+/// tiles along each side of the atlas, the same as in the shader
+const ATLAS_TILES: u32 = 16;
+
+/// The colour of every block, taken from the atlas, used to match palette
+/// colours to blocks and give exported blocks their colour.
+pub struct BlockColors {
+    colors: Vec<(Block, [u8; 3])>,
+}
+
+impl BlockColors {
+    /// Averages the top texture of every block in an RGBA atlas, leaving out
+    /// the see through texels of blocks like leaves.
+    pub fn from_atlas(pixels: &[u8], width: u32, height: u32) -> Self {
+        let tile_width = width / ATLAS_TILES;
+        let tile_height = height / ATLAS_TILES;
+        let top = FACE_NORMALS
+            .iter()
+            .position(|&normal| normal.y > 0)
+            .unwrap();
+
+        let mut colors = Vec::new();
+        for block in registry().blocks().filter(|block| !block.is_air()) {
+            let tile = block.texture(top) as u32;
+            let origin_x = tile % ATLAS_TILES * tile_width;
+            let origin_y = tile / ATLAS_TILES * tile_height;
+
+            let mut sum = [0u64; 3];
+            let mut count = 0;
+            for y in origin_y..origin_y + tile_height {
+                for x in origin_x..origin_x + tile_width {
+                    let index = ((y * width + x) * 4) as usize;
+                    let Some(texel) = pixels.get(index..index + 4) else {
+                        continue;
+                    };
+                    if texel[3] < 128 {
+                        continue;
+                    }
+                    for (sum, &channel) in sum.iter_mut().zip(texel) {
+                        *sum += channel as u64;
+                    }
+                    count += 1;
+                }
+            }
+
+            if count > 0 {
+                colors.push((block, sum.map(|sum| (sum / count) as u8)));
+            }
+        }
+
+        Self { colors }
+    }
+
+    pub fn color(&self, block: Block) -> Option<[u8; 3]> {
+        self.colors
+            .iter()
+            .find(|(other, _)| *other == block)
+            .map(|&(_, color)| color)
+    }
+
+    /// The placeable block that looks the most like `color`.
+    pub fn closest(&self, color: [u8; 3]) -> Option<Block> {
+        self.colors
+            .iter()
+            .filter(|(block, _)| block.fluid().is_none_or(|fluid| fluid.is_source()))
+            .min_by_key(|(_, other)| {
+                color
+                    .iter()
+                    .zip(other)
+                    .map(|(&a, &b)| (a as i32 - b as i32).pow(2))
+                    .sum::<i32>()
+            })
+            .map(|&(block, _)| block)
+    }
+}
+
+/// Blocks chosen for palette entries by hand, taking priority over the
+/// closest colour.
+///
+/// Written one entry a line as `<palette index> = <block>` or
+/// `#rrggbb = <block>`. Mapping to `air` leaves those voxels out.
+#[derive(Clone, Debug, Default)]
+pub struct PaletteMapping {
+    by_index: AHashMap<u8, Block>,
+    by_color: AHashMap<[u8; 3], Block>,
+}
+
+impl PaletteMapping {
+    pub fn parse(text: &str) -> anyhow::Result<Self> {
+        let mut mapping = Self::default();
+        for line in text.lines() {
+            let Some((key, name)) = line.split_once('=') else {
+                continue;
+            };
+            let (key, name) = (key.trim(), name.trim());
+            let block = registry()
+                .get(name)
+                .with_context(|| format!("unknown block {name}"))?;
+
+            if let Some(hex) = key.strip_prefix('#') {
+                let color = u32::from_str_radix(hex, 16)
+                    .ok()
+                    .filter(|_| hex.len() == 6)
+                    .with_context(|| format!("invalid colour {key}"))?;
+                let [_, r, g, b] = color.to_be_bytes();
+                mapping.by_color.insert([r, g, b], block);
+            } else {
+                let index = key
+                    .parse::<u8>()
+                    .ok()
+                    .filter(|&index| index > 0)
+                    .with_context(|| format!("invalid palette index {key}"))?;
+                mapping.by_index.insert(index, block);
+            }
+        }
+
+        Ok(mapping)
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> anyhow::Result<Self> {
+        let path = path.as_ref();
+        let text = fs::read_to_string(path).with_context(|| format!("unable to read {path:?}"))?;
+        Self::parse(&text).with_context(|| format!("invalid palette mapping {path:?}"))
+    }
+}
+
+pub fn load(
+    path: impl AsRef<Path>,
+    colors: &BlockColors,
+    mapping: &PaletteMapping,
+) -> anyhow::Result<Schematic> {
+    let path = path.as_ref();
+    let bytes = fs::read(path).with_context(|| format!("unable to read {path:?}"))?;
+    decode(&bytes, colors, mapping).with_context(|| format!("unable to load {path:?}"))
+}
+
+pub fn save(
+    path: impl AsRef<Path>,
+    schematic: &Schematic,
+    colors: &BlockColors,
+) -> anyhow::Result<()> {
+    let path = path.as_ref();
+    if let Some(directory) = path.parent() {
+        fs::create_dir_all(directory)?;
+    }
+    fs::write(path, encode(schematic, colors)?).with_context(|| format!("unable to write {path:?}"))
+}
+
+/// A model of a `.vox` file, its voxels being x, y, z and palette index.
+struct Model {
+    size: UVec3,
+    voxels: Vec<[u8; 4]>,
+}
+
+/// A transform node of the scene graph, which MagicaVoxel uses to place the
+/// models of a file relative to each other.
+struct TransformNode {
+    child: i32,
+    layer: i32,
+    hidden: bool,
+    rotation: Mat3,
+    translation: Vec3,
+}
+
+#[derive(Default)]
+struct Scene {
+    transforms: AHashMap<i32, TransformNode>,
+    groups: AHashMap<i32, Vec<i32>>,
+    shapes: AHashMap<i32, Vec<i32>>,
+    hidden_layers: AHashSet<i32>,
+}
+
+impl Scene {
+    /// Finds every visible model below `node`, with the rotation and
+    /// translation that places it in the scene. `visited` counts the nodes
+    /// walked so far.
+    fn place(
+        &self,
+        node: i32,
+        rotation: Mat3,
+        translation: Vec3,
+        depth: usize,
+        visited: &mut usize,
+        placed: &mut Vec<(i32, Mat3, Vec3)>,
+    ) -> anyhow::Result<()> {
+        if depth > MAX_SCENE_DEPTH {
+            bail!("the scene graph is nested too deep");
+        }
+        *visited += 1;
+        if *visited > MAX_SCENE_NODES {
+            bail!("the scene graph places more than {MAX_SCENE_NODES} nodes");
+        }
+
+        if let Some(transform) = self.transforms.get(&node) {
+            if transform.hidden || self.hidden_layers.contains(&transform.layer) {
+                return Ok(());
+            }
+            self.place(
+                transform.child,
+                rotation * transform.rotation,
+                rotation * transform.translation + translation,
+                depth + 1,
+                visited,
+                placed,
+            )?;
+        } else if let Some(children) = self.groups.get(&node) {
+            for &child in children {
+                self.place(child, rotation, translation, depth + 1, visited, placed)?;
+            }
+        } else if let Some(models) = self.shapes.get(&node) {
+            placed.extend(models.iter().map(|&model| (model, rotation, translation)));
+        } else {
+            bail!("missing scene node {node}");
+        }
+
+        Ok(())
+    }
+}
+
+/// Reads a `.vox` file into a schematic just big enough for every visible
+/// model, placed the way the scene graph places them. Palette entries become
+/// the block the mapping picks for them, or the one with the closest colour.
+pub fn decode(
+    bytes: &[u8],
+    colors: &BlockColors,
+    mapping: &PaletteMapping,
+) -> anyhow::Result<Schematic> {
+    let mut reader = Reader(bytes);
+    if reader.take(4)? != MAGIC {
+        bail!("not a vox file");
+    }
+    let _version = reader.i32()?;
+    let (id, _, children) = reader.chunk()?;
+    if id != b"MAIN" {
+        bail!("missing the main chunk");
+    }
+
+    let mut models = Vec::new();
+    let mut size = None;
+    let mut palette = default_palette();
+    let mut scene = Scene::default();
+    let mut reader = Reader(children);
+    while !reader.0.is_empty() {
+        let (id, content, _) = reader.chunk()?;
+        let mut content = Reader(content);
+        match id {
+            b"SIZE" => {
+                let model_size = UVec3::new(content.u32()?, content.u32()?, content.u32()?);
+                if model_size.cmpgt(UVec3::splat(MAX_MODEL_SIZE)).any() {
+                    bail!("model of {model_size} is too large");
+                }
+                size = Some(model_size);
+            }
+            b"XYZI" => {
+                let size = size.take().context("voxels without a size")?;
+                let count = content.u32()? as usize;
+                let voxels = content
+                    .take(count.checked_mul(4).context("too many voxels")?)?
+                    .chunks_exact(4)
+                    .map(|voxel| <[u8; 4]>::try_from(voxel).unwrap())
+                    .collect::<Vec<_>>();
+                if let Some(voxel) = voxels.iter().find(|voxel| {
+                    UVec3::new(voxel[0] as u32, voxel[1] as u32, voxel[2] as u32)
+                        .cmpge(size)
+                        .any()
+                }) {
+                    bail!("voxel {voxel:?} is outside of its model");
+                }
+                models.push(Model { size, voxels });
+            }
+            b"RGBA" => {
+                // the first colour is for palette index 1, index 0 is empty
+                for (index, color) in content.take(255 * 4)?.chunks_exact(4).enumerate() {
+                    palette[index + 1] = [color[0], color[1], color[2]];
+                }
+            }
+            b"nTRN" => {
+                let node = content.i32()?;
+                let attributes = content.dict()?;
+                let child = content.i32()?;
+                let _reserved = content.i32()?;
+                let layer = content.i32()?;
+                let frames = content.i32()?;
+                // only the first frame of animations is used
+                let frame = if frames > 0 {
+                    content.dict()?
+                } else {
+                    AHashMap::new()
+                };
+
+                let translation = match frame.get("_t") {
+                    Some(text) => parse_translation(text)?,
+                    None => Vec3::ZERO,
+                };
+                let rotation = match frame.get("_r") {
+                    Some(text) => parse_rotation(text)?,
+                    None => Mat3::IDENTITY,
+                };
+                scene.transforms.insert(
+                    node,
+                    TransformNode {
+                        child,
+                        layer,
+                        hidden: attributes.get("_hidden").is_some_and(|value| value == "1"),
+                        rotation,
+                        translation,
+                    },
+                );
+            }
+            b"nGRP" => {
+                let node = content.i32()?;
+                let _attributes = content.dict()?;
+                let count = content.length(4)?;
+                let children = (0..count)
+                    .map(|_| content.i32())
+                    .collect::<anyhow::Result<_>>()?;
+                scene.groups.insert(node, children);
+            }
+            b"nSHP" => {
+                let node = content.i32()?;
+                let _attributes = content.dict()?;
+                let count = content.length(4)?;
+                let mut shape_models = Vec::with_capacity(count);
+                for _ in 0..count {
+                    shape_models.push(content.i32()?);
+                    let _attributes = content.dict()?;
+                }
+                scene.shapes.insert(node, shape_models);
+            }
+            b"LAYR" => {
+                let layer = content.i32()?;
+                let attributes = content.dict()?;
+                if attributes.get("_hidden").is_some_and(|value| value == "1") {
+                    scene.hidden_layers.insert(layer);
+                }
+            }
+            // materials, cameras, notes and the like don't matter here
+            _ => (),
+        }
+    }
+
+    if models.is_empty() {
+        bail!("there are no models");
+    }
+
+    let mut placed = Vec::new();
+    if scene.transforms.contains_key(&0) {
+        scene.place(0, Mat3::IDENTITY, Vec3::ZERO, 0, &mut 0, &mut placed)?;
+    } else {
+        // files from before the scene graph just have their models side by side
+        let mut x = 0.0;
+        for (index, model) in models.iter().enumerate() {
+            let center = (model.size / 2).as_vec3();
+            placed.push((
+                index as i32,
+                Mat3::IDENTITY,
+                Vec3::new(x, 0.0, 0.0) + center,
+            ));
+            x += model.size.x as f32;
+        }
+    }
+
+    // models of a file that fits in a schematic can't be spread out further
+    // than all of them side by side, which also keeps block positions from
+    // overflowing
+    let max_translation =
+        (schematic::MAX_SIZE as usize * models.len()).min(i32::MAX as usize / 2) as f32;
+
+    // the same models placed over and over can add up to more than fits in memory
+    let voxel_count = placed
+        .iter()
+        .filter_map(|&(model, ..)| models.get(usize::try_from(model).ok()?))
+        .map(|model| model.voxels.len())
+        .sum::<usize>();
+    if voxel_count > MAX_VOXELS {
+        bail!("the models have {voxel_count} voxels together, more than {MAX_VOXELS}");
+    }
+
+    let blocks = palette_blocks(&palette, colors, mapping);
+    let mut voxels = Vec::new();
+    for (model, rotation, translation) in placed {
+        if translation.abs().max_element() > max_translation {
+            bail!("model {model} is placed too far out at {translation}");
+        }
+        let model = usize::try_from(model)
+            .ok()
+            .and_then(|model| models.get(model))
+            .with_context(|| format!("missing model {model}"))?;
+        let half_size = model.size.as_vec3() / 2.0;
+        for &[x, y, z, color] in &model.voxels {
+            let block = blocks[color as usize];
+            if block.is_air() {
+                continue;
+            }
+            // the centre of the voxel relative to the centre of the model
+            let center = Vec3::new(x as f32, y as f32, z as f32) + 0.5 - half_size;
+            let position = (rotation * center + translation).floor().as_ivec3();
+            voxels.push((IVec3::new(position.x, position.z, -position.y), block));
+        }
+    }
+
+    let Some(min) = voxels
+        .iter()
+        .map(|&(position, _)| position)
+        .reduce(IVec3::min)
+    else {
+        bail!("there are no visible voxels");
+    };
+    let max = voxels
+        .iter()
+        .map(|&(position, _)| position)
+        .reduce(IVec3::max)
+        .unwrap();
+    let size = max.as_i64vec3() - min.as_i64vec3() + 1;
+    if size.cmpgt(I64Vec3::splat(schematic::MAX_SIZE as i64)).any() {
+        bail!("the models take up {size} blocks, more than fit in a schematic");
+    }
+    let mut schematic = Schematic::new(size.as_uvec3())?;
+    for (position, block) in voxels {
+        schematic.set((position - min).as_uvec3(), block);
+    }
+
+    Ok(schematic)
+}
+
+/// Writes a schematic as a `.vox` file, cut into several models if it is
+/// larger than a single model can be, with a palette entry for every kind of
+/// block in it.
+pub fn encode(schematic: &Schematic, colors: &BlockColors) -> anyhow::Result<Vec<u8>> {
+    let size = schematic.size();
+    // x, y and z in MagicaVoxel
+    let vox_size = UVec3::new(size.x, size.z, size.y);
+    let to_schematic =
+        |position: UVec3| UVec3::new(position.x, position.z, size.z - 1 - position.y);
+
+    let mut palette = Vec::new();
+    let mut palette_indices = AHashMap::new();
+    let mut models = Vec::new();
+    for tile_z in (0..vox_size.z).step_by(MAX_MODEL_SIZE as usize) {
+        for tile_y in (0..vox_size.y).step_by(MAX_MODEL_SIZE as usize) {
+            for tile_x in (0..vox_size.x).step_by(MAX_MODEL_SIZE as usize) {
+                let offset = UVec3::new(tile_x, tile_y, tile_z);
+                let model_size = (vox_size - offset).min(UVec3::splat(MAX_MODEL_SIZE));
+
+                let mut voxels = Vec::new();
+                for z in 0..model_size.z {
+                    for y in 0..model_size.y {
+                        for x in 0..model_size.x {
+                            let block = schematic.get(to_schematic(offset + UVec3::new(x, y, z)));
+                            if block.is_air() {
+                                continue;
+                            }
+                            let index = match palette_indices.get(&block) {
+                                Some(&index) => index,
+                                None => {
+                                    if palette.len() == 255 {
+                                        bail!(
+                                            "more than 255 kinds of blocks don't fit in a palette"
+                                        );
+                                    }
+                                    palette.push(block);
+                                    palette_indices.insert(block, palette.len() as u8);
+                                    palette.len() as u8
+                                }
+                            };
+                            voxels.push([x as u8, y as u8, z as u8, index]);
+                        }
+                    }
+                }
+
+                if !voxels.is_empty() {
+                    models.push((
+                        offset,
+                        Model {
+                            size: model_size,
+                            voxels,
+                        },
+                    ));
+                }
+            }
+        }
+    }
+    if models.is_empty() {
+        bail!("there are no blocks to export");
+    }
+
+    let mut children = Vec::new();
+    for (_, model) in &models {
+        let mut content = Vec::new();
+        for length in model.size.to_array() {
+            content.extend_from_slice(&length.to_le_bytes());
+        }
+        write_chunk(&mut children, b"SIZE", &content);
+
+        let mut content = (model.voxels.len() as u32).to_le_bytes().to_vec();
+        content.extend(model.voxels.iter().flatten());
+        write_chunk(&mut children, b"XYZI", &content);
+    }
+
+    // a transform at the root holding a group with a transform and a shape
+    // for every model
+    let model_count = models.len() as i32;
+    let mut content = Vec::new();
+    write_transform(&mut content, 0, 1, -1, None);
+    write_chunk(&mut children, b"nTRN", &content);
+
+    let mut content = Vec::new();
+    content.extend_from_slice(&1i32.to_le_bytes());
+    write_dict(&mut content, &[]);
+    content.extend_from_slice(&model_count.to_le_bytes());
+    for model in 0..model_count {
+        content.extend_from_slice(&(2 + 2 * model).to_le_bytes());
+    }
+    write_chunk(&mut children, b"nGRP", &content);
+
+    for (model, (offset, Model { size, .. })) in (0..model_count).zip(&models) {
+        // MagicaVoxel places models by their centre
+        let translation = (*offset + *size / 2).as_ivec3();
+        let node = 2 + 2 * model;
+        let mut content = Vec::new();
+        write_transform(&mut content, node, node + 1, 0, Some(translation));
+        write_chunk(&mut children, b"nTRN", &content);
+
+        let mut content = Vec::new();
+        content.extend_from_slice(&(node + 1).to_le_bytes());
+        write_dict(&mut content, &[]);
+        content.extend_from_slice(&1i32.to_le_bytes());
+        content.extend_from_slice(&model.to_le_bytes());
+        write_dict(&mut content, &[]);
+        write_chunk(&mut children, b"nSHP", &content);
+    }
+
+    let mut content = Vec::new();
+    content.extend_from_slice(&0i32.to_le_bytes());
+    write_dict(&mut content, &[]);
+    content.extend_from_slice(&(-1i32).to_le_bytes());
+    write_chunk(&mut children, b"LAYR", &content);
+
+    let mut content = vec![0; 256 * 4];
+    for (color, block) in content.chunks_exact_mut(4).zip(&palette) {
+        let [r, g, b] = colors.color(*block).unwrap_or_else(|| {
+            log::warn!("{} has no colour, exporting it as grey", block.name());
+            [128; 3]
+        });
+        color.copy_from_slice(&[r, g, b, 255]);
+    }
+    write_chunk(&mut children, b"RGBA", &content);
+
+    let mut bytes = MAGIC.to_vec();
+    bytes.extend_from_slice(&VERSION.to_le_bytes());
+    bytes.extend_from_slice(b"MAIN");
+    bytes.extend_from_slice(&0u32.to_le_bytes());
+    bytes.extend_from_slice(&(children.len() as u32).to_le_bytes());
+    bytes.extend(children);
+
+    Ok(bytes)
+}
+
+/// The block of every palette index, picked by the mapping or by colour.
+fn palette_blocks(
+    palette: &[[u8; 3]; 256],
+    colors: &BlockColors,
+    mapping: &PaletteMapping,
+) -> [Block; 256] {
+    let mut blocks = [Block::AIR; 256];
+    for (index, color) in palette.iter().enumerate().skip(1) {
+        blocks[index] = mapping
+            .by_index
+            .get(&(index as u8))
+            .or_else(|| mapping.by_color.get(color))
+            .copied()
+            .or_else(|| colors.closest(*color))
+            .unwrap_or(Block::AIR);
+    }
+
+    blocks
+}
+
+/// The palette files without their own palette use, built the same way
+/// MagicaVoxel builds it: a cube of six shades of every channel followed by
+/// ramps of red, green, blue and grey.
+fn default_palette() -> [[u8; 3]; 256] {
+    let mut palette = [[0; 3]; 256];
+    let shades = [0xff, 0xcc, 0x99, 0x66, 0x33, 0x00];
+    let mut index = 1;
+    for &r in &shades {
+        for &g in &shades {
+            for &b in &shades {
+                // black comes last in the ramps instead
+                if index < 216 {
+                    palette[index] = [r, g, b];
+                    index += 1;
+                }
+            }
+        }
+    }
+
+    let ramp = [0xee, 0xdd, 0xbb, 0xaa, 0x88, 0x77, 0x55, 0x44, 0x22, 0x11];
+    for channel in 0..4 {
+        for &shade in &ramp {
+            palette[index] = match channel {
+                0 => [shade, 0, 0],
+                1 => [0, shade, 0],
+                2 => [0, 0, shade],
+                _ => [shade; 3],
+            };
+            index += 1;
+        }
+    }
+
+    palette
+}
+
+/// Parses the translation of a transform node, like `1 -2 3`.
+fn parse_translation(text: &str) -> anyhow::Result<Vec3> {
+    let values = text
+        .split_whitespace()
+        .map(|value| value.parse::<i32>())
+        .collect::<Result<Vec<i32>, _>>()
+        .ok()
+        .filter(|values| values.len() == 3)
+        .with_context(|| format!("invalid translation {text}"))?;
+    Ok(IVec3::from_slice(&values).as_vec3())
+}
+
+/// Parses the rotation of a transform node, a byte giving the column of the
+/// one non-zero entry in the first two rows and the sign of all three.
+fn parse_rotation(text: &str) -> anyhow::Result<Mat3> {
+    let bits = text
+        .parse::<u8>()
+        .with_context(|| format!("invalid rotation {text}"))?;
+    let first = (bits & 3) as usize;
+    let second = ((bits >> 2) & 3) as usize;
+    if first > 2 || second > 2 || first == second {
+        bail!("invalid rotation {text}");
+    }
+    let columns = [first, second, 3 - first - second];
+
+    let mut rows = [Vec3::ZERO; 3];
+    for (row, (values, column)) in rows.iter_mut().zip(columns).enumerate() {
+        values[column] = if bits & (1 << (4 + row)) != 0 {
+            -1.0
+        } else {
+            1.0
+        };
+    }
+
+    Ok(Mat3::from_cols(rows[0], rows[1], rows[2]).transpose())
+}
+
+fn write_chunk(bytes: &mut Vec<u8>, id: &[u8; 4], content: &[u8]) {
+    bytes.extend_from_slice(id);
+    bytes.extend_from_slice(&(content.len() as u32).to_le_bytes());
+    bytes.extend_from_slice(&0u32.to_le_bytes());
+    bytes.extend_from_slice(content);
+}
+
+fn write_dict(bytes: &mut Vec<u8>, entries: &[(&str, &str)]) {
+    bytes.extend_from_slice(&(entries.len() as u32).to_le_bytes());
+    for text in entries.iter().flat_map(|(key, value)| [key, value]) {
+        bytes.extend_from_slice(&(text.len() as u32).to_le_bytes());
+        bytes.extend_from_slice(text.as_bytes());
+    }
+}
+
+fn write_transform(
+    bytes: &mut Vec<u8>,
+    node: i32,
+    child: i32,
+    layer: i32,
+    translation: Option<IVec3>,
+) {
+    bytes.extend_from_slice(&node.to_le_bytes());
+    write_dict(bytes, &[]);
+    bytes.extend_from_slice(&child.to_le_bytes());
+    bytes.extend_from_slice(&(-1i32).to_le_bytes());
+    bytes.extend_from_slice(&layer.to_le_bytes());
+    bytes.extend_from_slice(&1i32.to_le_bytes());
+    match translation {
+        Some(translation) => {
+            let text = format!("{} {} {}", translation.x, translation.y, translation.z);
+            write_dict(bytes, &[("_t", &text)]);
+        }
+        None => write_dict(bytes, &[]),
+    }
+}
+
+/// Reads little endian values one after the other.
+struct Reader<'a>(&'a [u8]);
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, length: usize) -> anyhow::Result<&'a [u8]> {
+        if self.0.len() < length {
+            bail!("vox data ends early");
+        }
+        let (bytes, rest) = self.0.split_at(length);
+        self.0 = rest;
+        Ok(bytes)
+    }
+
+    fn u32(&mut self) -> anyhow::Result<u32> {
+        Ok(u32::from_le_bytes(self.take(4)?.try_into().unwrap()))
+    }
+
+    fn i32(&mut self) -> anyhow::Result<i32> {
+        Ok(i32::from_le_bytes(self.take(4)?.try_into().unwrap()))
+    }
+
+    /// The length of a list, which may not claim more elements than there
+    /// are bytes left.
+    fn length(&mut self, element_size: usize) -> anyhow::Result<usize> {
+        let length = self.u32()? as usize;
+        if length.saturating_mul(element_size) > self.0.len() {
+            bail!("vox length {length} runs past the end");
+        }
+        Ok(length)
+    }
+
+    fn string(&mut self) -> anyhow::Result<String> {
+        let length = self.length(1)?;
+        Ok(String::from_utf8_lossy(self.take(length)?).into_owned())
+    }
+
+    fn dict(&mut self) -> anyhow::Result<AHashMap<String, String>> {
+        let count = self.length(8)?;
+        let mut dict = AHashMap::with_capacity(count);
+        for _ in 0..count {
+            let key = self.string()?;
+            dict.insert(key, self.string()?);
+        }
+        Ok(dict)
+    }
+
+    /// Splits off a chunk, returning its id, its content and its children.
+    fn chunk(&mut self) -> anyhow::Result<(&'a [u8], &'a [u8], &'a [u8])> {
+        let id = self.take(4)?;
+        let content_length = self.u32()? as usize;
+        let children_length = self.u32()? as usize;
+        Ok((id, self.take(content_length)?, self.take(children_length)?))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn colors() -> BlockColors {
+        BlockColors {
+            colors: [
+                ("stone", [100, 100, 100]),
+                ("dirt", [120, 80, 40]),
+                ("log", [90, 60, 30]),
+                ("lamp", [250, 220, 120]),
+            ]
+            .into_iter()
+            .map(|(name, color)| (Block::named(name), color))
+            .collect(),
+        }
+    }
+
+    fn model(out: &mut Vec<u8>, size: [u32; 3], voxels: &[[u8; 4]]) {
+        write_chunk(out, b"SIZE", bytemuck::cast_slice(&size));
+        let mut content = (voxels.len() as u32).to_le_bytes().to_vec();
+        content.extend(voxels.iter().flatten());
+        write_chunk(out, b"XYZI", &content);
+    }
+
+    fn transform(
+        out: &mut Vec<u8>,
+        node: i32,
+        child: i32,
+        attributes: &[(&str, &str)],
+        frame: &[(&str, &str)],
+    ) {
+        let mut content = node.to_le_bytes().to_vec();
+        write_dict(&mut content, attributes);
+        for value in [child, -1, 0, 1] {
+            content.extend_from_slice(&value.to_le_bytes());
+        }
+        write_dict(&mut content, frame);
+        write_chunk(out, b"nTRN", &content);
+    }
+
+    fn group(out: &mut Vec<u8>, node: i32, children: &[i32]) {
+        let mut content = node.to_le_bytes().to_vec();
+        write_dict(&mut content, &[]);
+        content.extend_from_slice(&(children.len() as i32).to_le_bytes());
+        for child in children {
+            content.extend_from_slice(&child.to_le_bytes());
+        }
+        write_chunk(out, b"nGRP", &content);
+    }
+
+    fn shape(out: &mut Vec<u8>, node: i32, model: i32) {
+        let mut content = node.to_le_bytes().to_vec();
+        write_dict(&mut content, &[]);
+        content.extend_from_slice(&1i32.to_le_bytes());
+        content.extend_from_slice(&model.to_le_bytes());
+        write_dict(&mut content, &[]);
+        write_chunk(out, b"nSHP", &content);
+    }
+
+    fn file(children: &[u8]) -> Vec<u8> {
+        let mut bytes = MAGIC.to_vec();
+        bytes.extend_from_slice(&VERSION.to_le_bytes());
+        write_chunk(&mut bytes, b"MAIN", &[]);
+        // the children length of the main chunk
+        let length = bytes.len() - 4;
+        bytes[length..].copy_from_slice(&(children.len() as u32).to_le_bytes());
+        bytes.extend_from_slice(children);
+        bytes
+    }
+
+    /// A flat model and a column of three voxels moved 5 along x, in a group
+    /// with a hidden copy of the first one, where the second one is moved to.
+    fn scene(second_translation: &str) -> Vec<u8> {
+        let mut children = Vec::new();
+        model(&mut children, [2, 2, 1], &[[0, 0, 0, 1], [1, 1, 0, 2]]);
+        model(
+            &mut children,
+            [1, 1, 3],
+            &[[0, 0, 0, 3], [0, 0, 1, 3], [0, 0, 2, 3]],
+        );
+
+        transform(&mut children, 0, 1, &[], &[]);
+        group(&mut children, 1, &[2, 4, 6]);
+        transform(&mut children, 2, 3, &[], &[("_t", "0 0 0")]);
+        shape(&mut children, 3, 0);
+        transform(&mut children, 4, 5, &[], &[("_t", second_translation)]);
+        shape(&mut children, 5, 1);
+        transform(
+            &mut children,
+            6,
+            7,
+            &[("_hidden", "1")],
+            &[("_t", "-20 0 0")],
+        );
+        shape(&mut children, 7, 0);
+
+        let mut palette = vec![0; 256 * 4];
+        palette[..12].copy_from_slice(&[101, 99, 100, 255, 1, 2, 3, 255, 255, 230, 130, 255]);
+        write_chunk(&mut children, b"RGBA", &palette);
+
+        file(&children)
+    }
+
+    #[test]
+    fn scenes_are_placed_like_in_magicavoxel() {
+        // index 1 goes by the closest colour, 2 and 3 by the mapping
+        let mapping = PaletteMapping::parse("#010203 = log\n3 = dirt").unwrap();
+        let schematic = decode(&scene("5 0 1"), &colors(), &mapping).unwrap();
+
+        // vox z is up and vox y runs the other way along our z
+        assert_eq!(schematic.size(), UVec3::new(7, 3, 2));
+        assert_eq!(schematic.get(UVec3::new(0, 0, 1)), Block::named("stone"));
+        assert_eq!(schematic.get(UVec3::new(1, 0, 0)), Block::named("log"));
+        for y in 0..3 {
+            assert_eq!(schematic.get(UVec3::new(6, y, 0)), Block::named("dirt"));
+        }
+        let solid = (0..3)
+            .flat_map(|y| (0..2).flat_map(move |z| (0..7).map(move |x| UVec3::new(x, y, z))))
+            .filter(|&position| !schematic.get(position).is_air())
+            .count();
+        assert_eq!(solid, 5);
+
+        // without a mapping the closest colour wins
+        let schematic = decode(&scene("5 0 1"), &colors(), &PaletteMapping::default()).unwrap();
+        assert_eq!(schematic.get(UVec3::new(1, 0, 0)), Block::named("log"));
+        assert_eq!(schematic.get(UVec3::new(6, 0, 0)), Block::named("lamp"));
+    }
+
+    #[test]
+    fn far_out_models_are_refused() {
+        let mapping = PaletteMapping::default();
+        for translation in ["2000000000 0 0", "0 -2147483648 0", "1500 0 0"] {
+            assert!(
+                decode(&scene(translation), &colors(), &mapping).is_err(),
+                "{translation}"
+            );
+        }
+    }
+
+    #[test]
+    fn scenes_placing_too_much_are_refused() {
+        let mapping = PaletteMapping::default();
+
+        // every group places the next one twice, which would take 2^40 steps
+        let mut children = Vec::new();
+        model(&mut children, [1, 1, 1], &[[0, 0, 0, 1]]);
+        transform(&mut children, 0, 1, &[], &[]);
+        for node in 1..=40 {
+            group(&mut children, node, &[node + 1, node + 1]);
+        }
+        shape(&mut children, 41, 0);
+        let error = decode(&file(&children), &colors(), &mapping).unwrap_err();
+        assert!(error.to_string().contains("nodes"), "{error}");
+
+        // a few nodes placing the same large model over and over
+        let mut children = Vec::new();
+        let voxels = (0..16 * 16 * 16)
+            .map(|i: u32| [(i % 16) as u8, (i / 16 % 16) as u8, (i / 256) as u8, 1])
+            .collect::<Vec<[u8; 4]>>();
+        model(&mut children, [16, 16, 16], &voxels);
+        transform(&mut children, 0, 1, &[], &[]);
+        group(&mut children, 1, &[2; 4097]);
+        shape(&mut children, 2, 0);
+        let error = decode(&file(&children), &colors(), &mapping).unwrap_err();
+        assert!(error.to_string().contains("voxels"), "{error}");
+    }
+
+    #[test]
+    fn schematics_round_trip() {
+        // wider than a model can be, so it is split in two
+        let mut schematic = Schematic::new(UVec3::new(300, 2, 3)).unwrap();
+        let blocks = ["stone", "dirt", "log", "lamp"].map(Block::named);
+        for (i, position) in [
+            UVec3::new(0, 0, 0),
+            UVec3::new(299, 1, 2),
+            UVec3::new(255, 0, 1),
+            UVec3::new(256, 1, 0),
+            UVec3::new(10, 1, 2),
+        ]
+        .into_iter()
+        .enumerate()
+        {
+            schematic.set(position, blocks[i % blocks.len()]);
+        }
+
+        let bytes = encode(&schematic, &colors()).unwrap();
+        let decoded = decode(&bytes, &colors(), &PaletteMapping::default()).unwrap();
+        assert_eq!(decoded, schematic);
+    }
+}
@@ -0,0 +1,543 @@
+use std::{
+    fs::{self, File},
+    io::{Read, Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+};
+
+use ahash::AHashMap;
+use anyhow::{Context, bail};
+use flate2::{Compression, read::ZlibDecoder, write::ZlibEncoder};
+use glam::IVec3;
+
+use crate::{
+    block::{Block, registry},
+    chunk::{CHUNK_SIZE, Chunk},
+    time::TimeOfDay,
+};
+
+const CHUNK_VOLUME: usize = CHUNK_SIZE * CHUNK_SIZE * CHUNK_SIZE;
+
+/// number of chunks along each axis of a region file
+pub const REGION_SIZE: i32 = 8;
+const REGION_VOLUME: usize = (REGION_SIZE * REGION_SIZE * REGION_SIZE) as usize;
+
+const REGION_MAGIC: &[u8; 4] = b"VXRG";
+const REGION_VERSION: u32 = 1;
+const CHUNK_FORMAT_VERSION: u8 = 2;
+/// the most a stored chunk can decompress to, which is a palette with a block
+/// of the longest possible name for every voxel followed by the indices
+const MAX_CHUNK_BYTES: usize = 3 + CHUNK_VOLUME * (1 + u8::MAX as usize) + CHUNK_VOLUME * 2;
+
+/// Version 1 chunks stored the block IDs of the old hardcoded block list,
+/// these are the names those IDs map to.
+const LEGACY_BLOCK_NAMES: [&str; 13] = [
+    "air",
+    "dirt",
+    "grass",
+    "stone",
+    "log",
+    "plank",
+    "leaves",
+    "sand",
+    "bricks",
+    "snow",
+    "ice",
+    "stone_bricks",
+    "lamp",
+];
+
+/// 4 byte magic + 4 byte version + an (offset, length) pair for every chunk slot
+const HEADER_SIZE: usize = 8 + REGION_VOLUME * 8;
+
+/// World wide settings stored next to the region files, so that chunks that
+/// were never saved regenerate the same way when the world is reopened.
+#[derive(Clone, Debug)]
+pub struct LevelInfo {
+    pub seed: u32,
+    pub generator: String,
+    /// the time of day when the world was last saved
+    pub time: TimeOfDay,
+}
+
+/// Stores modified chunks on disk, grouped into region files of
+/// `REGION_SIZE`³ chunks where every chunk is compressed on its own.
+pub struct WorldSave {
+    directory: PathBuf,
+}
+
+impl WorldSave {
+    pub fn new(directory: impl AsRef<Path>) -> anyhow::Result<Self> {
+        let directory = directory.as_ref().to_path_buf();
+        fs::create_dir_all(directory.join("region"))
+            .with_context(|| format!("unable to create world directory {directory:?}"))?;
+
+        Ok(Self { directory })
+    }
+
+    pub fn load_level_info(&self) -> anyhow::Result<Option<LevelInfo>> {
+        let path = self.directory.join("level.txt");
+        let text = match fs::read_to_string(&path) {
+            Ok(text) => text,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e).with_context(|| format!("unable to read {path:?}")),
+        };
+
+        let mut seed = None;
+        let mut generator = None;
+        let mut time = TimeOfDay::default();
+        for line in text.lines() {
+            let Some((key, value)) = line.split_once('=') else {
+                continue;
+            };
+            match key.trim() {
+                "seed" => seed = Some(value.trim().parse()?),
+                "generator" => generator = Some(value.trim().to_string()),
+                "time" => time = TimeOfDay::from_ticks(value.trim().parse()?),
+                _ => (),
+            }
+        }
+
+        Ok(Some(LevelInfo {
+            seed: seed.with_context(|| format!("missing seed in {path:?}"))?,
+            generator: generator.unwrap_or_else(|| "default".to_string()),
+            time,
+        }))
+    }
+
+    pub fn save_level_info(&self, info: &LevelInfo) -> anyhow::Result<()> {
+        let path = self.directory.join("level.txt");
+        fs::write(
+            &path,
+            format!(
+                "seed = {}\ngenerator = {}\ntime = {}\n",
+                info.seed,
+                info.generator,
+                info.time.ticks()
+            ),
+        )
+        .with_context(|| format!("unable to write {path:?}"))
+    }
+
+    pub fn chunk_to_region_pos(chunk_position: IVec3) -> IVec3 {
+        chunk_position.div_euclid(IVec3::splat(REGION_SIZE))
+    }
+
+    fn region_path(&self, region_position: IVec3) -> PathBuf {
+        self.directory.join("region").join(format!(
+            "r.{}.{}.{}.bin",
+            region_position.x, region_position.y, region_position.z
+        ))
+    }
+
+    fn slot_index(chunk_position: IVec3) -> usize {
+        let local = chunk_position.rem_euclid(IVec3::splat(REGION_SIZE));
+        (REGION_SIZE * REGION_SIZE * local.z + REGION_SIZE * local.y + local.x) as usize
+    }
+
+    /// Loads the stored blocks for a chunk, returning `None` if it was never saved.
+    pub fn load_chunk(&self, position: IVec3) -> anyhow::Result<Option<Chunk>> {
+        let path = self.region_path(Self::chunk_to_region_pos(position));
+        let mut file = match File::open(&path) {
+            Ok(file) => file,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e).with_context(|| format!("unable to open {path:?}")),
+        };
+
+        let mut header = vec![0; HEADER_SIZE];
+        file.read_exact(&mut header)?;
+        check_header(&header)?;
+
+        let slot = 8 + Self::slot_index(position) * 8;
+        let offset = u32::from_le_bytes(header[slot..slot + 4].try_into().unwrap());
+        let length = u32::from_le_bytes(header[slot + 4..slot + 8].try_into().unwrap());
+        if length == 0 {
+            return Ok(None);
+        }
+
+        let mut compressed = vec![0; length as usize];
+        file.seek(SeekFrom::Start(offset as u64))?;
+        file.read_exact(&mut compressed)?;
+
+        let chunk = decode_chunk(position, &compressed)
+            .with_context(|| format!("corrupt chunk {position} in {path:?}"))?;
+
+        Ok(Some(chunk))
+    }
+
+    /// Writes the given chunks to their region files, rewriting each affected region once.
+    pub fn save_chunks<'a>(
+        &self,
+        chunks: impl IntoIterator<Item = &'a Chunk>,
+    ) -> anyhow::Result<()> {
+        let mut by_region: AHashMap<IVec3, Vec<&Chunk>> = AHashMap::new();
+        for chunk in chunks {
+            by_region
+                .entry(Self::chunk_to_region_pos(chunk.position))
+                .or_default()
+                .push(chunk);
+        }
+
+        for (region_position, chunks) in by_region {
+            let path = self.region_path(region_position);
+            let mut slots = read_region_slots(&path)?;
+
+            for chunk in chunks {
+                slots[Self::slot_index(chunk.position)] = Some(encode_chunk(chunk)?);
+            }
+
+            write_region_slots(&path, &slots)?;
+        }
+
+        Ok(())
+    }
+}
+
+fn check_header(header: &[u8]) -> anyhow::Result<()> {
+    if &header[0..4] != REGION_MAGIC {
+        bail!("not a region file");
+    }
+
+    let version = u32::from_le_bytes(header[4..8].try_into().unwrap());
+    if version != REGION_VERSION {
+        bail!("unsupported region version {version}");
+    }
+
+    Ok(())
+}
+
+fn read_region_slots(path: &Path) -> anyhow::Result<Vec<Option<Vec<u8>>>> {
+    let mut slots = vec![None; REGION_VOLUME];
+
+    let data = match fs::read(path) {
+        Ok(data) => data,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(slots),
+        Err(e) => return Err(e).with_context(|| format!("unable to read {path:?}")),
+    };
+
+    if data.len() < HEADER_SIZE {
+        bail!("truncated region file {path:?}");
+    }
+    check_header(&data)?;
+
+    for (i, slot) in slots.iter_mut().enumerate() {
+        let entry = 8 + i * 8;
+        let offset = u32::from_le_bytes(data[entry..entry + 4].try_into().unwrap()) as usize;
+        let length = u32::from_le_bytes(data[entry + 4..entry + 8].try_into().unwrap()) as usize;
+        if length == 0 {
+            continue;
+        }
+
+        let bytes = data
+            .get(offset..offset + length)
+            .with_context(|| format!("chunk slot {i} out of bounds in {path:?}"))?;
+        *slot = Some(bytes.to_vec());
+    }
+
+    Ok(slots)
+}
+
+fn write_region_slots(path: &Path, slots: &[Option<Vec<u8>>]) -> anyhow::Result<()> {
+    let mut header = Vec::with_capacity(HEADER_SIZE);
+    header.extend_from_slice(REGION_MAGIC);
+    header.extend_from_slice(&REGION_VERSION.to_le_bytes());
+
+    let mut body = Vec::new();
+    for slot in slots {
+        let (offset, length) = match slot {
+            Some(bytes) => {
+                let offset = HEADER_SIZE + body.len();
+                body.extend_from_slice(bytes);
+                (offset as u32, bytes.len() as u32)
+            }
+            None => (0, 0),
+        };
+        header.extend_from_slice(&offset.to_le_bytes());
+        header.extend_from_slice(&length.to_le_bytes());
+    }
+
+    // write to a temporary file first so a crash mid-write can't corrupt the region
+    let temp_path = path.with_extension("tmp");
+    let mut file =
+        File::create(&temp_path).with_context(|| format!("unable to create {temp_path:?}"))?;
+    file.write_all(&header)?;
+    file.write_all(&body)?;
+    file.sync_all()?;
+    fs::rename(&temp_path, path).with_context(|| format!("unable to replace {path:?}"))?;
+
+    Ok(())
+}
+
+/// Chunks are stored as a palette of the names of the blocks they contain,
+/// followed by the palette index of every block, so saves don't depend on the
+/// IDs the block registry happens to assign. Servers send chunks to their
+/// clients the same way.
+pub fn encode_chunk(chunk: &Chunk) -> anyhow::Result<Vec<u8>> {
+    let mut palette = Vec::new();
+    let mut palette_indices = AHashMap::new();
+    let mut indices = Vec::with_capacity(CHUNK_VOLUME * 2);
+    for block in chunk.blocks.iter() {
+        let index = *palette_indices.entry(block).or_insert_with(|| {
+            palette.push(block);
+            palette.len() as u16 - 1
+        });
+        indices.extend_from_slice(&index.to_le_bytes());
+    }
+
+    let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+    encoder.write_all(&[CHUNK_FORMAT_VERSION])?;
+    encoder.write_all(&(palette.len() as u16).to_le_bytes())?;
+    for block in palette {
+        let name = block.name().as_bytes();
+        encoder.write_all(&[u8::try_from(name.len()).context("block name too long")?])?;
+        encoder.write_all(name)?;
+    }
+    encoder.write_all(&indices)?;
+
+    Ok(encoder.finish()?)
+}
+
+pub fn decode_chunk(position: IVec3, compressed: &[u8]) -> anyhow::Result<Chunk> {
+    // a broken or malicious chunk could inflate into far more than memory
+    // holds, so reading stops at the size no real chunk gets past
+    let mut data = Vec::new();
+    ZlibDecoder::new(compressed)
+        .take(MAX_CHUNK_BYTES as u64 + 1)
+        .read_to_end(&mut data)?;
+    if data.len() > MAX_CHUNK_BYTES {
+        bail!("chunk data is larger than {MAX_CHUNK_BYTES} bytes");
+    }
+
+    let mut chunk = Chunk::new(position);
+    match data.first() {
+        Some(1) => {
+            if data.len() != 1 + CHUNK_VOLUME {
+                bail!("unexpected chunk length {}", data.len());
+            }
+
+            let palette = resolve_palette(LEGACY_BLOCK_NAMES.iter().copied());
+            for (i, &id) in data[1..].iter().enumerate() {
+                let block = palette
+                    .get(id as usize)
+                    .with_context(|| format!("unknown block id {id}"))?;
+                chunk.blocks.set(i, *block);
+            }
+        }
+        Some(&CHUNK_FORMAT_VERSION) => {
+            let mut cursor = &data[1..];
+            let mut take = |length: usize| {
+                if cursor.len() < length {
+                    bail!("chunk data ends early");
+                }
+                let (bytes, rest) = cursor.split_at(length);
+                cursor = rest;
+                Ok(bytes)
+            };
+
+            let palette_length = u16::from_le_bytes(take(2)?.try_into().unwrap());
+            let mut names = Vec::with_capacity(palette_length as usize);
+            for _ in 0..palette_length {
+                let length = take(1)?[0] as usize;
+                names.push(std::str::from_utf8(take(length)?)?.to_string());
+            }
+            let palette = resolve_palette(names.iter().map(String::as_str));
+
+            let indices = take(CHUNK_VOLUME * 2)?;
+            for (i, index) in indices.chunks_exact(2).enumerate() {
+                let index = u16::from_le_bytes([index[0], index[1]]);
+                let block = palette
+                    .get(index as usize)
+                    .with_context(|| format!("palette index {index} out of range"))?;
+                chunk.blocks.set(i, *block);
+            }
+        }
+        _ => bail!("unsupported chunk format"),
+    }
+    chunk.is_empty = chunk.blocks.uniform() == Some(Block::AIR);
+
+    Ok(chunk)
+}
+
+/// Looks up the blocks of a stored palette, blocks that have since been
+/// removed from the block registry turn into air.
+fn resolve_palette<'a>(names: impl Iterator<Item = &'a str>) -> Vec<Block> {
+    names
+        .map(|name| {
+            registry().get(name).unwrap_or_else(|| {
+                log::warn!(
+                    "unknown block \"{}\" in saved chunk, replacing it with air",
+                    name
+                );
+                Block::AIR
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::{chunk::CHUNK_SIZE, chunk_manager::ChunkManager, generator::NoiseGenerator};
+
+    /// An empty directory of its own for every test.
+    fn temp_world(name: &str) -> PathBuf {
+        let directory =
+            std::env::temp_dir().join(format!("voxel_world_save_{name}_{}", std::process::id()));
+        let _ = fs::remove_dir_all(&directory);
+        directory
+    }
+
+    #[test]
+    fn edits_survive_eviction() {
+        let directory = temp_world("eviction");
+        let mut chunk_manager = ChunkManager::new(1, Arc::new(NoiseGenerator::new(0)));
+        chunk_manager.world_save = Some(Arc::new(WorldSave::new(&directory).unwrap()));
+        chunk_manager.update_around(IVec3::ZERO);
+        chunk_manager.finish_jobs();
+
+        let placed = IVec3::new(5, 40, 7);
+        let removed = IVec3::new(5, -4, 7);
+        chunk_manager.set_block(placed, Block::named("log"));
+        chunk_manager.set_block(removed, Block::AIR);
+
+        chunk_manager.update_around(IVec3::new(100, 0, 0));
+        chunk_manager.finish_jobs();
+        assert_eq!(chunk_manager.get_block(placed), None);
+
+        chunk_manager.update_around(IVec3::ZERO);
+        chunk_manager.finish_jobs();
+        assert_eq!(chunk_manager.get_block(placed), Some(Block::named("log")));
+        assert_eq!(chunk_manager.get_block(removed), Some(Block::AIR));
+
+        fs::remove_dir_all(directory).unwrap();
+    }
+
+    /// Saves a chunk holding nothing but one block.
+    fn save_block(world_save: &WorldSave, position: IVec3, index: usize, block: Block) {
+        let mut chunk = Chunk::new(position);
+        chunk.blocks.set(index, block);
+        world_save.save_chunks([&chunk]).unwrap();
+    }
+
+    fn load_block(world_save: &WorldSave, position: IVec3, index: usize) -> Option<Block> {
+        let chunk = world_save.load_chunk(position).unwrap()?;
+        assert!(!chunk.is_empty);
+        Some(chunk.blocks.get(index))
+    }
+
+    #[test]
+    fn chunks_of_a_region_are_kept_when_saving_others() {
+        let directory = temp_world("region_slots");
+        let world_save = WorldSave::new(&directory).unwrap();
+
+        // both in region (-1, 0, 1), in the first and last slot
+        let first = IVec3::new(-8, 0, 8);
+        let last = IVec3::new(-1, 7, 15);
+        let last_index = CHUNK_SIZE * CHUNK_SIZE * CHUNK_SIZE - 1;
+        save_block(&world_save, first, 0, Block::named("stone"));
+        save_block(&world_save, last, last_index, Block::named("sand"));
+
+        assert_eq!(
+            load_block(&world_save, first, 0),
+            Some(Block::named("stone"))
+        );
+        assert_eq!(
+            load_block(&world_save, last, last_index),
+            Some(Block::named("sand"))
+        );
+        // a chunk of the same region that was never saved
+        assert_eq!(load_block(&world_save, IVec3::new(-2, 0, 8), 0), None);
+        assert_eq!(fs::read_dir(directory.join("region")).unwrap().count(), 1);
+
+        fs::remove_dir_all(directory).unwrap();
+    }
+
+    fn compress(data: &[u8]) -> Vec<u8> {
+        let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(data).unwrap();
+        encoder.finish().unwrap()
+    }
+
+    /// A version 2 chunk with the given palette, every block using `index`.
+    fn palette_chunk(names: &[&str], index: u16) -> Vec<u8> {
+        compress(&palette_chunk_data(names, index))
+    }
+
+    fn palette_chunk_data(names: &[&str], index: u16) -> Vec<u8> {
+        let mut data = vec![CHUNK_FORMAT_VERSION];
+        data.extend_from_slice(&(names.len() as u16).to_le_bytes());
+        for name in names {
+            data.push(name.len() as u8);
+            data.extend_from_slice(name.as_bytes());
+        }
+        for _ in 0..CHUNK_SIZE * CHUNK_SIZE * CHUNK_SIZE {
+            data.extend_from_slice(&index.to_le_bytes());
+        }
+        data
+    }
+
+    #[test]
+    fn palettes_are_resolved_by_name() {
+        let chunk = decode_chunk(IVec3::ZERO, &palette_chunk(&["air", "sand"], 1)).unwrap();
+        assert!(
+            chunk
+                .blocks
+                .iter()
+                .all(|block| block == Block::named("sand"))
+        );
+        assert!(!chunk.is_empty);
+
+        // blocks removed from the registry since the chunk was saved
+        let chunk = decode_chunk(IVec3::ZERO, &palette_chunk(&["no_such_block"], 0)).unwrap();
+        assert!(chunk.is_empty);
+    }
+
+    #[test]
+    fn legacy_chunks_are_decoded() {
+        let mut data = vec![1; 1 + CHUNK_SIZE * CHUNK_SIZE * CHUNK_SIZE];
+        data[1] = 12;
+        data[2] = 0;
+        let chunk = decode_chunk(IVec3::ZERO, &compress(&data)).unwrap();
+        assert_eq!(chunk.blocks.get(0), Block::named("lamp"));
+        assert_eq!(chunk.blocks.get(1), Block::AIR);
+        assert_eq!(chunk.blocks.get(2), Block::named("dirt"));
+    }
+
+    #[test]
+    fn broken_chunks_are_refused() {
+        let mut unknown_legacy_block = vec![255; 1 + CHUNK_SIZE * CHUNK_SIZE * CHUNK_SIZE];
+        unknown_legacy_block[0] = 1;
+        let mut short_indices = palette_chunk_data(&["stone"], 0);
+        short_indices.pop();
+        // a valid chunk, but with more data after it than any chunk has
+        let mut oversized = palette_chunk_data(&["stone"], 0);
+        oversized.resize(MAX_CHUNK_BYTES + 1, 0);
+
+        for data in [
+            compress(&[CHUNK_FORMAT_VERSION, 1, 2]),
+            compress(&[CHUNK_FORMAT_VERSION + 1]),
+            compress(&[1; 100]),
+            compress(&unknown_legacy_block),
+            palette_chunk(&["stone"], 1),
+            compress(&short_indices),
+            compress(&oversized),
+            b"not zlib".to_vec(),
+        ] {
+            assert!(decode_chunk(IVec3::ZERO, &data).is_err());
+        }
+    }
+
+    #[test]
+    fn foreign_region_files_are_refused() {
+        let directory = temp_world("foreign");
+        let world_save = WorldSave::new(&directory).unwrap();
+        let path = world_save.region_path(IVec3::ZERO);
+        fs::write(&path, vec![0; HEADER_SIZE]).unwrap();
+
+        assert!(world_save.load_chunk(IVec3::ONE).is_err());
+        assert!(read_region_slots(&path).is_err());
+
+        fs::remove_dir_all(directory).unwrap();
+    }
+}
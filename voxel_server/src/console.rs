@@ -0,0 +1,226 @@
+//! Admin commands typed into the standard input of the server.
+
+use std::{
+    io::BufRead,
+    sync::mpsc::{self, Receiver},
+};
+
+use anyhow::{Context, bail};
+use glam::{IVec3, Vec3};
+use voxel_core::{
+    block::{Block, registry},
+    time::TimeOfDay,
+};
+
+pub const HELP: &str = "\
+commands:
+  help                        show this
+  list                        list the players
+  save                        save the world
+  setblock <x> <y> <z> <block>
+                              change a block in a loaded chunk
+  tp <player> <x> <y> <z>     teleport a player to a position
+  tp <player> <other player>  teleport a player to another one
+  time <ticks|sunrise|day|noon|sunset|night|midnight>
+                              set the time of day
+  kick <player> [reason]      disconnect a player
+  stop                        save the world and shut down";
+
+/// Where a player gets teleported to.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Destination {
+    Position(Vec3),
+    Player(String),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Command {
+    Help,
+    List,
+    Save,
+    SetBlock { position: IVec3, block: Block },
+    Teleport { player: String, to: Destination },
+    Time(TimeOfDay),
+    Kick { player: String, reason: String },
+    Stop,
+}
+
+impl Command {
+    pub fn parse(line: &str) -> anyhow::Result<Self> {
+        let mut words = line.split_whitespace();
+        let name = words.next().context("empty command")?;
+        let mut arg = |what: &str| {
+            words
+                .next()
+                .with_context(|| format!("missing {what}, see help"))
+        };
+
+        let command = match name {
+            "help" => Self::Help,
+            "list" => Self::List,
+            "save" => Self::Save,
+            "setblock" => {
+                let position = IVec3::new(
+                    parse_number(arg("x")?)?,
+                    parse_number(arg("y")?)?,
+                    parse_number(arg("z")?)?,
+                );
+                let name = arg("block")?;
+                let block = registry()
+                    .get(name)
+                    .with_context(|| format!("unknown block {name}"))?;
+                Self::SetBlock { position, block }
+            }
+            "tp" => {
+                let player = arg("player")?.to_string();
+                // a single word is a player, even one whose name is a number
+                let to = match words.collect::<Vec<&str>>()[..] {
+                    [other] => Destination::Player(other.to_string()),
+                    [x, y, z] => Destination::Position(Vec3::new(
+                        parse_coordinate(x)?,
+                        parse_coordinate(y)?,
+                        parse_coordinate(z)?,
+                    )),
+                    [] => bail!("missing destination, see help"),
+                    _ => bail!("expected a player or a position to teleport to, see help"),
+                };
+                Self::Teleport { player, to }
+            }
+            "time" => {
+                let time = arg("time")?;
+                Self::Time(TimeOfDay::parse(time).with_context(|| format!("invalid time {time}"))?)
+            }
+            "kick" => {
+                let player = arg("player")?.to_string();
+                let reason = words.collect::<Vec<&str>>().join(" ");
+                Self::Kick {
+                    player,
+                    reason: if reason.is_empty() {
+                        "kicked by the server".to_string()
+                    } else {
+                        reason
+                    },
+                }
+            }
+            "stop" => Self::Stop,
+            _ => bail!("unknown command {name}, see help"),
+        };
+
+        Ok(command)
+    }
+}
+
+fn parse_number<T: std::str::FromStr>(text: &str) -> anyhow::Result<T> {
+    text.parse()
+        .ok()
+        .with_context(|| format!("invalid number {text}"))
+}
+
+/// A number that can be a position, so not infinite or NaN.
+fn parse_coordinate(text: &str) -> anyhow::Result<f32> {
+    Some(parse_number::<f32>(text)?)
+        .filter(|coordinate| coordinate.is_finite())
+        .with_context(|| format!("invalid coordinate {text}"))
+}
+
+/// Reads the lines typed into the standard input on a thread of its own, so
+/// the server can pick them up between ticks.
+pub fn read_stdin() -> Receiver<String> {
+    let (sender, receiver) = mpsc::channel();
+    std::thread::Builder::new()
+        .name("console".to_string())
+        .spawn(move || {
+            for line in std::io::stdin().lock().lines() {
+                let Ok(line) = line else {
+                    break;
+                };
+                if sender.send(line).is_err() {
+                    break;
+                }
+            }
+        })
+        .expect("unable to start the console");
+
+    receiver
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn commands_parse() {
+        assert_eq!(Command::parse("  list ").unwrap(), Command::List);
+        assert_eq!(
+            Command::parse("setblock 1 -2 3 stone").unwrap(),
+            Command::SetBlock {
+                position: IVec3::new(1, -2, 3),
+                block: Block::named("stone"),
+            }
+        );
+        assert_eq!(
+            Command::parse("time noon").unwrap(),
+            Command::Time(TimeOfDay::NOON)
+        );
+        assert_eq!(
+            Command::parse("kick bob too   loud").unwrap(),
+            Command::Kick {
+                player: "bob".to_string(),
+                reason: "too loud".to_string(),
+            }
+        );
+        assert_eq!(
+            Command::parse("kick bob").unwrap(),
+            Command::Kick {
+                player: "bob".to_string(),
+                reason: "kicked by the server".to_string(),
+            }
+        );
+    }
+
+    #[test]
+    fn teleports_go_to_positions_or_players() {
+        assert_eq!(
+            Command::parse("tp alice 1.5 64 -3").unwrap(),
+            Command::Teleport {
+                player: "alice".to_string(),
+                to: Destination::Position(Vec3::new(1.5, 64.0, -3.0)),
+            }
+        );
+        assert_eq!(
+            Command::parse("tp alice bob").unwrap(),
+            Command::Teleport {
+                player: "alice".to_string(),
+                to: Destination::Player("bob".to_string()),
+            }
+        );
+        assert_eq!(
+            Command::parse("tp alice 42").unwrap(),
+            Command::Teleport {
+                player: "alice".to_string(),
+                to: Destination::Player("42".to_string()),
+            }
+        );
+    }
+
+    #[test]
+    fn broken_commands_are_refused() {
+        for line in [
+            "",
+            "fly",
+            "setblock 1 2 stone",
+            "setblock 1 2 3.5 stone",
+            "setblock 1 2 3 no_such_block",
+            "tp alice",
+            "tp alice 1 2",
+            "tp alice 1 2 z",
+            "tp alice 1 2 3 4",
+            "tp alice inf 0 0",
+            "tp alice 0 NaN 0",
+            "time later",
+            "kick",
+        ] {
+            assert!(Command::parse(line).is_err(), "{line:?}");
+        }
+    }
+}
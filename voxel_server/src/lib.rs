@@ -0,0 +1,791 @@
+//! The dedicated server: owns the world, streams the chunks around every
+//! player to them, checks and applies the blocks they change and keeps every
+//! player up to date with where the others are. Admins run it through
+//! [`console`] commands.
+
+use std::{
+    io,
+    net::{SocketAddr, TcpListener},
+    sync::mpsc::Receiver,
+    time::{Duration, Instant},
+};
+
+use anyhow::{Context, bail};
+
+use ahash::{AHashMap, AHashSet};
+use glam::{IVec3, Vec3};
+use voxel_core::{
+    block::{Block, registry},
+    chunk::{CHUNK_SIZE, Chunk},
+    chunk_manager::{ChunkManager, WorldRole},
+    load_area::LoadArea,
+    net::{ClientMessage, Connection, PROTOCOL_VERSION, ServerMessage, Welcome},
+    world_save::{LevelInfo, encode_chunk},
+};
+
+use console::{Command, Destination};
+
+pub mod console;
+
+/// how often the world is simulated and the players are sent what changed
+pub const TICK: Duration = Duration::from_millis(50);
+
+/// the most chunks sent to a player every tick, so nearby edits and
+/// positions don't queue up behind a whole render distance of chunks
+const CHUNKS_PER_TICK: usize = 16;
+/// how far from their eyes players can change blocks, a little more than
+/// the client allows to make up for the position being out of date
+const REACH: f32 = 12.0;
+/// how far players may move every tick, a little more than falling at
+/// terminal velocity takes them
+const MOVE_PER_TICK: f32 = 4.0;
+/// how many ticks of unused movement players can save up, so positions that
+/// arrive bunched up aren't refused
+const MOVE_TICKS_SAVED: f32 = 10.0;
+/// how long a client gets to say hello
+const HELLO_TIMEOUT: Duration = Duration::from_secs(10);
+const MAX_NAME_LENGTH: usize = 32;
+const AUTOSAVE_INTERVAL: Duration = Duration::from_secs(60);
+/// how often the players are told the time of day, which their clocks
+/// drift from in between
+const TIME_SYNC_INTERVAL: Duration = Duration::from_secs(5);
+
+/// A connected client, which becomes a player once it said hello.
+struct Player {
+    connection: Connection<ClientMessage, ServerMessage>,
+    connected_at: Instant,
+    /// `None` until the client said hello
+    name: Option<String>,
+    /// the last position the server accepted, which is where the player
+    /// really is as far as the rest of the world is concerned
+    position: Vec3,
+    yaw: f32,
+    pitch: f32,
+    /// how far the player may move before the next tick tops it up again
+    movement: f32,
+    /// the distance chunks are sent to the player from, the smaller of the
+    /// server's and the client's
+    render_distance: i32,
+    /// whether the others haven't been told the latest position yet
+    moved: bool,
+    /// the chunks the client has, or has on the way
+    sent_chunks: AHashSet<IVec3>,
+    /// set when the player gets disconnected, with the reason they're told
+    kicked: Option<String>,
+}
+
+impl Player {
+    fn chunk_position(&self) -> IVec3 {
+        Chunk::world_to_chunk_pos(self.position.floor().as_ivec3())
+    }
+}
+
+pub struct Server {
+    listener: TcpListener,
+    chunk_manager: ChunkManager,
+    level_info: LevelInfo,
+    /// where players join
+    pub spawn: Vec3,
+    players: AHashMap<u32, Player>,
+    next_player_id: u32,
+    /// the chunks the chunk manager was last told to keep loaded around
+    load_centers: Vec<IVec3>,
+    last_save: Instant,
+    last_time_sync: Instant,
+    running: bool,
+}
+
+impl Server {
+    /// Serves the world of `chunk_manager` to the clients connecting to
+    /// `listener`. The load area of the chunk manager is the one kept loaded
+    /// around every player.
+    pub fn new(
+        listener: TcpListener,
+        mut chunk_manager: ChunkManager,
+        level_info: LevelInfo,
+    ) -> io::Result<Self> {
+        listener.set_nonblocking(true)?;
+        chunk_manager.role = WorldRole::Server;
+        chunk_manager.lod_levels = 0;
+        chunk_manager.time_of_day = level_info.time;
+
+        Ok(Self {
+            listener,
+            chunk_manager,
+            level_info,
+            spawn: Vec3::new(0.0, CHUNK_SIZE as f32, 0.0),
+            players: AHashMap::new(),
+            next_player_id: 0,
+            load_centers: Vec::new(),
+            last_save: Instant::now(),
+            last_time_sync: Instant::now(),
+            running: true,
+        })
+    }
+
+    pub fn local_address(&self) -> io::Result<SocketAddr> {
+        self.listener.local_addr()
+    }
+
+    pub fn chunk_manager(&self) -> &ChunkManager {
+        &self.chunk_manager
+    }
+
+    /// Ticks the server at [`TICK`] intervals, running the console
+    /// commands from `commands` in between, until it's told to stop.
+    pub fn run(&mut self, commands: &Receiver<String>) {
+        let mut last_tick = Instant::now();
+        while self.running {
+            for line in commands.try_iter() {
+                if line.trim().is_empty() {
+                    continue;
+                }
+                if let Err(e) = Command::parse(&line).and_then(|command| self.run_command(command))
+                {
+                    log::error!("{:#}", e);
+                }
+            }
+
+            let start = Instant::now();
+            self.tick(start.duration_since(last_tick).as_secs_f32());
+            last_tick = start;
+
+            std::thread::sleep(TICK.saturating_sub(start.elapsed()));
+        }
+
+        self.shut_down();
+    }
+
+    pub fn run_command(&mut self, command: Command) -> anyhow::Result<()> {
+        match command {
+            Command::Help => log::info!("{}", console::HELP),
+            Command::List => {
+                let names = self
+                    .players
+                    .values()
+                    .filter_map(|player| player.name.as_deref())
+                    .collect::<Vec<&str>>();
+                log::info!("{} players: {}", names.len(), names.join(", "));
+            }
+            Command::Save => {
+                self.save();
+                log::info!("saved the world");
+            }
+            Command::SetBlock { position, block } => {
+                if self.chunk_manager.get_block(position).is_none() {
+                    bail!("the chunk of {position} isn't loaded");
+                }
+                self.chunk_manager.set_block(position, block);
+            }
+            Command::Teleport { player, to } => {
+                let id = self.find_player(&player)?;
+                let position = match to {
+                    Destination::Position(position) => position,
+                    Destination::Player(other) => self.players[&self.find_player(&other)?].position,
+                };
+
+                let player = self.players.get_mut(&id).unwrap();
+                player.position = position;
+                player.moved = true;
+                player.connection.send(ServerMessage::Teleport { position });
+                log::info!(
+                    "teleported {} to {}",
+                    player.name.as_deref().unwrap_or_default(),
+                    position
+                );
+            }
+            Command::Time(time) => {
+                self.chunk_manager.time_of_day = time;
+                self.send_time();
+                log::info!("set the time to {}", time.ticks());
+            }
+            Command::Kick { player, reason } => {
+                let id = self.find_player(&player)?;
+                self.players.get_mut(&id).unwrap().kicked = Some(reason);
+            }
+            Command::Stop => self.running = false,
+        }
+
+        Ok(())
+    }
+
+    /// The id of the player with the given name.
+    fn find_player(&self, name: &str) -> anyhow::Result<u32> {
+        self.players
+            .iter()
+            .find(|(_, player)| player.name.as_deref() == Some(name))
+            .map(|(&id, _)| id)
+            .with_context(|| format!("no player named {name}"))
+    }
+
+    /// Disconnects every player and saves the world.
+    pub fn shut_down(&mut self) {
+        log::info!("stopping the server");
+        for (_, player) in self.players.drain() {
+            player.connection.send(ServerMessage::Disconnect {
+                reason: "the server stopped".to_string(),
+            });
+            player.connection.close();
+        }
+
+        self.save();
+    }
+
+    /// Takes in new clients and everything the clients sent, advances the
+    /// world by `dt` seconds and sends the players what changed.
+    pub fn tick(&mut self, dt: f32) {
+        self.accept_clients();
+
+        for player in self.players.values_mut() {
+            player.movement =
+                (player.movement + MOVE_PER_TICK).min(MOVE_PER_TICK * MOVE_TICKS_SAVED);
+        }
+
+        let ids = self.players.keys().copied().collect::<Vec<u32>>();
+        for id in ids {
+            self.handle_messages(id);
+        }
+        self.remove_disconnected();
+
+        self.update_load_area();
+        self.chunk_manager.time_of_day.advance(dt);
+        self.chunk_manager.update_fluids(dt);
+        self.chunk_manager.update_jobs();
+
+        self.send_block_changes();
+        self.send_chunks();
+        self.send_positions();
+        if self.last_time_sync.elapsed() >= TIME_SYNC_INTERVAL {
+            self.send_time();
+        }
+
+        if self.last_save.elapsed() >= AUTOSAVE_INTERVAL {
+            self.save();
+        }
+    }
+
+    /// Writes every edited chunk and the time of day to the world save.
+    pub fn save(&mut self) {
+        if let Err(e) = self.chunk_manager.save_all() {
+            log::error!("unable to save world {:#}", e);
+        }
+
+        self.level_info.time = self.chunk_manager.time_of_day;
+        if let Some(world_save) = &self.chunk_manager.world_save
+            && let Err(e) = world_save.save_level_info(&self.level_info)
+        {
+            log::error!("unable to save level info {:#}", e);
+        }
+
+        self.last_save = Instant::now();
+    }
+
+    fn accept_clients(&mut self) {
+        loop {
+            let stream = match self.listener.accept() {
+                Ok((stream, _)) => stream,
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => break,
+                Err(e) => {
+                    log::error!("unable to accept client: {}", e);
+                    break;
+                }
+            };
+
+            // the listener hands out non-blocking streams on some platforms
+            let connection = match stream
+                .set_nonblocking(false)
+                .and_then(|_| Connection::new(stream))
+            {
+                Ok(connection) => connection,
+                Err(e) => {
+                    log::error!("unable to set up client: {}", e);
+                    continue;
+                }
+            };
+            log::info!("client {} connected", connection.peer_address());
+
+            let id = self.next_player_id;
+            self.next_player_id += 1;
+            self.players.insert(
+                id,
+                Player {
+                    connection,
+                    connected_at: Instant::now(),
+                    name: None,
+                    position: self.spawn,
+                    yaw: 0.0,
+                    pitch: 0.0,
+                    movement: 0.0,
+                    render_distance: self.chunk_manager.load_area.radius,
+                    moved: false,
+                    sent_chunks: AHashSet::new(),
+                    kicked: None,
+                },
+            );
+        }
+    }
+
+    fn handle_messages(&mut self, id: u32) {
+        let player = self.players.get_mut(&id).unwrap();
+        let messages = player.connection.receive();
+        if player.name.is_none()
+            && messages.is_empty()
+            && player.connected_at.elapsed() > HELLO_TIMEOUT
+        {
+            player.kicked = Some("took too long to say hello".to_string());
+        }
+
+        for message in messages {
+            let player = self.players.get_mut(&id).unwrap();
+            if player.kicked.is_some() {
+                break;
+            }
+
+            match message {
+                ClientMessage::Hello {
+                    version,
+                    name,
+                    render_distance,
+                } => self.welcome(id, version, name, render_distance),
+                _ if player.name.is_none() => {
+                    player.kicked = Some("expected hello first".to_string());
+                }
+                ClientMessage::Position {
+                    position,
+                    yaw,
+                    pitch,
+                } => {
+                    if !(position.is_finite() && yaw.is_finite() && pitch.is_finite()) {
+                        continue;
+                    }
+
+                    let distance = position.distance(player.position);
+                    if distance <= player.movement {
+                        player.movement -= distance;
+                        player.position = position;
+                        player.yaw = yaw;
+                        player.pitch = pitch;
+                        player.moved = true;
+                    } else {
+                        // moving faster than anyone can, so put them back
+                        log::debug!("refused to move player {} to {}", id, position);
+                        player.connection.send(ServerMessage::Teleport {
+                            position: player.position,
+                        });
+                    }
+                }
+                ClientMessage::SetBlock { position, block } => {
+                    self.handle_set_block(id, position, &block);
+                }
+                ClientMessage::UnloadedChunks(positions) => {
+                    for position in positions {
+                        player.sent_chunks.remove(&position);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Lets the client in as a player, introducing it and the other players
+    /// to each other.
+    fn welcome(&mut self, id: u32, version: u16, name: String, render_distance: i32) {
+        let name = name.trim().to_string();
+        let refusal = if self.players[&id].name.is_some() {
+            Some("said hello twice".to_string())
+        } else if version != PROTOCOL_VERSION {
+            Some(format!(
+                "the server runs protocol version {PROTOCOL_VERSION}, not {version}"
+            ))
+        } else if name.is_empty() || name.chars().count() > MAX_NAME_LENGTH {
+            Some(format!("names need 1 to {MAX_NAME_LENGTH} characters"))
+        } else if self
+            .players
+            .values()
+            .any(|other| other.name.as_ref() == Some(&name))
+        {
+            Some(format!("{name} is already playing"))
+        } else {
+            None
+        };
+
+        let player = self.players.get_mut(&id).unwrap();
+        if refusal.is_some() {
+            player.kicked = refusal;
+            return;
+        }
+
+        log::info!("{} joined from {}", name, player.connection.peer_address());
+        player.name = Some(name.clone());
+        player.moved = false;
+
+        let load_area = self.chunk_manager.load_area;
+        player.render_distance = render_distance.clamp(1, load_area.radius);
+        player.connection.send(ServerMessage::Welcome(Welcome {
+            player_id: id,
+            seed: self.level_info.seed,
+            generator: self.level_info.generator.clone(),
+            render_distance: player.render_distance,
+            load_shape: load_area.shape,
+            spawn: self.spawn,
+        }));
+        player.connection.send(ServerMessage::Time {
+            ticks: self.chunk_manager.time_of_day.ticks(),
+        });
+
+        let joined = self.players.get(&id).unwrap();
+        for (&other_id, other) in &self.players {
+            let Some(other_name) = &other.name else {
+                continue;
+            };
+            if other_id == id {
+                continue;
+            }
+
+            joined.connection.send(ServerMessage::PlayerJoined {
+                id: other_id,
+                name: other_name.clone(),
+            });
+            joined.connection.send(moved_message(other_id, other));
+            other.connection.send(ServerMessage::PlayerJoined {
+                id,
+                name: name.clone(),
+            });
+            other.connection.send(moved_message(id, joined));
+        }
+    }
+
+    /// Applies a block change a player asked for, if they could have made
+    /// it from where the server has them. Refused changes are undone on the
+    /// client by sending it the block that's really there.
+    fn handle_set_block(&mut self, id: u32, position: IVec3, block: &str) {
+        let player = &self.players[&id];
+        let current = self.chunk_manager.get_block(position);
+        let placeable = registry().get(block).filter(|&block| is_placeable(block));
+        let in_reach = (position.as_vec3() + 0.5).distance(player.position) <= REACH;
+        let has_chunk = player
+            .sent_chunks
+            .contains(&Chunk::world_to_chunk_pos(position));
+
+        match (current, placeable) {
+            (Some(_), Some(block)) if in_reach && has_chunk => {
+                self.chunk_manager.set_block(position, block);
+            }
+            _ => {
+                log::debug!("refused to set {} to {} for player {}", position, block, id);
+                if let Some(current) = current
+                    && has_chunk
+                {
+                    player.connection.send(ServerMessage::BlockChanged {
+                        position,
+                        block: current.name().to_string(),
+                    });
+                }
+            }
+        }
+    }
+
+    fn remove_disconnected(&mut self) {
+        let removed = self
+            .players
+            .extract_if(|_, player| player.kicked.is_some() || player.connection.is_closed())
+            .collect::<Vec<(u32, Player)>>();
+
+        for (id, player) in removed {
+            if let Some(reason) = &player.kicked {
+                log::info!(
+                    "disconnecting {}: {}",
+                    player.connection.peer_address(),
+                    reason
+                );
+                player.connection.send(ServerMessage::Disconnect {
+                    reason: reason.clone(),
+                });
+            }
+
+            if let Some(name) = &player.name {
+                log::info!("{} left", name);
+                for other in self.players.values() {
+                    other.connection.send(ServerMessage::PlayerLeft { id });
+                }
+            }
+        }
+    }
+
+    /// Keeps the chunks around every player loaded.
+    fn update_load_area(&mut self) {
+        let centers = self
+            .players
+            .values()
+            .filter(|player| player.name.is_some())
+            .map(Player::chunk_position)
+            .collect::<Vec<IVec3>>();
+
+        if centers != self.load_centers {
+            self.chunk_manager.update_around_all(&centers);
+            self.load_centers = centers;
+        }
+    }
+
+    /// Sends every block changed since the last tick to the players that
+    /// have its chunk. The others get the new block with the chunk.
+    fn send_block_changes(&mut self) {
+        for (position, block) in std::mem::take(&mut self.chunk_manager.changed_blocks) {
+            let chunk_position = Chunk::world_to_chunk_pos(position);
+            for player in self.players.values() {
+                if player.sent_chunks.contains(&chunk_position) {
+                    player.connection.send(ServerMessage::BlockChanged {
+                        position,
+                        block: block.name().to_string(),
+                    });
+                }
+            }
+        }
+    }
+
+    /// Sends every player the closest loaded chunks around them that they
+    /// don't have yet.
+    fn send_chunks(&mut self) {
+        for player in self.players.values_mut() {
+            if player.name.is_none() {
+                continue;
+            }
+
+            let center = player.chunk_position();
+            let area = LoadArea {
+                center,
+                radius: player.render_distance,
+                ..self.chunk_manager.load_area
+            };
+            player
+                .sent_chunks
+                .retain(|position| area.contains(*position));
+
+            let mut missing = area
+                .chunks()
+                .filter(|position| {
+                    !player.sent_chunks.contains(position)
+                        && self.chunk_manager.chunk_map.contains_key(position)
+                })
+                .collect::<Vec<IVec3>>();
+            missing.sort_by_key(|position| (*position - center).length_squared());
+
+            for position in missing.into_iter().take(CHUNKS_PER_TICK) {
+                let chunk = &self.chunk_manager.chunk_map[&position];
+                match encode_chunk(chunk) {
+                    Ok(data) => {
+                        player
+                            .connection
+                            .send(ServerMessage::Chunk { position, data });
+                        player.sent_chunks.insert(position);
+                    }
+                    Err(e) => log::error!("unable to encode chunk {}: {:#}", position, e),
+                }
+            }
+        }
+    }
+
+    fn send_time(&mut self) {
+        let ticks = self.chunk_manager.time_of_day.ticks();
+        for player in self.players.values() {
+            if player.name.is_some() {
+                player.connection.send(ServerMessage::Time { ticks });
+            }
+        }
+        self.last_time_sync = Instant::now();
+    }
+
+    /// Tells every player where the ones that moved since the last tick are.
+    fn send_positions(&mut self) {
+        let moved = self
+            .players
+            .iter_mut()
+            .filter(|(_, player)| player.moved && player.name.is_some())
+            .map(|(&id, player)| {
+                player.moved = false;
+                (id, moved_message(id, player))
+            })
+            .collect::<Vec<(u32, ServerMessage)>>();
+
+        for (id, message) in moved {
+            for (&other_id, other) in &self.players {
+                if other_id != id && other.name.is_some() {
+                    other.connection.send(message.clone());
+                }
+            }
+        }
+    }
+}
+
+fn moved_message(id: u32, player: &Player) -> ServerMessage {
+    ServerMessage::PlayerMoved {
+        id,
+        position: player.position,
+        yaw: player.yaw,
+        pitch: player.pitch,
+    }
+}
+
+/// Whether players can place the block, which leaves out the flowing levels
+/// of fluids. Air is placed to break blocks.
+fn is_placeable(block: Block) -> bool {
+    block.fluid().is_none_or(|fluid| fluid.is_source())
+}
+
+#[cfg(test)]
+mod tests {
+    use voxel_core::{client::Client, generator::generator_from_name, time::TimeOfDay};
+
+    use super::*;
+
+    /// how long a test waits for something to happen before failing
+    const TIMEOUT: Duration = Duration::from_secs(60);
+
+    struct TestClient {
+        client: Client,
+        chunk_manager: ChunkManager,
+    }
+
+    fn start_server() -> Server {
+        let level_info = LevelInfo {
+            seed: 0,
+            generator: "flat".to_string(),
+            time: TimeOfDay::default(),
+        };
+        let chunk_manager = ChunkManager::new(1, generator_from_name("flat", 0).unwrap());
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        Server::new(listener, chunk_manager, level_info).unwrap()
+    }
+
+    /// Connects a client, ticking the server until it let the client in.
+    fn join(server: &mut Server, name: &str) -> TestClient {
+        let address = server.local_address().unwrap();
+        let name = name.to_string();
+        let connecting = std::thread::spawn(move || Client::connect(address, &name));
+        while !connecting.is_finished() {
+            server.tick(TICK.as_secs_f32());
+            std::thread::sleep(Duration::from_millis(5));
+        }
+
+        let client = connecting.join().unwrap().unwrap();
+        let mut chunk_manager = client.create_chunk_manager().unwrap();
+        chunk_manager.update_around(Chunk::world_to_chunk_pos(
+            client.welcome.spawn.floor().as_ivec3(),
+        ));
+        TestClient {
+            client,
+            chunk_manager,
+        }
+    }
+
+    /// Ticks the server and the clients until `done` holds.
+    fn run_until(
+        server: &mut Server,
+        clients: &mut [&mut TestClient],
+        done: impl Fn(&Server, &[&mut TestClient]) -> bool,
+    ) {
+        let start = Instant::now();
+        while !done(server, clients) {
+            assert!(start.elapsed() < TIMEOUT, "timed out");
+
+            server.tick(TICK.as_secs_f32());
+            for test_client in clients.iter_mut() {
+                test_client
+                    .client
+                    .update(&mut test_client.chunk_manager)
+                    .unwrap();
+                test_client.chunk_manager.update_jobs();
+            }
+            std::thread::sleep(Duration::from_millis(5));
+        }
+    }
+
+    #[test]
+    fn players_share_a_world() {
+        let mut server = start_server();
+        let mut alice = join(&mut server, "alice");
+        let mut bob = join(&mut server, "bob");
+        let alice_id = alice.client.welcome.player_id;
+
+        // every chunk around the spawn is streamed to both
+        let area = alice.chunk_manager.load_area;
+        run_until(&mut server, &mut [&mut alice, &mut bob], |_, clients| {
+            clients.iter().all(|test_client| {
+                area.chunks()
+                    .all(|chunk| test_client.chunk_manager.chunk_map.contains_key(&chunk))
+            })
+        });
+        let grass = Block::named("grass");
+        let ground = IVec3::new(20, 3, 20);
+        assert_eq!(alice.chunk_manager.get_block(ground), Some(grass));
+        assert_eq!(bob.chunk_manager.get_block(ground), Some(grass));
+
+        // blocks placed within reach end up everywhere
+        let stone = Block::named("stone");
+        let placed = server.spawn.floor().as_ivec3() + IVec3::new(2, -2, 2);
+        alice
+            .client
+            .set_block(&mut alice.chunk_manager, placed, stone);
+        run_until(
+            &mut server,
+            &mut [&mut alice, &mut bob],
+            |server, clients| {
+                server.chunk_manager().get_block(placed) == Some(stone)
+                    && clients[1].chunk_manager.get_block(placed) == Some(stone)
+            },
+        );
+
+        // blocks out of reach are put back on the client that changed them
+        alice
+            .client
+            .set_block(&mut alice.chunk_manager, ground, Block::AIR);
+        assert_eq!(alice.chunk_manager.get_block(ground), Some(Block::AIR));
+        run_until(&mut server, &mut [&mut alice, &mut bob], |_, clients| {
+            clients[0].chunk_manager.get_block(ground) == Some(grass)
+        });
+        assert_eq!(server.chunk_manager().get_block(ground), Some(grass));
+        assert_eq!(bob.chunk_manager.get_block(ground), Some(grass));
+
+        // and everyone sees where the others go
+        let moved_to = server.spawn + Vec3::new(5.0, 1.0, -3.0);
+        std::thread::sleep(Duration::from_millis(60));
+        alice.client.send_position(moved_to, 1.0, -0.5);
+        run_until(&mut server, &mut [&mut alice, &mut bob], |_, clients| {
+            clients[1]
+                .client
+                .players
+                .get(&alice_id)
+                .is_some_and(|player| player.position == moved_to)
+        });
+        let seen = &bob.client.players[&alice_id];
+        assert_eq!(seen.name, "alice");
+        assert_eq!((seen.yaw, seen.pitch), (1.0, -0.5));
+    }
+
+    #[test]
+    fn players_cant_move_faster_than_falling() {
+        let mut server = start_server();
+        let mut alice = join(&mut server, "alice");
+
+        std::thread::sleep(Duration::from_millis(60));
+        alice
+            .client
+            .send_position(server.spawn + Vec3::new(500.0, 0.0, 0.0), 0.0, 0.0);
+
+        // the client is put back where the server last had it
+        let start = Instant::now();
+        let teleport = loop {
+            assert!(start.elapsed() < TIMEOUT, "timed out");
+            server.tick(TICK.as_secs_f32());
+            alice.client.update(&mut alice.chunk_manager).unwrap();
+            if let Some(teleport) = alice.client.take_teleport() {
+                break teleport;
+            }
+            std::thread::sleep(Duration::from_millis(5));
+        };
+        assert_eq!(teleport, server.spawn);
+        assert_eq!(
+            server.players[&alice.client.welcome.player_id].position,
+            server.spawn
+        );
+    }
+}
@@ -0,0 +1,145 @@
+use std::{net::TcpListener, path::PathBuf, sync::Arc};
+
+use anyhow::{Context, bail};
+use voxel_core::{
+    block::{BlockRegistry, set_registry},
+    chunk_manager::ChunkManager,
+    generator::generator_from_name,
+    load_area::LoadShape,
+    net::DEFAULT_PORT,
+    time::TimeOfDay,
+    world_save::{LevelInfo, WorldSave},
+};
+use voxel_server::{Server, console};
+
+/// Command line options for the server.
+struct Options {
+    world: PathBuf,
+    /// only used when creating a new world, existing worlds keep their seed
+    seed: Option<u32>,
+    generator: Option<String>,
+    /// the block pack the world is played with, clients need the same one
+    blocks: PathBuf,
+    port: u16,
+    /// how many chunks around every player are loaded and sent to them
+    render_distance: i32,
+    load_shape: LoadShape,
+}
+
+impl Options {
+    fn from_args() -> anyhow::Result<Self> {
+        let mut options = Self {
+            world: PathBuf::from("world"),
+            seed: None,
+            generator: None,
+            blocks: PathBuf::from("assets/blocks.toml"),
+            port: DEFAULT_PORT,
+            render_distance: 10,
+            load_shape: LoadShape::default(),
+        };
+
+        let mut args = std::env::args().skip(1);
+        while let Some(arg) = args.next() {
+            let mut value = || {
+                args.next()
+                    .with_context(|| format!("missing value for {arg}"))
+            };
+            match arg.as_str() {
+                "--world" => options.world = PathBuf::from(value()?),
+                "--seed" => options.seed = Some(value()?.parse().context("invalid seed")?),
+                "--generator" => options.generator = Some(value()?),
+                "--blocks" => options.blocks = PathBuf::from(value()?),
+                "--port" => options.port = value()?.parse().context("invalid port")?,
+                "--render-distance" => {
+                    options.render_distance =
+                        value()?.parse().context("invalid render distance")?;
+                    if options.render_distance < 1 {
+                        bail!("the render distance has to be at least 1");
+                    }
+                }
+                "--load-shape" => {
+                    let name = value()?;
+                    options.load_shape = LoadShape::parse(&name).with_context(|| {
+                        format!(
+                            "invalid load shape {name}, expected cube, sphere or cylinder:<height>"
+                        )
+                    })?;
+                }
+                _ => bail!("unknown argument {arg}"),
+            }
+        }
+
+        Ok(options)
+    }
+}
+
+/// Opens the world directory, creating it with the requested seed and
+/// generator if it doesn't exist yet.
+fn open_world(options: &Options) -> anyhow::Result<(ChunkManager, LevelInfo)> {
+    let world_save = WorldSave::new(&options.world)?;
+
+    let level_info = match world_save.load_level_info()? {
+        Some(level_info) => {
+            if options.seed.is_some_and(|seed| seed != level_info.seed) {
+                log::warn!("world already exists, keeping its seed {}", level_info.seed);
+            }
+            level_info
+        }
+        None => {
+            let level_info = LevelInfo {
+                seed: options.seed.unwrap_or_else(rand::random),
+                generator: options
+                    .generator
+                    .clone()
+                    .unwrap_or_else(|| "default".to_string()),
+                time: TimeOfDay::default(),
+            };
+            world_save.save_level_info(&level_info)?;
+            level_info
+        }
+    };
+
+    let generator = generator_from_name(&level_info.generator, level_info.seed)
+        .with_context(|| format!("unknown world generator {}", level_info.generator))?;
+    log::info!(
+        "opened world {:?} with seed {} and {} generator",
+        options.world,
+        level_info.seed,
+        level_info.generator
+    );
+
+    let mut chunk_manager = ChunkManager::new(options.render_distance, generator);
+    chunk_manager.world_save = Some(Arc::new(world_save));
+    chunk_manager.load_area.shape = options.load_shape;
+
+    Ok((chunk_manager, level_info))
+}
+
+fn run() -> anyhow::Result<()> {
+    env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("info")).init();
+
+    let options = Options::from_args()?;
+    if set_registry(BlockRegistry::from_file(&options.blocks)?).is_err() {
+        bail!("the block registry was already in use");
+    }
+
+    let (chunk_manager, level_info) = open_world(&options)?;
+    let listener = TcpListener::bind(("0.0.0.0", options.port))
+        .with_context(|| format!("unable to listen on port {}", options.port))?;
+    let mut server = Server::new(listener, chunk_manager, level_info)?;
+    log::info!(
+        "listening on {}, type help for commands",
+        server.local_address()?
+    );
+
+    server.run(&console::read_stdin());
+
+    Ok(())
+}
+
+fn main() {
+    if let Err(e) = run() {
+        eprintln!("error: {e:#}");
+        std::process::exit(1);
+    }
+}
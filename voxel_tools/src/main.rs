@@ -0,0 +1,312 @@
+use std::{
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::Duration,
+};
+
+use anyhow::{Context, bail};
+use glam::IVec3;
+use voxel_core::{
+    block::{BlockRegistry, set_registry},
+    chunk::{CHUNK_SIZE, Chunk},
+    chunk_manager::ChunkManager,
+    generator::generator_from_name,
+    load_area::LoadShape,
+    mesh_export::ExportMesh,
+    schematic::{Schematic, Transform},
+    vox::{self, BlockColors, PaletteMapping},
+    world_save::WorldSave,
+};
+
+const USAGE: &str = "\
+usage: voxel_tools <command> [options]
+
+commands:
+  export <file.obj|file.gltf|file.glb|file.vox>
+      export part of a world as a mesh or a MagicaVoxel model
+      --world <dir>          the world to export, defaults to world
+      --blocks <file>        the block pack, defaults to assets/blocks.toml
+      --atlas <file>         the block textures, defaults to assets/atlas.png
+      --center <x> <y> <z>   export the chunks around this block, defaults to
+                             the spawn
+      --radius <chunks>      how many chunks around the center, defaults to 4
+      --from <x> <y> <z> --to <x> <y> <z>
+                             export only the blocks between two corners
+
+  import <file.vox|file.vxs|file.schem>
+      paste a MagicaVoxel model or a schematic into a world
+      --at <x> <y> <z>       the lowest corner to paste at
+      --world <dir>          the world to paste into, defaults to world
+      --blocks <file>        the block pack, defaults to assets/blocks.toml
+      --atlas <file>         the block textures to match colours against,
+                             defaults to assets/atlas.png
+      --mapping <file>       blocks for palette entries, one per line as
+                             <index> = <block> or #rrggbb = <block>
+      --rotate <turns>       quarter turns around the vertical axis
+      --mirror               mirror along the x axis before rotating";
+
+/// Command line options for exporting a world as a mesh or a model.
+struct ExportOptions {
+    output: PathBuf,
+    world: PathBuf,
+    blocks: PathBuf,
+    atlas: PathBuf,
+    center: IVec3,
+    radius: i32,
+    from: Option<IVec3>,
+    to: Option<IVec3>,
+}
+
+impl ExportOptions {
+    fn from_args(mut args: impl Iterator<Item = String>) -> anyhow::Result<Self> {
+        let mut options = Self {
+            output: PathBuf::from(args.next().context("missing the file to export to")?),
+            world: PathBuf::from("world"),
+            blocks: PathBuf::from("assets/blocks.toml"),
+            atlas: PathBuf::from("assets/atlas.png"),
+            center: IVec3::new(0, CHUNK_SIZE as i32, 0),
+            radius: 4,
+            from: None,
+            to: None,
+        };
+
+        while let Some(arg) = args.next() {
+            let mut value = || {
+                args.next()
+                    .with_context(|| format!("missing value for {arg}"))
+            };
+            match arg.as_str() {
+                "--world" => options.world = PathBuf::from(value()?),
+                "--blocks" => options.blocks = PathBuf::from(value()?),
+                "--atlas" => options.atlas = PathBuf::from(value()?),
+                "--center" => options.center = parse_position(value)?,
+                "--radius" => {
+                    options.radius = value()?.parse().context("invalid radius")?;
+                    if options.radius < 1 {
+                        bail!("the radius has to be at least 1");
+                    }
+                }
+                "--from" => options.from = Some(parse_position(value)?),
+                "--to" => options.to = Some(parse_position(value)?),
+                _ => bail!("unknown argument {arg}"),
+            }
+        }
+
+        Ok(options)
+    }
+
+    /// The box of blocks to export, if only part of the loaded chunks is
+    /// wanted.
+    fn region(&self) -> anyhow::Result<Option<(IVec3, IVec3)>> {
+        match (self.from, self.to) {
+            (Some(from), Some(to)) => Ok(Some((from, to))),
+            (None, None) => Ok(None),
+            _ => bail!("--from and --to have to be given together"),
+        }
+    }
+}
+
+/// Command line options for pasting a model or schematic into a world.
+struct ImportOptions {
+    input: PathBuf,
+    at: IVec3,
+    world: PathBuf,
+    blocks: PathBuf,
+    atlas: PathBuf,
+    mapping: Option<PathBuf>,
+    transform: Transform,
+}
+
+impl ImportOptions {
+    fn from_args(mut args: impl Iterator<Item = String>) -> anyhow::Result<Self> {
+        let input = PathBuf::from(args.next().context("missing the file to import")?);
+        let mut at = None;
+        let mut options = Self {
+            input,
+            at: IVec3::ZERO,
+            world: PathBuf::from("world"),
+            blocks: PathBuf::from("assets/blocks.toml"),
+            atlas: PathBuf::from("assets/atlas.png"),
+            mapping: None,
+            transform: Transform::default(),
+        };
+
+        while let Some(arg) = args.next() {
+            let mut value = || {
+                args.next()
+                    .with_context(|| format!("missing value for {arg}"))
+            };
+            match arg.as_str() {
+                "--at" => at = Some(parse_position(value)?),
+                "--world" => options.world = PathBuf::from(value()?),
+                "--blocks" => options.blocks = PathBuf::from(value()?),
+                "--atlas" => options.atlas = PathBuf::from(value()?),
+                "--mapping" => options.mapping = Some(PathBuf::from(value()?)),
+                "--rotate" => {
+                    let turns = value()?.parse::<i32>().context("invalid rotation")?;
+                    options.transform.rotation = turns.rem_euclid(4) as u8;
+                }
+                "--mirror" => options.transform.mirror = true,
+                _ => bail!("unknown argument {arg}"),
+            }
+        }
+        options.at = at.context("missing --at <x> <y> <z>")?;
+
+        Ok(options)
+    }
+}
+
+fn parse_position(mut value: impl FnMut() -> anyhow::Result<String>) -> anyhow::Result<IVec3> {
+    let mut coordinate = || -> anyhow::Result<i32> {
+        let text = value()?;
+        text.parse()
+            .with_context(|| format!("invalid coordinate {text}"))
+    };
+    Ok(IVec3::new(coordinate()?, coordinate()?, coordinate()?))
+}
+
+fn use_blocks(path: &Path) -> anyhow::Result<()> {
+    if set_registry(BlockRegistry::from_file(path)?).is_err() {
+        bail!("the block registry was already in use");
+    }
+    Ok(())
+}
+
+fn is_vox(path: &Path) -> bool {
+    path.extension().is_some_and(|extension| extension == "vox")
+}
+
+fn block_colors(atlas: &Path) -> anyhow::Result<BlockColors> {
+    let image = image::open(atlas)
+        .with_context(|| format!("unable to read the atlas {atlas:?}"))?
+        .to_rgba8();
+    Ok(BlockColors::from_atlas(
+        image.as_raw(),
+        image.width(),
+        image.height(),
+    ))
+}
+
+/// The center and radius in chunks of the smallest area that loads every
+/// chunk the box between two corners touches.
+fn area_around(a: IVec3, b: IVec3) -> (IVec3, i32) {
+    let half_size = (a - b).abs().max_element() / 2;
+    ((a + b) / 2, half_size / CHUNK_SIZE as i32 + 1)
+}
+
+/// Opens an existing world and loads every chunk within `radius` chunks of
+/// `center`, waiting until they are all there.
+fn load_world(world: &Path, center: IVec3, radius: i32) -> anyhow::Result<ChunkManager> {
+    let world_save = WorldSave::new(world)?;
+    let level_info = world_save
+        .load_level_info()?
+        .with_context(|| format!("there is no world at {world:?}"))?;
+    let generator = generator_from_name(&level_info.generator, level_info.seed)
+        .with_context(|| format!("unknown world generator {}", level_info.generator))?;
+
+    let mut chunk_manager = ChunkManager::new(radius, generator);
+    chunk_manager.world_save = Some(Arc::new(world_save));
+    chunk_manager.load_area.shape = LoadShape::Cube;
+    chunk_manager.update_around(Chunk::world_to_chunk_pos(center));
+
+    log::info!("loading chunks around {center}");
+    while chunk_manager.has_pending_jobs() {
+        chunk_manager.update_jobs();
+        std::thread::sleep(Duration::from_millis(1));
+    }
+    log::info!("loaded {} chunks", chunk_manager.chunk_map.len());
+
+    Ok(chunk_manager)
+}
+
+fn export(options: ExportOptions) -> anyhow::Result<()> {
+    let region = options.region()?;
+    // load just enough chunks to cover the region
+    let (center, radius) = match region {
+        Some((from, to)) => area_around(from, to),
+        None => (options.center, options.radius),
+    };
+    let chunk_manager = load_world(&options.world, center, radius)?;
+
+    if is_vox(&options.output) {
+        // without a region every loaded chunk goes in
+        let (from, to) = region.unwrap_or_else(|| {
+            let positions = chunk_manager.chunk_map.keys().copied();
+            let min = positions.clone().reduce(IVec3::min).unwrap();
+            let max = positions.reduce(IVec3::max).unwrap();
+            (min * CHUNK_SIZE as i32, (max + 1) * CHUNK_SIZE as i32 - 1)
+        });
+        let schematic = Schematic::copy(&chunk_manager, from, to)?;
+        vox::save(&options.output, &schematic, &block_colors(&options.atlas)?)?;
+        log::info!(
+            "exported {} blocks to {:?}",
+            schematic.size(),
+            options.output
+        );
+        return Ok(());
+    }
+
+    let mesh = ExportMesh::from_world(&chunk_manager, region);
+    mesh.save(&options.output, &options.atlas)?;
+    log::info!(
+        "exported {} triangles to {:?}",
+        (mesh.indices.len() + mesh.translucent_indices.len()) / 3,
+        options.output
+    );
+
+    Ok(())
+}
+
+fn import(options: ImportOptions) -> anyhow::Result<()> {
+    let schematic = if is_vox(&options.input) {
+        let mapping = match &options.mapping {
+            Some(path) => PaletteMapping::load(path)?,
+            None => PaletteMapping::default(),
+        };
+        vox::load(&options.input, &block_colors(&options.atlas)?, &mapping)?
+    } else {
+        Schematic::load(&options.input)?
+    };
+
+    let size = options.transform.size(schematic.size()).as_ivec3();
+    let (center, radius) = area_around(options.at, options.at + size - 1);
+    let mut chunk_manager = load_world(&options.world, center, radius)?;
+    let placed = schematic.paste(&mut chunk_manager, options.at, options.transform);
+    chunk_manager.save_all()?;
+    log::info!(
+        "pasted {placed} blocks of {:?} at {}",
+        options.input,
+        options.at
+    );
+
+    Ok(())
+}
+
+fn run() -> anyhow::Result<()> {
+    env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("info")).init();
+
+    let mut args = std::env::args().skip(1);
+    let command = args.next();
+    match command.as_deref() {
+        Some("export") => {
+            let options = ExportOptions::from_args(args)?;
+            use_blocks(&options.blocks)?;
+            export(options)
+        }
+        Some("import") => {
+            let options = ImportOptions::from_args(args)?;
+            use_blocks(&options.blocks)?;
+            import(options)
+        }
+        Some("help" | "--help") => {
+            println!("{USAGE}");
+            Ok(())
+        }
+        Some(command) => bail!("unknown command {command}\n{USAGE}"),
+        None => bail!("{USAGE}"),
+    }
+}
+
+fn main() {
+    run().unwrap();
+}